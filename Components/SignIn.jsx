@@ -0,0 +1,49 @@
+import React, { useState } from "react";
+
+// Log in or register; hands the session token to `onSignIn`
+const SignIn = ({ api, onSignIn }) => {
+  const [username, setUsername] = useState("");
+  const [password, setPassword] = useState("");
+  const [code, setCode] = useState("");
+  const [error, setError] = useState("");
+
+  const logIn = async () => {
+    try {
+      const response = await api.post("/auth/login", {
+        username,
+        password,
+        code: code || undefined,
+      });
+      setError("");
+      onSignIn(response.data.token);
+    } catch (error) {
+      setError(error.response?.data || "Could not log in");
+    }
+  };
+
+  const register = async () => {
+    try {
+      await api.post("/auth/register", { username, password });
+      await logIn();
+    } catch (error) {
+      setError(error.response?.data || "Could not register");
+    }
+  };
+
+  return (
+    <div className="input-section">
+      <input type="text" placeholder="Username" value={username} onChange={(e) => setUsername(e.target.value)} />
+      <input type="password" placeholder="Password" value={password} onChange={(e) => setPassword(e.target.value)} />
+      <input type="text" placeholder="Two-factor code (if enabled)" value={code} onChange={(e) => setCode(e.target.value)} />
+      <button onClick={() => logIn()} className="add">
+        Log in
+      </button>
+      <button onClick={() => register()}>
+        Register
+      </button>
+      {error && <p className="not-found">{error}</p>}
+    </div>
+  );
+};
+
+export default SignIn;
@@ -6,10 +6,22 @@ import { format } from "date-fns";
 
 // IMPORT COMPONENTS
 import CheckBox from "../Components/CheckBox";
+import SignIn from "../Components/SignIn";
+
+// Every call but logging in and registering needs the session token kept from logging in
+const api = axios.create({ baseURL: "http://127.0.0.1:8080" });
+api.interceptors.request.use((config) => {
+  const token = localStorage.getItem("token");
+  if (token) {
+    config.headers.Authorization = `Bearer ${token}`;
+  }
+  return config;
+});
 
 
 
 const index = () => {
+  const [token, setToken] = useState(null);
   const [editText, setEditText] = useState("");
   const [todos, setTodos] = useState([]);
   const [todosCopy, setTodosCopy] = useState(todos);
@@ -23,10 +35,43 @@ const index = () => {
   const [search, setSearch] = useState("");
   const [searchItem, setSearchItem] = useState(search);
 
+  // pick up the session of an earlier visit
+  useEffect(() => {
+    setToken(localStorage.getItem("token"));
+  }, []);
+
   // get the entire data
   useEffect(() => {
-    fetchTodos();
-  }, [count]);
+    if (token) {
+      fetchTodos();
+    }
+  }, [count, token]);
+
+  const signIn = (newToken) => {
+    localStorage.setItem("token", newToken);
+    setToken(newToken);
+  };
+
+  const signOut = async () => {
+    try {
+      await api.post("/auth/logout");
+    } catch (error) {
+      handleError(error);
+    }
+    localStorage.removeItem("token");
+    setToken(null);
+    setTodos([]);
+    setTodosCopy([]);
+  };
+
+  // a session that expired or was ended elsewhere asks for logging in again
+  const handleError = (error) => {
+    console.log(error);
+    if (error.response?.status === 401) {
+      localStorage.removeItem("token");
+      setToken(null);
+    }
+  };
 
   const editTodo = (index) => {
     setTodoInput(todos[index].title);
@@ -35,32 +80,32 @@ const index = () => {
 
   const fetchTodos = async () => {
     try {
-      const response = await axios.get("http://127.0.0.1:8080/todos");
+      const response = await api.get("/todos");
       console.log(response);
-      setTodos(response.data);
-      setTodosCopy(response.data);
+      setTodos(response.data.items);
+      setTodosCopy(response.data.items);
     } catch (error) {
-      console.log(error);
+      handleError(error);
     }
   }
 
   const addTodo = async () => {
     try {
       if (editIndex == -1) {
-        const response = await axios.post("http://127.0.0.1:8080/todos",
+        const response = await api.post("/todos",
           {
             title: todoInput,
             completed: false,
           }
         );
-        setTodos(response.data);
-        setTodosCopy(response.data);
+        setTodos([...todos, response.data]);
+        setTodosCopy([...todosCopy, response.data]);
         setTodoInput("");
       }
       else {
         // Update the existing todo
         const todoToUpdate = { ...todos[editIndex], title: todoInput };
-        const response = await axios.put(`http://127.0.0.1:8080/todos/${todoToUpdate.id}`,
+        const response = await api.put(`/todos/${todoToUpdate.id}`,
 
           todoToUpdate
 
@@ -77,17 +122,17 @@ const index = () => {
       }
 
     } catch (error) {
-      console.log(error);
-
+      handleError(error);
     }
   }
 
   const deleteTodo = async (id) => {
     try {
-      await axios.delete(`http://127.0.0.1:8080/todos/${id}`);
+      await api.delete(`/todos/${id}`);
       setTodos(todos.filter((todo) => todo.id !== id));
+      setTodosCopy(todosCopy.filter((todo) => todo.id !== id));
     } catch (error) {
-      console.log(error);
+      handleError(error);
     }
   };
 
@@ -97,22 +142,23 @@ const index = () => {
         ...todos[index],
         completed: !todos[index].completed,
       }
-      const response = await axios.put(`http://127.0.0.1:8080/todos/${todoToUpdate.id}`, todoToUpdate);
+      const response = await api.put(`/todos/${todoToUpdate.id}`, todoToUpdate);
       const updatedTodos = [...todos];
       updatedTodos[index] = response.data;
       setTodos(updatedTodos);
       setCount(count + 1);
     } catch (error) {
-      console.log(error);
-
+      handleError(error);
     }
   }
 
-  const searchTodos = () => {
-    const searchResult = todosCopy.filter((todo) =>
-      todo.title.toLowerCase().includes(searchInput.toLowerCase())
-    );
-    setSearchResult(searchResult);
+  const searchTodos = async () => {
+    try {
+      const response = await api.get("/todos/search", { params: { q: searchInput } });
+      setSearchResult(response.data);
+    } catch (error) {
+      handleError(error);
+    }
   }
 
 
@@ -154,15 +200,18 @@ const index = () => {
 
   // Filter
 
-  const onHandleSearch = (value) => {
-    const filteredToDo = todos.filter(({ title }) =>
-      title.toLowerCase().includes(value.toLowerCase())
-    );
-    if (filteredToDo.length === 0) {
-      setTodos(todosCopy);
-    }
-    else {
-      setTodos(filteredToDo);
+  const onHandleSearch = async (value) => {
+    try {
+      const response = await api.get("/todos/search", { params: { q: value } });
+      const filteredToDo = response.data;
+      if (filteredToDo.length === 0) {
+        setTodos(todosCopy);
+      }
+      else {
+        setTodos(filteredToDo);
+      }
+    } catch (error) {
+      handleError(error);
     }
   }
 
@@ -187,6 +236,16 @@ const index = () => {
   }, [search])
 
 
+  if (!token) {
+    return (
+      <div className="main-body">
+        <div className="todo-app">
+          <SignIn api={api} onSignIn={signIn} />
+        </div>
+      </div>
+    );
+  }
+
   return (
     <div className="main-body">
       <div className="todo-app">
@@ -199,6 +258,9 @@ const index = () => {
           <button onClick={() => { }}>
             Search
           </button>
+          <button onClick={() => signOut()}>
+            Log out
+          </button>
         </div>
         {/* Body  */}
         <div className="todos">
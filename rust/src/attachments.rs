@@ -0,0 +1,192 @@
+// Content-addressed storage for attachment contents on local disk
+//
+// Each blob is stored once under its SHA-256 digest, however many attachments share it. Uploads are
+// streamed to a temporary file while they are hashed and only moved into place once complete, and a
+// blob is removed as soon as no attachment refers to it any more.
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::store::{StoreResult, TodoStore};
+
+// Leading bytes kept from an upload to tell its type from
+const HEAD_LEN: usize = 8192;
+
+pub struct BlobStore {
+    dir: PathBuf, // Directory holding the blobs, fanned out by the first two digest characters
+    pub max_bytes: u64, // Largest upload accepted, in bytes
+}
+
+impl BlobStore {
+    // Blobs in TODO_ATTACHMENTS_DIR (default `attachments`), accepting uploads of up to
+    // TODO_ATTACHMENT_MAX_BYTES (default 10 MiB). Uploads left behind by a crash are cleared.
+    pub fn from_env() -> io::Result<Self> {
+        let dir = PathBuf::from(
+            env::var("TODO_ATTACHMENTS_DIR").unwrap_or_else(|_| "attachments".to_string()),
+        );
+        let max_bytes = match env::var("TODO_ATTACHMENT_MAX_BYTES") {
+            Ok(value) => value.parse().map_err(|_| {
+                io::Error::other(format!(
+                    "invalid byte count `{}` in TODO_ATTACHMENT_MAX_BYTES",
+                    value
+                ))
+            })?,
+            Err(_) => 10 * 1024 * 1024,
+        };
+        BlobStore::open(dir, max_bytes)
+    }
+
+    // Blobs in `dir`, accepting uploads of up to `max_bytes`. Uploads left behind by a crash are
+    // cleared.
+    pub fn open(dir: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        let tmp = dir.join("tmp");
+        if tmp.exists() {
+            fs::remove_dir_all(&tmp)?;
+        }
+        fs::create_dir_all(&tmp)?;
+        Ok(BlobStore { dir, max_bytes })
+    }
+
+    // Where the blob with `digest` is kept
+    pub fn path(&self, digest: &str) -> PathBuf {
+        self.dir.join(&digest[..2]).join(digest)
+    }
+
+    // Start receiving a new upload
+    pub fn upload(&self) -> io::Result<Upload> {
+        let tmp = self.dir.join("tmp").join(Uuid::new_v4().to_string());
+        Ok(Upload {
+            file: File::create(&tmp)?,
+            tmp,
+            hasher: Sha256::new(),
+            size: 0,
+            head: Vec::new(),
+        })
+    }
+
+    // Remove the blobs among `digests` that no attachment refers to any more
+    pub fn remove_unused(
+        &self,
+        store: &dyn TodoStore,
+        digests: &HashSet<String>,
+    ) -> StoreResult<()> {
+        if digests.is_empty() {
+            return Ok(());
+        }
+        let used = used_digests(store)?;
+        digests
+            .difference(&used)
+            .for_each(|digest| self.remove(digest));
+        Ok(())
+    }
+
+    // Remove every blob no attachment refers to, such as one left behind when the server stopped
+    // between saving a blob and recording its attachment. Returns how many were removed.
+    pub fn sweep(&self, store: &dyn TodoStore) -> StoreResult<usize> {
+        let used = used_digests(store)?;
+        let mut removed = 0;
+        for fan in fs::read_dir(&self.dir)? {
+            let fan = fan?;
+            if fan.file_name() == "tmp" || !fan.file_type()?.is_dir() {
+                continue;
+            }
+            for blob in fs::read_dir(fan.path())? {
+                let digest = blob?.file_name().to_string_lossy().into_owned();
+                if !used.contains(&digest) {
+                    self.remove(&digest);
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    // Remove one blob, logging rather than failing: a blob left behind only costs disk space
+    fn remove(&self, digest: &str) {
+        match fs::remove_file(self.path(digest)) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => log::error!("failed to remove blob {}: {}", digest, err),
+        }
+    }
+}
+
+// Digests of every stored attachment, live or in the trash
+fn used_digests(store: &dyn TodoStore) -> StoreResult<HashSet<String>> {
+    Ok(store
+        .attachments(None)?
+        .into_iter()
+        .map(|attachment| attachment.digest)
+        .collect())
+}
+
+// An upload being written to a temporary file; dropped unfinished, the file is removed
+pub struct Upload {
+    file: File,
+    tmp: PathBuf,
+    hasher: Sha256,
+    size: u64,
+    head: Vec<u8>,
+}
+
+impl Upload {
+    pub fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.file.write_all(chunk)?;
+        self.hasher.update(chunk);
+        self.size += chunk.len() as u64;
+        let wanted = HEAD_LEN.saturating_sub(self.head.len()).min(chunk.len());
+        self.head.extend_from_slice(&chunk[..wanted]);
+        Ok(())
+    }
+
+    // Bytes received so far
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    // Move the upload into `blobs`, returning its digest, size and sniffed content type. A blob
+    // already stored under the same digest is kept as it is.
+    pub fn finish(self, blobs: &BlobStore) -> io::Result<(String, u64, String)> {
+        self.file.sync_all()?;
+        let digest: String = self
+            .hasher
+            .clone()
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let path = blobs.path(&digest);
+        if !path.exists() {
+            fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+            fs::rename(&self.tmp, &path)?;
+        }
+        Ok((digest, self.size, sniff(&self.head)))
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        // Already gone once the upload has been moved into place
+        let _ = fs::remove_file(&self.tmp);
+    }
+}
+
+// MIME type of a file starting with `head`. The type the client claims is never trusted: known
+// magic numbers decide, then anything that reads as UTF-8 is plain text.
+fn sniff(head: &[u8]) -> String {
+    if let Some(kind) = infer::get(head) {
+        return kind.mime_type().to_string();
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => "text/plain; charset=utf-8".to_string(),
+        // A multi-byte character cut off at the end of the head still counts as text
+        Err(err) if err.error_len().is_none() && head.len() == HEAD_LEN => {
+            "text/plain; charset=utf-8".to_string()
+        }
+        Err(_) => "application/octet-stream".to_string(),
+    }
+}
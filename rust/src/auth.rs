@@ -0,0 +1,358 @@
+// Accounts and sign-in: argon2 password hashes, bearer-token sessions and the middleware for them
+//
+// Logging in hands out a random token that is sent back as `Authorization: Bearer <token>`. Only its
+// SHA-256 is stored, so a copy of the database is no use for signing in. Every route except
+// registration, login and share links needs a valid token; the middleware puts the signed-in `User`
+// into the request for handlers to pick up.
+//
+// API tokens and JWTs (see `jwt`) are sent the same way and told apart by their shape. They can
+// only do what their scopes allow: each route names the scope it needs with a `needs` guard, and a
+// request whose token lacks it falls through to `unmatched`, which refuses it.
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::guard::{Guard, GuardContext};
+use actix_web::http::header::{HeaderMap, AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::{DateTime, Duration, Utc};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+use crate::jwt;
+use crate::models::{ApiToken, Scope, User};
+use crate::state::AppState;
+use crate::store::{Event, StoreResult};
+
+// Routes that can be used without signing in
+const PUBLIC_PATHS: &[&str] = &["/auth/register", "/auth/login"];
+
+// Start of the paths of share links, which carry their own token instead of a sign-in
+const SHARED_PREFIX: &str = "/shared/";
+
+// Start of every API token, telling it apart from session tokens
+const API_TOKEN_PREFIX: &str = "tdo_";
+
+// How stale the recorded last use of an API token may get before it is written again
+const LAST_USED_PRECISION: Duration = Duration::minutes(1);
+
+// Wrong passwords in a row after which an account takes none for `LOGIN_LOCKOUT`
+const MAX_LOGIN_FAILURES: u32 = 5;
+const LOGIN_LOCKOUT: Duration = Duration::minutes(15);
+
+// Why a password or a second-step code was turned away
+pub enum Refusal {
+    Wrong,                 // Not the right one
+    Locked(DateTime<Utc>), // Too many wrong ones; none is checked before this time
+}
+
+// How a request was signed in, put into the request next to the `User`
+#[derive(Clone)]
+pub enum Grant {
+    Session,           // With a session from logging in, which may do everything
+    Token(Vec<Scope>), // With an API token or a JWT, which may only do what its scopes allow
+}
+
+// How long a session lasts, from TODO_SESSION_TTL_HOURS (default a week)
+pub fn session_ttl_from_env() -> Result<Duration, String> {
+    match env::var("TODO_SESSION_TTL_HOURS") {
+        Ok(value) => match value.parse::<i64>() {
+            Ok(hours) if hours > 0 => Ok(Duration::hours(hours)),
+            _ => Err(format!(
+                "invalid hour count `{}` in TODO_SESSION_TTL_HOURS",
+                value
+            )),
+        },
+        Err(_) => Ok(Duration::weeks(1)),
+    }
+}
+
+// Argon2id hash of `password` with a fresh salt, in PHC string format
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("default argon2 parameters accept any password and generated salt")
+        .to_string()
+}
+
+// Whether `password` matches a hash made by `hash_password`
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+// A hash no password matches, checked when there is no account by the name given so that
+// answering takes as long as for a wrong password
+pub fn dummy_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| hash_password(&new_token()))
+}
+
+// Count a password check of `user` at `now`, as `totp::check` counts codes: a wrong password adds
+// to the failures and locks the account once there are too many, a right one clears them. Nothing
+// counts while the account is locked.
+pub fn count_login(user: &mut User, right: bool, now: DateTime<Utc>) -> Result<(), Refusal> {
+    if let Some(until) = user.locked_until.filter(|until| *until > now) {
+        return Err(Refusal::Locked(until));
+    }
+    if right {
+        user.failed_logins = 0;
+        user.locked_until = None;
+        return Ok(());
+    }
+    user.failed_logins += 1;
+    if user.failed_logins >= MAX_LOGIN_FAILURES {
+        user.failed_logins = 0;
+        user.locked_until = Some(now + LOGIN_LOCKOUT);
+        return Err(Refusal::Locked(now + LOGIN_LOCKOUT));
+    }
+    Err(Refusal::Wrong)
+}
+
+// A new random session token
+pub fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex(&bytes)
+}
+
+// A new random API token
+pub fn new_api_token() -> String {
+    format!("{}{}", API_TOKEN_PREFIX, new_token())
+}
+
+// Id a session is stored under: the SHA-256 of its token
+pub fn token_id(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Token sent in the Authorization header, if any
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+// Account signed in with the bearer token in `headers` and how, unless the token is missing,
+// unknown or expired
+fn signed_in_user(data: &AppState, headers: &HeaderMap) -> StoreResult<Option<(User, Grant)>> {
+    let token = match bearer_token(headers) {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+    let now = Utc::now();
+    if let Some(verifier) = data.jwt.as_ref().filter(|_| jwt::is_jwt(token)) {
+        return match verifier.verify(token) {
+            Ok(claims) => {
+                Ok(jwt_subject(data, &claims.sub)?
+                    .map(|user| (user, Grant::Token(claims.scopes()))))
+            }
+            Err(err) => {
+                log::debug!("refused JWT: {}", err);
+                Ok(None)
+            }
+        };
+    }
+    if token.starts_with(API_TOKEN_PREFIX) {
+        return match data.store.api_token_by_hash(&token_id(token))? {
+            Some(token) if token.expires_at.is_none_or(|expires_at| expires_at > now) => {
+                let user = data.store.user(token.user_id)?;
+                let grant = Grant::Token(token.scopes.clone());
+                note_use(data, token)?;
+                Ok(user.map(|user| (user, grant)))
+            }
+            _ => Ok(None),
+        };
+    }
+    match data.store.session(&token_id(token))? {
+        Some(session) if session.expires_at > now => Ok(data
+            .store
+            .user(session.user_id)?
+            .map(|user| (user, Grant::Session))),
+        _ => Ok(None),
+    }
+}
+
+// Account a JWT signs in as: `sub` is its id. Usernames are not looked up, since another issuer
+// may well hand out a `sub` that happens to be the name of a local account.
+fn jwt_subject(data: &AppState, sub: &str) -> StoreResult<Option<User>> {
+    match Uuid::parse_str(sub) {
+        Ok(id) => data.store.user(id),
+        Err(_) => Ok(None),
+    }
+}
+
+// Record that `token` is being used, unless that was already done less than a minute ago
+fn note_use(data: &AppState, mut token: ApiToken) -> StoreResult<()> {
+    let now = Utc::now();
+    if token
+        .last_used_at
+        .is_some_and(|last_used_at| now - last_used_at < LAST_USED_PRECISION)
+    {
+        return Ok(());
+    }
+    let _guard = data.writes.lock().unwrap();
+    // The token may have been revoked in the meantime
+    if data.store.api_token(token.id)?.is_none() {
+        return Ok(());
+    }
+    token.last_used_at = Some(now);
+    data.commit(&[Event::ApiTokenUpdated(token)])
+}
+
+// 401 response asking the client to sign in
+pub fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header((WWW_AUTHENTICATE, "Bearer"))
+        .body("Sign in required")
+}
+
+// Middleware refusing requests without a valid session, except on the public routes
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    if !PUBLIC_PATHS.contains(&req.path()) && !req.path().starts_with(SHARED_PREFIX) {
+        let data = req
+            .app_data::<web::Data<AppState>>()
+            .expect("app state is registered")
+            .clone();
+        match signed_in_user(&data, req.headers())? {
+            Some((user, grant)) => {
+                req.extensions_mut().insert(user);
+                req.extensions_mut().insert(grant);
+            }
+            None => return Ok(req.into_response(unauthorized())),
+        }
+    }
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+// Route guard admitting sessions, and API tokens only when they carry the scope, if any
+pub struct Needs(Option<Scope>);
+
+// Guard for a route that API tokens may use with `scope`
+pub fn needs(scope: Scope) -> Needs {
+    Needs(Some(scope))
+}
+
+// Guard for a route that only a signed-in session may use, never an API token
+pub fn session_only() -> Needs {
+    Needs(None)
+}
+
+// Left in the request by a `Needs` guard that turned it away, for `unmatched` to explain
+struct Refused(Option<Scope>);
+
+impl Guard for Needs {
+    fn check(&self, ctx: &GuardContext<'_>) -> bool {
+        let allowed = match ctx.req_data().get::<Grant>() {
+            Some(Grant::Token(scopes)) => self.0.is_some_and(|scope| scopes.contains(&scope)),
+            _ => true,
+        };
+        if !allowed {
+            ctx.req_data_mut().insert(Refused(self.0));
+        }
+        allowed
+    }
+}
+
+// Fallback for requests no route took: a 403 when a route would have, but not with this API token
+pub async fn unmatched(req: HttpRequest) -> HttpResponse {
+    match req.extensions().get::<Refused>() {
+        Some(Refused(Some(scope))) => {
+            HttpResponse::Forbidden().body(format!("This token lacks the {} scope", scope.name()))
+        }
+        Some(Refused(None)) => {
+            HttpResponse::Forbidden().body("This needs a session from logging in")
+        }
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> User {
+        User {
+            id: Uuid::new_v4(),
+            username: "ada".to_string(),
+            password_hash: hash_password("correct horse"),
+            created_at: Utc::now(),
+            failed_logins: 0,
+            locked_until: None,
+        }
+    }
+
+    #[test]
+    fn wrong_passwords_lock_the_account_for_a_while() {
+        let mut user = user();
+        let now = Utc::now();
+        for _ in 1..MAX_LOGIN_FAILURES {
+            assert!(matches!(
+                count_login(&mut user, false, now),
+                Err(Refusal::Wrong)
+            ));
+        }
+        let until = now + LOGIN_LOCKOUT;
+        assert!(
+            matches!(count_login(&mut user, false, now), Err(Refusal::Locked(at)) if at == until)
+        );
+        // Not even the right password gets in until the lock lifts
+        let later = until - Duration::seconds(1);
+        assert!(matches!(
+            count_login(&mut user, true, later),
+            Err(Refusal::Locked(_))
+        ));
+        assert!(count_login(&mut user, true, until).is_ok());
+        assert_eq!((user.failed_logins, user.locked_until), (0, None));
+    }
+
+    #[test]
+    fn a_right_password_clears_the_failures() {
+        let mut user = user();
+        let now = Utc::now();
+        assert!(count_login(&mut user, false, now).is_err());
+        assert!(count_login(&mut user, true, now).is_ok());
+        assert_eq!(user.failed_logins, 0);
+    }
+
+    #[test]
+    fn a_jwt_subject_names_an_account_by_id_only() {
+        let dir = crate::testing::TempDir::new();
+        let data = crate::testing::app_state(&dir);
+        let ada = user();
+        data.commit(&[Event::UserCreated(ada.clone())]).unwrap();
+        let by_id = jwt_subject(&data, &ada.id.to_string()).unwrap();
+        assert_eq!(by_id.map(|user| user.id), Some(ada.id));
+        // Another issuer's `sub` that matches a local username does not sign in as that account
+        assert!(jwt_subject(&data, "ada").unwrap().is_none());
+        assert!(jwt_subject(&data, &Uuid::new_v4().to_string())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn no_password_matches_the_dummy_hash() {
+        assert!(!verify_password("", dummy_hash()));
+        assert!(!verify_password("correct horse", dummy_hash()));
+        assert!(verify_password("correct horse", &user().password_hash));
+    }
+}
@@ -0,0 +1,109 @@
+// Dependencies between tasks: the "blocked by" links, which have to form a directed acyclic graph
+use std::collections::{BTreeSet, HashMap, HashSet};
+use uuid::Uuid;
+
+use crate::models::TodoItem;
+use crate::store::{StoreResult, TodoQuery, TodoStore};
+
+// Every item by id, for walking the links of many items at once; trashed ones never count
+pub struct Dependencies(HashMap<Uuid, TodoItem>);
+
+impl Dependencies {
+    pub fn load(store: &dyn TodoStore) -> StoreResult<Self> {
+        let mut todos = store.list(&TodoQuery::default())?;
+        todos.extend(store.list(&TodoQuery {
+            in_trash: true,
+            ..TodoQuery::default()
+        })?);
+        Ok(Dependencies(
+            todos.into_iter().map(|todo| (todo.id, todo)).collect(),
+        ))
+    }
+
+    // Live items blocking `todo` that are not completed yet
+    fn open_blockers(&self, todo: &TodoItem) -> Vec<&TodoItem> {
+        todo.blocked_by
+            .iter()
+            .filter_map(|id| self.0.get(id))
+            .filter(|blocker| blocker.deleted_at.is_none() && !blocker.completed)
+            .collect()
+    }
+
+    // Live items that are still open and are not waiting for any other item, oldest first
+    pub fn actionable(&self) -> Vec<TodoItem> {
+        let mut todos: Vec<TodoItem> = self
+            .0
+            .values()
+            .filter(|todo| todo.deleted_at.is_none() && !todo.completed)
+            .filter(|todo| self.open_blockers(todo).is_empty())
+            .cloned()
+            .collect();
+        todos.sort_by_key(|todo| (todo.created_at, todo.id));
+        todos
+    }
+
+    // Every live item `id` waits for, directly or not, in an order where each item comes after the
+    // items blocking it
+    pub fn prerequisites(&self, id: Uuid) -> Vec<TodoItem> {
+        let mut visited = HashSet::new();
+        visited.insert(id);
+        let mut ordered = Vec::new();
+        if let Some(todo) = self.0.get(&id) {
+            for blocker_id in &todo.blocked_by {
+                self.visit(*blocker_id, &mut visited, &mut ordered);
+            }
+        }
+        ordered
+    }
+
+    // Depth-first walk adding an item only once everything blocking it has been added
+    fn visit(&self, id: Uuid, visited: &mut HashSet<Uuid>, ordered: &mut Vec<TodoItem>) {
+        if !visited.insert(id) {
+            return;
+        }
+        let todo = match self.0.get(&id) {
+            Some(todo) if todo.deleted_at.is_none() => todo,
+            _ => return,
+        };
+        for blocker_id in &todo.blocked_by {
+            self.visit(*blocker_id, visited, ordered);
+        }
+        ordered.push(todo.clone());
+    }
+}
+
+// Live items blocking `todo` that are not completed yet. Trashed blockers no longer count.
+pub fn open_blockers(store: &dyn TodoStore, todo: &TodoItem) -> StoreResult<Vec<TodoItem>> {
+    let mut open = Vec::new();
+    for blocker_id in &todo.blocked_by {
+        match store.get(*blocker_id)? {
+            Some(blocker) if blocker.deleted_at.is_none() && !blocker.completed => {
+                open.push(blocker)
+            }
+            _ => {}
+        }
+    }
+    Ok(open)
+}
+
+// Whether letting `id` be blocked by `blocked_by` would make it wait for itself. Trashed items are
+// followed too, so that restoring one can never close a cycle.
+pub fn creates_cycle(
+    store: &dyn TodoStore,
+    id: Uuid,
+    blocked_by: &BTreeSet<Uuid>,
+) -> StoreResult<bool> {
+    let mut seen = HashSet::new();
+    let mut pending: Vec<Uuid> = blocked_by.iter().copied().collect();
+    while let Some(blocker_id) = pending.pop() {
+        if blocker_id == id {
+            return Ok(true);
+        }
+        if seen.insert(blocker_id) {
+            if let Some(blocker) = store.get(blocker_id)? {
+                pending.extend(blocker.blocked_by.iter().copied());
+            }
+        }
+    }
+    Ok(false)
+}
@@ -0,0 +1,129 @@
+// Splits a filter expression into tokens, remembering where each one starts
+use super::ParseError;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum TokenKind {
+    Word(String), // Field name, keyword or unquoted value
+    Text(String), // Double-quoted string, with escapes resolved
+    Op(Operator), // Comparison operator
+    Open,         // (
+    Close,        // )
+}
+
+// Comparison between a field and a value
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operator {
+    Colon,        // field:value, "has" (containment for text, equality otherwise)
+    Equal,        // =
+    NotEqual,     // !=
+    Less,         // <
+    LessEqual,    // <=
+    Greater,      // >
+    GreaterEqual, // >=
+}
+
+impl Operator {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Operator::Colon => ":",
+            Operator::Equal => "=",
+            Operator::NotEqual => "!=",
+            Operator::Less => "<",
+            Operator::LessEqual => "<=",
+            Operator::Greater => ">",
+            Operator::GreaterEqual => ">=",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub position: usize, // Offset of the first character, counted in characters
+}
+
+// Characters that may appear in an unquoted word, e.g. `work`, `2024-05-01` or `+3d`
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '+' | '.')
+}
+
+pub fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let kind = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                TokenKind::Open
+            }
+            ')' => {
+                i += 1;
+                TokenKind::Close
+            }
+            ':' | '=' => {
+                i += 1;
+                TokenKind::Op(if c == ':' {
+                    Operator::Colon
+                } else {
+                    Operator::Equal
+                })
+            }
+            '<' | '>' | '!' => {
+                let equals = chars.get(i + 1) == Some(&'=');
+                i += if equals { 2 } else { 1 };
+                TokenKind::Op(match (c, equals) {
+                    ('<', false) => Operator::Less,
+                    ('<', true) => Operator::LessEqual,
+                    ('>', false) => Operator::Greater,
+                    ('>', true) => Operator::GreaterEqual,
+                    ('!', true) => Operator::NotEqual,
+                    _ => return Err(ParseError::new("expected `!=`", start)),
+                })
+            }
+            '"' => {
+                i += 1;
+                let mut text = String::new();
+                loop {
+                    match chars.get(i) {
+                        None => return Err(ParseError::new("unterminated string", start)),
+                        Some('"') => break,
+                        Some('\\') if i + 1 < chars.len() => {
+                            text.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(&c) => {
+                            text.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1; // Closing quote
+                TokenKind::Text(text)
+            }
+            _ if is_word_char(c) => {
+                while i < chars.len() && is_word_char(chars[i]) {
+                    i += 1;
+                }
+                TokenKind::Word(chars[start..i].iter().collect())
+            }
+            _ => {
+                return Err(ParseError::new(
+                    format!("unexpected character `{}`", c),
+                    start,
+                ))
+            }
+        };
+        tokens.push(Token {
+            kind,
+            position: start,
+        });
+    }
+    Ok(tokens)
+}
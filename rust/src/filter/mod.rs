@@ -0,0 +1,241 @@
+// Filter expressions such as `tag:work and not completed and due < +3d`, accepted by
+// GET /todos?filter= and stored in smart lists
+//
+// Grammar (keywords are case-insensitive, and `and` may be left out):
+//   expr      = and ("or" and)*
+//   and       = unary ("and"? unary)*
+//   unary     = "not" unary | "(" expr ")" | condition
+//   condition = field op value | word | "quoted text"
+//   op        = ":" | "=" | "!=" | "<" | "<=" | ">" | ">="
+// A bare flag name (`completed`, `overdue`) tests that flag; any other bare word or quoted text is
+// looked for in the title. `due`, `start` and `tag` can also be compared with `none`, e.g.
+// `due != none` or `tag:none`. `priority` takes none, low, medium or high and supports `<` and `>`.
+//
+// Times can be written as `now`, `today`, `yesterday`, `tomorrow`, a date (`2024-05-01`), a quoted
+// RFC 3339 timestamp, or an offset from now such as `+3d`, `-12h` or `-2w`. A date covers the whole
+// day in the filter's time zone, so `created = today` matches anything created today and
+// `created > today` only later items. All-day due and start dates are spans of a day too.
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::fmt;
+
+use crate::models::{Priority, Schedule, TodoItem};
+
+mod lexer;
+mod parser;
+
+use lexer::Operator;
+
+// Why an expression could not be parsed and where, as an offset in characters from its start
+#[derive(Serialize, Clone, Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl ParseError {
+    fn new(message: impl Into<String>, position: usize) -> Self {
+        ParseError {
+            message: message.into(),
+            position,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+// A parsed expression, with relative times pinned to the moment it was parsed
+#[derive(Clone, Debug)]
+pub struct Filter {
+    expr: Expr,
+    context: Context,
+}
+
+// What relative values are resolved against
+#[derive(Clone, Copy, Debug)]
+struct Context {
+    now: DateTime<Utc>,
+    tz: Tz, // Time zone deciding where days start, for dates, `today` and all-day due dates
+}
+
+impl Filter {
+    pub fn parse(source: &str, tz: Tz) -> Result<Self, ParseError> {
+        Ok(Filter {
+            expr: parser::parse(source)?,
+            context: Context {
+                now: Utc::now(),
+                tz,
+            },
+        })
+    }
+
+    pub fn matches(&self, todo: &TodoItem) -> bool {
+        self.expr.eval(todo, &self.context)
+    }
+
+    // Filter matching only items that both filters match
+    pub fn and(self, other: Filter) -> Filter {
+        Filter {
+            expr: Expr::And(Box::new(self.expr), Box::new(other.expr)),
+            context: self.context,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Condition(Condition),
+}
+
+// A single test against one field of an item
+#[derive(Clone, Debug)]
+enum Condition {
+    Completed(bool),
+    TitleContains(String), // Lowercased needle
+    TitleEquals(String),   // Lowercased title
+    Created(Operator, TimeValue),
+    Updated(Operator, TimeValue), // Items never updated count as updated when they were created
+    Due(Operator, TimeValue),     // Items without a due date never match
+    Start(Operator, TimeValue),   // Items without a start date never match
+    HasDue(bool),
+    HasStart(bool),
+    Overdue,           // Not completed and due date passed
+    Tag(String, bool), // Normalized tag the item must carry (true) or must not carry (false)
+    HasTags(bool),
+    Priority(Operator, Priority), // Priorities compare in order none < low < medium < high
+}
+
+// A point in time as written in the expression
+#[derive(Clone, Copy, Debug)]
+enum TimeValue {
+    FromNow(Duration), // now, +3d, -12h
+    FromToday(i64),    // today, tomorrow, yesterday (whole days)
+    Date(NaiveDate),   // 2024-05-01 (a whole day)
+    Instant(DateTime<Utc>),
+}
+
+impl TimeValue {
+    fn resolve(self, context: &Context) -> Moment {
+        match self {
+            TimeValue::FromNow(offset) => Moment::at(context.now + offset),
+            TimeValue::FromToday(days) => Moment::day(
+                context.now.with_timezone(&context.tz).date_naive() + Duration::days(days),
+                context.tz,
+            ),
+            TimeValue::Date(date) => Moment::day(date, context.tz),
+            TimeValue::Instant(instant) => Moment::at(instant),
+        }
+    }
+}
+
+// Either an instant (no end) or a span of time [start, end)
+#[derive(Clone, Copy, Debug)]
+struct Moment {
+    start: DateTime<Utc>,
+    end: Option<DateTime<Utc>>,
+}
+
+impl Moment {
+    fn at(time: DateTime<Utc>) -> Self {
+        Moment {
+            start: time,
+            end: None,
+        }
+    }
+
+    fn day(date: NaiveDate, tz: Tz) -> Self {
+        Moment::from(Schedule::AllDay(date).span(tz))
+    }
+
+    // Entirely over before `other` begins
+    fn before(&self, other: &Moment) -> bool {
+        match self.end {
+            Some(end) => end <= other.start,
+            None => self.start < other.start,
+        }
+    }
+
+    // Overlapping `other`; for two instants, the same instant
+    fn meets(&self, other: &Moment) -> bool {
+        !self.before(other)
+            && !other.before(self)
+            && (self.end.is_some() || other.end.is_some() || self.start == other.start)
+    }
+
+    // Compare this item's moment with a value the way `op` asks
+    fn compare(&self, op: Operator, value: &Moment) -> bool {
+        match op {
+            Operator::Less => self.before(value),
+            Operator::GreaterEqual => !self.before(value),
+            Operator::Greater => value.before(self),
+            Operator::LessEqual => !value.before(self),
+            Operator::Colon | Operator::Equal => self.meets(value),
+            Operator::NotEqual => !self.meets(value),
+        }
+    }
+}
+
+impl From<(DateTime<Utc>, Option<DateTime<Utc>>)> for Moment {
+    fn from((start, end): (DateTime<Utc>, Option<DateTime<Utc>>)) -> Self {
+        Moment { start, end }
+    }
+}
+
+impl Expr {
+    fn eval(&self, todo: &TodoItem, context: &Context) -> bool {
+        match self {
+            Expr::And(left, right) => left.eval(todo, context) && right.eval(todo, context),
+            Expr::Or(left, right) => left.eval(todo, context) || right.eval(todo, context),
+            Expr::Not(inner) => !inner.eval(todo, context),
+            Expr::Condition(condition) => condition.eval(todo, context),
+        }
+    }
+}
+
+impl Condition {
+    fn eval(&self, todo: &TodoItem, context: &Context) -> bool {
+        let schedule = |schedule: Option<Schedule>, op: Operator, value: TimeValue| {
+            schedule.is_some_and(|schedule| {
+                Moment::from(schedule.span(context.tz)).compare(op, &value.resolve(context))
+            })
+        };
+        match self {
+            Condition::Completed(completed) => todo.completed == *completed,
+            Condition::TitleContains(needle) => todo.title.to_lowercase().contains(needle),
+            Condition::TitleEquals(title) => todo.title.to_lowercase() == *title,
+            Condition::Created(op, value) => {
+                Moment::at(todo.created_at).compare(*op, &value.resolve(context))
+            }
+            Condition::Updated(op, value) => Moment::at(todo.updated_at.unwrap_or(todo.created_at))
+                .compare(*op, &value.resolve(context)),
+            Condition::Due(op, value) => schedule(todo.due_at, *op, *value),
+            Condition::Start(op, value) => schedule(todo.start_at, *op, *value),
+            Condition::HasDue(has) => todo.due_at.is_some() == *has,
+            Condition::HasStart(has) => todo.start_at.is_some() == *has,
+            Condition::Overdue => {
+                !todo.completed
+                    && todo
+                        .due_at
+                        .is_some_and(|due_at| due_at.has_passed(context.now, context.tz))
+            }
+            Condition::Tag(tag, carried) => todo.tags.contains(tag) == *carried,
+            Condition::HasTags(has) => todo.tags.is_empty() != *has,
+            Condition::Priority(op, priority) => match op {
+                Operator::Colon | Operator::Equal => todo.priority == *priority,
+                Operator::NotEqual => todo.priority != *priority,
+                Operator::Less => todo.priority < *priority,
+                Operator::LessEqual => todo.priority <= *priority,
+                Operator::Greater => todo.priority > *priority,
+                Operator::GreaterEqual => todo.priority >= *priority,
+            },
+        }
+    }
+}
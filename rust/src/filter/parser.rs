@@ -0,0 +1,320 @@
+// Recursive descent parser turning tokens into an expression tree, checking fields and values as it goes
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use super::lexer::{self, Operator, Token, TokenKind};
+use super::{Condition, Expr, ParseError, TimeValue};
+use crate::models::{normalize_tag, Priority};
+
+// Furthest an offset like `+3d` may reach from now, in days, so that every offset is a valid time
+const MAX_OFFSET_DAYS: i64 = 100 * 366;
+
+pub fn parse(source: &str) -> Result<Expr, ParseError> {
+    let mut parser = Parser {
+        tokens: lexer::tokenize(source)?,
+        index: 0,
+        end: source.chars().count(),
+    };
+    let expr = parser.or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) if token.kind == TokenKind::Close => Err(ParseError::new(
+            "unexpected `)` without a matching `(`",
+            token.position,
+        )),
+        Some(token) => Err(ParseError::new("expected `and` or `or`", token.position)),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+    end: usize, // Length of the source, reported for errors at the end of input
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+        token
+    }
+
+    // Consume the next token if it is the given keyword
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Word(word),
+                ..
+            }) if word.eq_ignore_ascii_case(keyword) => {
+                self.index += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+        loop {
+            if !self.keyword("and") {
+                // Two conditions next to each other are implicitly and-ed
+                match self.peek() {
+                    Some(Token {
+                        kind: TokenKind::Word(word),
+                        ..
+                    }) if !word.eq_ignore_ascii_case("or") => {}
+                    Some(Token {
+                        kind: TokenKind::Text(_) | TokenKind::Open,
+                        ..
+                    }) => {}
+                    _ => return Ok(expr),
+                }
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        let token = match self.next() {
+            Some(token) => token,
+            None => return Err(ParseError::new("expected a condition", self.end)),
+        };
+        match token.kind {
+            TokenKind::Open => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token {
+                        kind: TokenKind::Close,
+                        ..
+                    }) => Ok(expr),
+                    Some(other) => Err(ParseError::new("expected `)`", other.position)),
+                    None => Err(ParseError::new("expected `)`", self.end)),
+                }
+            }
+            TokenKind::Text(text) => Ok(Expr::Condition(Condition::TitleContains(
+                text.to_lowercase(),
+            ))),
+            TokenKind::Word(word) => match self.peek() {
+                Some(Token {
+                    kind: TokenKind::Op(op),
+                    position,
+                }) => {
+                    let (op, op_position) = (*op, *position);
+                    self.index += 1;
+                    let value = match self.next() {
+                        Some(value) => value,
+                        None => {
+                            return Err(ParseError::new(
+                                format!("expected a value after `{}`", op.symbol()),
+                                self.end,
+                            ))
+                        }
+                    };
+                    Ok(Expr::Condition(condition(
+                        &word,
+                        token.position,
+                        op,
+                        op_position,
+                        &value,
+                    )?))
+                }
+                _ => Ok(Expr::Condition(flag(&word))),
+            },
+            TokenKind::Op(op) => Err(ParseError::new(
+                format!("expected a field name before `{}`", op.symbol()),
+                token.position,
+            )),
+            TokenKind::Close => Err(ParseError::new("expected a condition", token.position)),
+        }
+    }
+}
+
+// A word on its own: a flag if it names one, otherwise text to look for in the title
+fn flag(word: &str) -> Condition {
+    match word.to_ascii_lowercase().as_str() {
+        "completed" | "done" => Condition::Completed(true),
+        "overdue" => Condition::Overdue,
+        _ => Condition::TitleContains(word.to_lowercase()),
+    }
+}
+
+// A `field op value` test, rejecting unknown fields, operators the field does not support and bad values
+fn condition(
+    field: &str,
+    field_position: usize,
+    op: Operator,
+    op_position: usize,
+    value: &Token,
+) -> Result<Condition, ParseError> {
+    let text = match &value.kind {
+        TokenKind::Word(text) | TokenKind::Text(text) => text.as_str(),
+        _ => return Err(ParseError::new("expected a value", value.position)),
+    };
+    let unsupported = || {
+        Err(ParseError::new(
+            format!("`{}` cannot be used with `{}`", op.symbol(), field),
+            op_position,
+        ))
+    };
+    match field.to_ascii_lowercase().as_str() {
+        "title" => match op {
+            Operator::Colon => Ok(Condition::TitleContains(text.to_lowercase())),
+            Operator::Equal => Ok(Condition::TitleEquals(text.to_lowercase())),
+            _ => unsupported(),
+        },
+        "completed" | "done" => {
+            let value = parse_bool(text, value.position)?;
+            match op {
+                Operator::Colon | Operator::Equal => Ok(Condition::Completed(value)),
+                Operator::NotEqual => Ok(Condition::Completed(!value)),
+                _ => unsupported(),
+            }
+        }
+        "created" => Ok(Condition::Created(op, parse_time(text, value.position)?)),
+        "updated" => Ok(Condition::Updated(op, parse_time(text, value.position)?)),
+        "due" | "start" => {
+            let due = field.eq_ignore_ascii_case("due");
+            if text.eq_ignore_ascii_case("none") {
+                let has = match op {
+                    Operator::Colon | Operator::Equal => false,
+                    Operator::NotEqual => true,
+                    _ => return unsupported(),
+                };
+                return Ok(if due {
+                    Condition::HasDue(has)
+                } else {
+                    Condition::HasStart(has)
+                });
+            }
+            let time = parse_time(text, value.position)?;
+            Ok(if due {
+                Condition::Due(op, time)
+            } else {
+                Condition::Start(op, time)
+            })
+        }
+        "tag" => {
+            if text.eq_ignore_ascii_case("none") {
+                return match op {
+                    Operator::Colon | Operator::Equal => Ok(Condition::HasTags(false)),
+                    Operator::NotEqual => Ok(Condition::HasTags(true)),
+                    _ => unsupported(),
+                };
+            }
+            let tag = normalize_tag(text).ok_or_else(|| {
+                ParseError::new(format!("invalid tag `{}`", text), value.position)
+            })?;
+            match op {
+                Operator::Colon | Operator::Equal => Ok(Condition::Tag(tag, true)),
+                Operator::NotEqual => Ok(Condition::Tag(tag, false)),
+                _ => unsupported(),
+            }
+        }
+        "priority" => Ok(Condition::Priority(
+            op,
+            Priority::parse(text).ok_or_else(|| {
+                ParseError::new(
+                    format!("expected none, low, medium or high, found `{}`", text),
+                    value.position,
+                )
+            })?,
+        )),
+        _ => Err(ParseError::new(
+            format!("unknown field `{}`", field),
+            field_position,
+        )),
+    }
+}
+
+fn parse_bool(text: &str, position: usize) -> Result<bool, ParseError> {
+    match text.to_ascii_lowercase().as_str() {
+        "true" | "yes" => Ok(true),
+        "false" | "no" => Ok(false),
+        _ => Err(ParseError::new(
+            format!("expected true or false, found `{}`", text),
+            position,
+        )),
+    }
+}
+
+fn parse_time(text: &str, position: usize) -> Result<TimeValue, ParseError> {
+    match text.to_ascii_lowercase().as_str() {
+        "now" => return Ok(TimeValue::FromNow(Duration::zero())),
+        "today" => return Ok(TimeValue::FromToday(0)),
+        "tomorrow" => return Ok(TimeValue::FromToday(1)),
+        "yesterday" => return Ok(TimeValue::FromToday(-1)),
+        _ => {}
+    }
+    if let Some(offset) = parse_offset(text) {
+        if offset.num_days().abs() > MAX_OFFSET_DAYS {
+            return Err(ParseError::new(
+                format!(
+                    "offset `{}` reaches more than {} days from now",
+                    text, MAX_OFFSET_DAYS
+                ),
+                position,
+            ));
+        }
+        return Ok(TimeValue::FromNow(offset));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(TimeValue::Date(date));
+    }
+    if let Ok(instant) = DateTime::parse_from_rfc3339(text) {
+        return Ok(TimeValue::Instant(instant.with_timezone(&Utc)));
+    }
+    Err(ParseError::new(
+        format!(
+            "expected a date, a timestamp or an offset like +3d, found `{}`",
+            text
+        ),
+        position,
+    ))
+}
+
+// Signed offset from now: a number of hours (h), days (d) or weeks (w), e.g. `+3d` or `-12h`
+fn parse_offset(text: &str) -> Option<Duration> {
+    let sign = match text.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let unit = text.chars().last()?;
+    let amount: i64 = text.get(1..text.len() - 1)?.parse().ok()?;
+    let amount = sign * amount;
+    match unit {
+        'h' => Duration::try_hours(amount),
+        'd' => Duration::try_days(amount),
+        'w' => Duration::try_weeks(amount),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_offset_out_of_range_is_refused_where_it_stands() {
+        assert!(parse("due < +36600d").is_ok());
+        assert!(parse("updated > -5000w").is_ok());
+        let err = parse("done = false and due < +100000000d").unwrap_err();
+        assert_eq!(err.position, 23);
+        assert!(err.message.contains("+100000000d"));
+        assert!(parse("created > -9999999h").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+// HTTP handlers for accounts: registering, logging in and out, and who is signed in
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::try_again_at;
+use super::two_factor::check_code;
+use crate::auth::{self, Refusal};
+use crate::models::{
+    normalize_username, Credentials, LoginResponse, Session, User, UserProfile,
+};
+use crate::state::AppState;
+use crate::store::{Event, StoreError, StoreResult, TodoQuery};
+
+// Shortest password accepted at registration, in characters
+const MIN_PASSWORD_LEN: usize = 8;
+
+// Answer to a login while the account is locked after too many wrong passwords
+const LOCKED_OUT: &str = "Too many failed logins; try again later";
+
+// Events handing everything stored before accounts existed to `owner`
+fn claim_unowned(data: &AppState, owner: Uuid) -> StoreResult<Vec<Event>> {
+    let mut events = Vec::new();
+    for in_trash in [false, true] {
+        let query = TodoQuery {
+            in_trash,
+            ..TodoQuery::default()
+        };
+        for mut todo in data.store.list(&query)? {
+            if todo.owner_id.is_none() {
+                todo.owner_id = Some(owner);
+                events.push(Event::TodoUpdated(todo));
+            }
+        }
+    }
+    for mut list in data.store.todo_lists()? {
+        if list.owner_id.is_none() {
+            list.owner_id = Some(owner);
+            events.push(Event::ListUpdated(list));
+        }
+    }
+    for mut list in data.store.smart_lists()? {
+        if list.owner_id.is_none() {
+            list.owner_id = Some(owner);
+            events.push(Event::SmartListUpdated(list));
+        }
+    }
+    Ok(events)
+}
+
+// POST /auth/register: create an account. The first account also takes over the items, lists and
+// smart lists stored before there were accounts.
+pub async fn register(
+    item: web::Json<Credentials>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let username = match normalize_username(&item.username) {
+        Some(username) => username,
+        None => {
+            return Ok(HttpResponse::BadRequest()
+                .body("Usernames are 3 to 32 letters, digits, dots, underscores or dashes"))
+        }
+    };
+    if item.password.chars().count() < MIN_PASSWORD_LEN {
+        return Ok(HttpResponse::BadRequest().body(format!(
+            "Passwords must be at least {} characters",
+            MIN_PASSWORD_LEN
+        )));
+    }
+    let password_hash = auth::hash_password(&item.password);
+    let _guard = data.writes.lock().unwrap();
+    if data.store.user_by_name(&username)?.is_some() {
+        return Ok(HttpResponse::Conflict().body("Username is taken"));
+    }
+    let user = User {
+        id: Uuid::new_v4(),
+        username,
+        password_hash,
+        created_at: Utc::now(),
+        failed_logins: 0,
+        locked_until: None,
+    };
+    let mut events = vec![Event::UserCreated(user.clone())];
+    events.extend(claim_unowned(&data, user.id)?);
+    data.commit(&events)?;
+    Ok(HttpResponse::Ok().json(UserProfile::from(&user)))
+}
+
+// POST /auth/login: check the password and start a session, returning its bearer token. Sessions of
+// the account that have expired are cleared out on the way.
+pub async fn login(
+    item: web::Json<Credentials>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let user = match normalize_username(&item.username) {
+        Some(username) => data.store.user_by_name(&username)?,
+        None => None,
+    };
+    // An unknown name costs the same hashing as a known one, so timing does not tell them apart
+    let user = match user {
+        Some(user) => user,
+        None => {
+            auth::verify_password(&item.password, auth::dummy_hash());
+            return Ok(HttpResponse::Unauthorized().body("Invalid username or password"));
+        }
+    };
+    if let Some(until) = user.locked_until.filter(|until| *until > Utc::now()) {
+        return Ok(try_again_at(until, LOCKED_OUT));
+    }
+    let right = auth::verify_password(&item.password, &user.password_hash);
+    let _guard = data.writes.lock().unwrap();
+    // Count the attempt against the account as stored now, not as read before hashing
+    let mut user = match data.store.user(user.id)? {
+        Some(user) => user,
+        None => return Ok(HttpResponse::Unauthorized().body("Invalid username or password")),
+    };
+    let before = (user.failed_logins, user.locked_until);
+    if let Err(refusal) = auth::count_login(&mut user, right, Utc::now()) {
+        data.commit(&[Event::UserUpdated(user)])?;
+        return Ok(match refusal {
+            Refusal::Wrong => HttpResponse::Unauthorized().body("Invalid username or password"),
+            Refusal::Locked(until) => try_again_at(until, LOCKED_OUT),
+        });
+    }
+    let counted = (user.failed_logins, user.locked_until) != before;
+    // With two-factor sign-in on, the password alone is not enough
+    let factor = match data.store.two_factor(user.id)? {
+        Some(mut factor) if factor.enabled => {
+            let code = match &item.code {
+                Some(code) => code,
+                None => return Ok(HttpResponse::Unauthorized().body("Two-factor code required")),
+            };
+            if let Err(rejection) =
+                check_code(&data, &mut factor, code, true, StatusCode::UNAUTHORIZED)?
+            {
+                return Ok(rejection);
+            }
+            Some(factor)
+        }
+        _ => None,
+    };
+    let token = auth::new_token();
+    let now = Utc::now();
+    let session = Session {
+        id: auth::token_id(&token),
+        user_id: user.id,
+        created_at: now,
+        expires_at: now + data.session_ttl,
+    };
+    let mut events: Vec<Event> = data
+        .store
+        .sessions(user.id)?
+        .into_iter()
+        .filter(|session| session.expires_at <= now)
+        .map(|session| Event::SessionDeleted { id: session.id })
+        .collect();
+    if counted {
+        events.push(Event::UserUpdated(user.clone()));
+    }
+    events.extend(factor.map(Event::TwoFactorUpdated));
+    events.push(Event::SessionCreated(session.clone()));
+    data.commit(&events)?;
+    Ok(HttpResponse::Ok().json(LoginResponse {
+        token,
+        expires_at: session.expires_at,
+        user: UserProfile::from(&user),
+    }))
+}
+
+// POST /auth/logout: end the session whose token signed this request
+pub async fn logout(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let id = match auth::bearer_token(req.headers()) {
+        Some(token) => auth::token_id(token),
+        None => return Ok(auth::unauthorized()),
+    };
+    let _guard = data.writes.lock().unwrap();
+    if data.store.session(&id)?.is_some() {
+        data.commit(&[Event::SessionDeleted { id }])?;
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// GET /auth/me: the signed-in account
+pub async fn get_me(user: User) -> HttpResponse {
+    HttpResponse::Ok().json(UserProfile::from(&user))
+}
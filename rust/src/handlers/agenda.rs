@@ -0,0 +1,148 @@
+// HTTP handlers for the due date views: overdue, due today and upcoming
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+
+use super::todos::time_zone;
+use crate::models::{AgendaParams, Role, Schedule, User};
+use crate::sharing;
+use crate::state::AppState;
+use crate::store::{StoreError, TodoQuery};
+
+// Number of days after today covered by the upcoming view
+const UPCOMING_DAYS: i64 = 7;
+
+// GET /todos/overdue?tz=: open items whose due date has passed
+pub async fn get_overdue(
+    params: web::Query<AgendaParams>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    agenda(&params, &user, &data, |due, now, _, tz| due.has_passed(now, tz))
+}
+
+// GET /todos/due-today?tz=: open items due today, including those whose time today has passed
+pub async fn get_due_today(
+    params: web::Query<AgendaParams>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    agenda(&params, &user, &data, |due, _, today, tz| due.date_in(tz) == today)
+}
+
+// GET /todos/upcoming?tz=: open items due within the week after today
+pub async fn get_upcoming(
+    params: web::Query<AgendaParams>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    agenda(&params, &user, &data, |due, _, today, tz| {
+        (1..=UPCOMING_DAYS).contains(&(due.date_in(tz) - today).num_days())
+    })
+}
+
+// Open, live items `user` can see whose due date passes `keep` (given the due date, now, today and
+// the time zone), soonest due first
+fn agenda(
+    params: &AgendaParams,
+    user: &User,
+    data: &AppState,
+    keep: impl Fn(Schedule, DateTime<Utc>, NaiveDate, Tz) -> bool,
+) -> Result<HttpResponse, StoreError> {
+    let tz = match time_zone(params.tz.as_deref()) {
+        Ok(tz) => tz,
+        Err(err) => return Ok(err.response()),
+    };
+    let now = Utc::now();
+    let today = now.with_timezone(&tz).date_naive();
+    let query = TodoQuery {
+        completed: Some(false),
+        visible: Some(sharing::visibility(
+            data.store.as_ref(),
+            user,
+            Role::Viewer,
+        )?),
+        ..TodoQuery::default()
+    };
+    let mut todos: Vec<_> = data
+        .store
+        .list(&query)?
+        .into_iter()
+        .filter(|todo| todo.due_at.is_some_and(|due| keep(due, now, today, tz)))
+        .collect();
+    todos.sort_by_key(|todo| (todo.due_at.map(|due| due.span(tz).0), todo.id));
+    Ok(HttpResponse::Ok().json(todos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TodoItem;
+    use crate::store::Event;
+    use crate::testing::{app_state, todo, user, TempDir};
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+    use chrono::Duration;
+    use uuid::Uuid;
+
+    fn due(owner: &User, days_from_today: i64) -> TodoItem {
+        let date = Utc::now().date_naive() + Duration::days(days_from_today);
+        TodoItem {
+            owner_id: Some(owner.id),
+            due_at: Some(Schedule::AllDay(date)),
+            ..todo(None)
+        }
+    }
+
+    async fn ids(response: HttpResponse) -> Vec<Uuid> {
+        let todos: Vec<TodoItem> =
+            serde_json::from_slice(&to_bytes(response.into_body()).await.unwrap()).unwrap();
+        todos.into_iter().map(|todo| todo.id).collect()
+    }
+
+    #[actix_web::test]
+    async fn views_hold_the_open_items_due_in_them() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let (owner, other) = (user("owner"), user("other"));
+        let overdue = due(&owner, -2);
+        let upcoming = due(&owner, 3);
+        let done = TodoItem {
+            completed: true,
+            ..due(&owner, -2)
+        };
+        let others = due(&other, -2);
+        let mut events = vec![Event::UserCreated(owner.clone()), Event::UserCreated(other)];
+        for item in [&overdue, &upcoming, &done, &others] {
+            events.push(Event::TodoCreated(item.clone()));
+        }
+        data.commit(&events).unwrap();
+
+        let params = || web::Query(AgendaParams { tz: None });
+        let response = get_overdue(params(), owner.clone(), data.clone())
+            .await
+            .unwrap();
+        assert_eq!(ids(response).await, [overdue.id]);
+        let response = get_upcoming(params(), owner.clone(), data.clone())
+            .await
+            .unwrap();
+        assert_eq!(ids(response).await, [upcoming.id]);
+        let response = get_due_today(params(), owner.clone(), data.clone())
+            .await
+            .unwrap();
+        assert!(ids(response).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn an_unknown_time_zone_is_refused() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let params = AgendaParams {
+            tz: Some("Mars/Olympus_Mons".to_string()),
+        };
+        let response = get_overdue(web::Query(params), user("owner"), data)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
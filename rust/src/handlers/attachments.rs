@@ -0,0 +1,318 @@
+// HTTP handlers for files attached to to-do items: multipart upload, listing, download and removal
+use actix_files::NamedFile;
+use actix_multipart::Multipart;
+use actix_web::http::header::{
+    Charset, ContentDisposition, DispositionParam, DispositionType, ExtendedValue,
+};
+use actix_web::{mime, web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use futures_util::StreamExt;
+use uuid::Uuid;
+
+use super::todos::{find_editable, find_live};
+use crate::attachments::Upload;
+use crate::models::{Attachment, User};
+use crate::state::AppState;
+use crate::store::{Event, StoreError};
+
+// Name to keep for an uploaded file: only its last path component, without control characters
+fn clean_file_name(name: &str) -> String {
+    let name: String = name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => "file".to_string(),
+        name => name.to_string(),
+    }
+}
+
+// Types browsers can show on their own without running anything; everything else is downloaded
+fn shown_inline(mime: &mime::Mime) -> bool {
+    match mime.type_() {
+        mime::IMAGE => mime.subtype() != mime::SVG,
+        mime::AUDIO | mime::VIDEO => true,
+        mime::TEXT => mime.subtype() == mime::PLAIN,
+        mime::APPLICATION => mime.subtype() == mime::PDF,
+        _ => false,
+    }
+}
+
+// Content-Disposition naming the file, with a UTF-8 variant when the name is not plain ASCII
+fn disposition(attachment: &Attachment, mime: &mime::Mime) -> ContentDisposition {
+    let name = &attachment.file_name;
+    let mut parameters = vec![DispositionParam::Filename(
+        name.chars()
+            .map(|c| if c.is_ascii() { c } else { '_' })
+            .collect(),
+    )];
+    if !name.is_ascii() {
+        parameters.push(DispositionParam::FilenameExt(ExtendedValue {
+            charset: Charset::Ext("UTF-8".to_string()),
+            language_tag: None,
+            value: name.as_bytes().to_vec(),
+        }));
+    }
+    ContentDisposition {
+        disposition: if shown_inline(mime) {
+            DispositionType::Inline
+        } else {
+            DispositionType::Attachment
+        },
+        parameters,
+    }
+}
+
+// The attachment `attachment_id` of the live item `id`, if both exist
+fn find_attachment(
+    data: &AppState,
+    user: &User,
+    id: Uuid,
+    attachment_id: Uuid,
+) -> Result<Result<Attachment, HttpResponse>, StoreError> {
+    if find_live(data, user, id)?.is_none() {
+        return Ok(Err(HttpResponse::NotFound().body("Todo item not found")));
+    }
+    match data.store.attachment(attachment_id)? {
+        Some(attachment) if attachment.todo_id == id => Ok(Ok(attachment)),
+        _ => Ok(Err(HttpResponse::NotFound().body("Attachment not found"))),
+    }
+}
+
+// POST /todos/{id}/attachments: store every file part of a multipart/form-data body, returning the
+// new attachments. Parts without a file name are ignored. The content type is sniffed from the
+// contents rather than taken from the client.
+pub async fn upload_attachments(
+    path: web::Path<Uuid>,
+    mut payload: Multipart,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let id = *path;
+    if let Err(rejection) = find_editable(&data, &user, id)? {
+        return Ok(rejection);
+    }
+    let mut uploads: Vec<(String, Upload)> = Vec::new();
+    while let Some(field) = payload.next().await {
+        let mut field = match field {
+            Ok(field) => field,
+            Err(err) => return Ok(HttpResponse::BadRequest().body(err.to_string())),
+        };
+        let file_name = match field
+            .content_disposition()
+            .and_then(|disposition| disposition.get_filename())
+        {
+            Some(name) => clean_file_name(name),
+            None => continue,
+        };
+        let mut upload = data.blobs.upload()?;
+        while let Some(chunk) = field.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(err) => return Ok(HttpResponse::BadRequest().body(err.to_string())),
+            };
+            if upload.size() + chunk.len() as u64 > data.blobs.max_bytes {
+                return Ok(HttpResponse::PayloadTooLarge().body(format!(
+                    "Attachments may be at most {} bytes",
+                    data.blobs.max_bytes
+                )));
+            }
+            upload.write(&chunk)?;
+        }
+        uploads.push((file_name, upload));
+    }
+    if uploads.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("No file in the upload"));
+    }
+    let _guard = data.writes.lock().unwrap();
+    // The item may have been deleted or its list unshared while the upload was streaming in
+    if let Err(rejection) = find_editable(&data, &user, id)? {
+        return Ok(rejection);
+    }
+    let created_at = Utc::now();
+    let mut attachments = Vec::new();
+    for (file_name, upload) in uploads {
+        let (digest, size, content_type) = upload.finish(&data.blobs)?;
+        attachments.push(Attachment {
+            id: Uuid::new_v4(),
+            todo_id: id,
+            file_name,
+            content_type,
+            size,
+            digest,
+            created_at,
+        });
+    }
+    let events: Vec<Event> = attachments
+        .iter()
+        .cloned()
+        .map(Event::AttachmentCreated)
+        .collect();
+    data.commit(&events)?;
+    Ok(HttpResponse::Ok().json(attachments))
+}
+
+// GET /todos/{id}/attachments: the files attached to an item, oldest first
+pub async fn get_attachments(
+    path: web::Path<Uuid>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    if find_live(&data, &user, *path)?.is_none() {
+        return Ok(HttpResponse::NotFound().body("Todo item not found"));
+    }
+    Ok(HttpResponse::Ok().json(data.store.attachments(Some(*path))?))
+}
+
+// GET /todos/{id}/attachments/{attachment_id}: the contents of a file, with support for range
+// requests and conditional requests
+pub async fn download_attachment(
+    path: web::Path<(Uuid, Uuid)>,
+    req: HttpRequest,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let (id, attachment_id) = path.into_inner();
+    let attachment = match find_attachment(&data, &user, id, attachment_id)? {
+        Ok(attachment) => attachment,
+        Err(rejection) => return Ok(rejection),
+    };
+    let mime: mime::Mime = attachment
+        .content_type
+        .parse()
+        .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+    let disposition = disposition(&attachment, &mime);
+    let mut response = NamedFile::open(data.blobs.path(&attachment.digest))?
+        .set_content_type(mime)
+        .set_content_disposition(disposition)
+        .into_response(&req);
+    // Browsers must not second-guess the sniffed type
+    response.headers_mut().insert(
+        actix_web::http::header::X_CONTENT_TYPE_OPTIONS,
+        actix_web::http::header::HeaderValue::from_static("nosniff"),
+    );
+    Ok(response)
+}
+
+// DELETE /todos/{id}/attachments/{attachment_id}: remove a file; its blob goes too unless another
+// attachment has the same contents
+pub async fn delete_attachment(
+    path: web::Path<(Uuid, Uuid)>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let (id, attachment_id) = path.into_inner();
+    let _guard = data.writes.lock().unwrap();
+    if let Err(rejection) = find_editable(&data, &user, id)? {
+        return Ok(rejection);
+    }
+    if let Err(rejection) = find_attachment(&data, &user, id, attachment_id)? {
+        return Ok(rejection);
+    }
+    data.commit(&[Event::AttachmentDeleted { id: attachment_id }])?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth;
+    use crate::models::{Session, TodoItem};
+    use crate::testing::{app_state, todo, user, TempDir};
+    use actix_web::http::header::{AUTHORIZATION, CONTENT_TYPE, RANGE};
+    use actix_web::http::StatusCode;
+    use actix_web::{middleware, test, App};
+    use chrono::Duration;
+
+    const BOUNDARY: &str = "attachment-test-boundary";
+
+    // A multipart/form-data body holding one file
+    fn form(file_name: &str, contents: &[u8]) -> Vec<u8> {
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            BOUNDARY, file_name
+        )
+        .into_bytes();
+        body.extend_from_slice(contents);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+        body
+    }
+
+    #[actix_web::test]
+    async fn files_are_stored_served_in_ranges_and_refused_past_the_limit() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let owner = user("owner");
+        let item = TodoItem {
+            owner_id: Some(owner.id),
+            ..todo(None)
+        };
+        let token = auth::new_token();
+        let session = Session {
+            id: auth::token_id(&token),
+            user_id: owner.id,
+            created_at: Utc::now(),
+            expires_at: Utc::now() + Duration::hours(1),
+        };
+        data.commit(&[
+            Event::UserCreated(owner),
+            Event::SessionCreated(session),
+            Event::TodoCreated(item.clone()),
+        ])
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .wrap(middleware::from_fn(auth::authenticate))
+                .route(
+                    "/todos/{id}/attachments",
+                    web::post().to(upload_attachments),
+                )
+                .route(
+                    "/todos/{id}/attachments/{attachment_id}",
+                    web::get().to(download_attachment),
+                ),
+        )
+        .await;
+        let bearer = format!("Bearer {}", token);
+        let upload = |body: Vec<u8>| {
+            test::TestRequest::post()
+                .uri(&format!("/todos/{}/attachments", item.id))
+                .insert_header((AUTHORIZATION, bearer.clone()))
+                .insert_header((
+                    CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={}", BOUNDARY),
+                ))
+                .set_payload(body)
+                .to_request()
+        };
+
+        let response = test::call_service(&app, upload(form("../notes.txt", b"hello"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let attachments: Vec<Attachment> = test::read_body_json(response).await;
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].file_name, "notes.txt");
+        assert_eq!(attachments[0].size, 5);
+
+        let download = test::TestRequest::get()
+            .uri(&format!(
+                "/todos/{}/attachments/{}",
+                item.id, attachments[0].id
+            ))
+            .insert_header((AUTHORIZATION, bearer.clone()))
+            .insert_header((RANGE, "bytes=1-3"))
+            .to_request();
+        let response = test::call_service(&app, download).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(test::read_body(response).await, "ell");
+
+        let too_large = vec![b'x'; data.blobs.max_bytes as usize + 1];
+        let response = test::call_service(&app, upload(form("big.bin", &too_large))).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(data.store.attachments(Some(item.id)).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,112 @@
+// HTTP handler for ticking off or editing a single checklist step without resending the whole list
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::todos::find_editable;
+use super::Actor;
+use crate::history;
+use crate::models::{RevisionAction, UpdateChecklistItem, User};
+use crate::state::AppState;
+use crate::store::{Event, StoreError};
+
+// PUT /todos/{id}/checklist/{item_id}: change the text or the done flag of one step, returning the item
+pub async fn update_checklist_item(
+    path: web::Path<(Uuid, Uuid)>,
+    item: web::Json<UpdateChecklistItem>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let (id, item_id) = path.into_inner();
+    if item
+        .text
+        .as_deref()
+        .is_some_and(|text| text.trim().is_empty())
+    {
+        return Ok(HttpResponse::BadRequest().body("Checklist items must have a text"));
+    }
+    let _guard = data.writes.lock().unwrap();
+    let before = match find_editable(&data, &user, id)? {
+        Ok(todo) => todo,
+        Err(rejection) => return Ok(rejection),
+    };
+    let mut todo = before.clone();
+    let step = match todo.checklist.iter_mut().find(|step| step.id == item_id) {
+        Some(step) => step,
+        None => return Ok(HttpResponse::NotFound().body("Checklist item not found")),
+    };
+    if let Some(text) = &item.text {
+        step.text = text.trim().to_string();
+    }
+    if let Some(done) = item.done {
+        step.done = done;
+    }
+    todo.updated_at = Some(Utc::now());
+    let mut events = vec![Event::TodoUpdated(todo.clone())];
+    events.extend(history::record(
+        data.store.as_ref(),
+        Some(&before),
+        &todo,
+        RevisionAction::Updated,
+        &actor.0,
+    )?);
+    data.commit(&events)?;
+    Ok(HttpResponse::Ok().json(todo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ChecklistItem, TodoItem};
+    use crate::testing::{app_state, todo, user, TempDir};
+    use actix_web::http::StatusCode;
+
+    #[actix_web::test]
+    async fn a_single_step_is_ticked_off_and_blank_texts_are_refused() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let owner = user("owner");
+        let step = ChecklistItem {
+            id: Uuid::new_v4(),
+            text: "buy milk".to_string(),
+            done: false,
+        };
+        let item = TodoItem {
+            owner_id: Some(owner.id),
+            checklist: vec![step.clone()],
+            ..todo(None)
+        };
+        data.commit(&[
+            Event::UserCreated(owner.clone()),
+            Event::TodoCreated(item.clone()),
+        ])
+        .unwrap();
+        let update = |step_id, text: Option<&str>, done| {
+            let change = UpdateChecklistItem {
+                text: text.map(str::to_string),
+                done,
+            };
+            update_checklist_item(
+                web::Path::from((item.id, step_id)),
+                web::Json(change),
+                owner.clone(),
+                Actor(owner.username.clone()),
+                data.clone(),
+            )
+        };
+
+        let response = update(step.id, None, Some(true)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let stored = data.store.get(item.id).unwrap().unwrap();
+        assert!(stored.checklist[0].done);
+        assert_eq!(stored.checklist[0].text, "buy milk");
+
+        let response = update(step.id, Some("  "), None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = update(Uuid::new_v4(), None, Some(true)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let stored = data.store.get(item.id).unwrap().unwrap();
+        assert_eq!(stored.checklist[0].text, "buy milk");
+    }
+}
@@ -0,0 +1,222 @@
+// HTTP handlers for the comment thread of a to-do item
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::todos::{find_editable, find_live};
+use super::Actor;
+use crate::models::{Comment, CommentBody, User};
+use crate::state::AppState;
+use crate::store::{Event, StoreError};
+
+// The trimmed body of a comment, or none when it is empty
+fn comment_body(item: &CommentBody) -> Option<String> {
+    Some(item.body.trim())
+        .filter(|body| !body.is_empty())
+        .map(str::to_string)
+}
+
+// The comment `comment_id` on the live item `id`, if `user` may change the item and the comment was
+// written by `actor`
+fn find_own_comment(
+    data: &AppState,
+    user: &User,
+    id: Uuid,
+    comment_id: Uuid,
+    actor: &Actor,
+) -> Result<Result<Comment, HttpResponse>, StoreError> {
+    if let Err(rejection) = find_editable(data, user, id)? {
+        return Ok(Err(rejection));
+    }
+    match data.store.comment(comment_id)? {
+        Some(comment) if comment.todo_id != id => {
+            Ok(Err(HttpResponse::NotFound().body("Comment not found")))
+        }
+        Some(comment) if comment.author != actor.0 => Ok(Err(
+            HttpResponse::Forbidden().body("Only the author can change a comment")
+        )),
+        Some(comment) => Ok(Ok(comment)),
+        None => Ok(Err(HttpResponse::NotFound().body("Comment not found"))),
+    }
+}
+
+// GET /todos/{id}/comments: the comments on an item, oldest first
+pub async fn get_comments(
+    path: web::Path<Uuid>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    if find_live(&data, &user, *path)?.is_none() {
+        return Ok(HttpResponse::NotFound().body("Todo item not found"));
+    }
+    Ok(HttpResponse::Ok().json(data.store.comments(*path)?))
+}
+
+// POST /todos/{id}/comments: post a comment as the actor, returning it
+pub async fn add_comment(
+    path: web::Path<Uuid>,
+    item: web::Json<CommentBody>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let body = match comment_body(&item) {
+        Some(body) => body,
+        None => return Ok(HttpResponse::BadRequest().body("Comments must have a body")),
+    };
+    let _guard = data.writes.lock().unwrap();
+    if let Err(rejection) = find_editable(&data, &user, *path)? {
+        return Ok(rejection);
+    }
+    let comment = Comment {
+        id: Uuid::new_v4(),
+        todo_id: *path,
+        author: actor.0,
+        body,
+        created_at: Utc::now(),
+        edited_at: None,
+    };
+    data.commit(&[Event::CommentCreated(comment.clone())])?;
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+// PUT /todos/{id}/comments/{comment_id}: change the body of one of the actor's own comments
+pub async fn update_comment(
+    path: web::Path<(Uuid, Uuid)>,
+    item: web::Json<CommentBody>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let (id, comment_id) = path.into_inner();
+    let body = match comment_body(&item) {
+        Some(body) => body,
+        None => return Ok(HttpResponse::BadRequest().body("Comments must have a body")),
+    };
+    let _guard = data.writes.lock().unwrap();
+    let mut comment = match find_own_comment(&data, &user, id, comment_id, &actor)? {
+        Ok(comment) => comment,
+        Err(rejection) => return Ok(rejection),
+    };
+    if comment.body != body {
+        comment.body = body;
+        comment.edited_at = Some(Utc::now());
+        data.commit(&[Event::CommentUpdated(comment.clone())])?;
+    }
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+// DELETE /todos/{id}/comments/{comment_id}: remove one of the actor's own comments
+pub async fn delete_comment(
+    path: web::Path<(Uuid, Uuid)>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let (id, comment_id) = path.into_inner();
+    let _guard = data.writes.lock().unwrap();
+    if let Err(rejection) = find_own_comment(&data, &user, id, comment_id, &actor)? {
+        return Ok(rejection);
+    }
+    data.commit(&[Event::CommentDeleted { id: comment_id }])?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ListMember, Role, TodoItem};
+    use crate::testing::{app_state, list, todo, user, TempDir};
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+
+    fn body(text: &str) -> web::Json<CommentBody> {
+        web::Json(CommentBody {
+            body: text.to_string(),
+        })
+    }
+
+    #[actix_web::test]
+    async fn only_the_author_changes_a_comment() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let (owner, editor) = (user("owner"), user("editor"));
+        let shared = list(&owner);
+        let item = TodoItem {
+            owner_id: Some(owner.id),
+            ..todo(Some(shared.id))
+        };
+        let member = ListMember {
+            list_id: shared.id,
+            user_id: editor.id,
+            role: Role::Editor,
+            added_at: Utc::now(),
+        };
+        data.commit(&[
+            Event::UserCreated(owner.clone()),
+            Event::UserCreated(editor.clone()),
+            Event::ListCreated(shared),
+            Event::MemberAdded(member),
+            Event::TodoCreated(item.clone()),
+        ])
+        .unwrap();
+        let actor = |user: &User| Actor(user.username.clone());
+
+        let response = add_comment(
+            web::Path::from(item.id),
+            body("  "),
+            owner.clone(),
+            actor(&owner),
+            data.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = add_comment(
+            web::Path::from(item.id),
+            body(" bring bags "),
+            owner.clone(),
+            actor(&owner),
+            data.clone(),
+        )
+        .await
+        .unwrap();
+        let comment: Comment =
+            serde_json::from_slice(&to_bytes(response.into_body()).await.unwrap()).unwrap();
+        assert_eq!(
+            (comment.body.as_str(), comment.author.as_str()),
+            ("bring bags", "owner")
+        );
+
+        // A member who may edit the item still may not edit someone else's comment
+        let path = || web::Path::from((item.id, comment.id));
+        let response = update_comment(
+            path(),
+            body("no"),
+            editor.clone(),
+            actor(&editor),
+            data.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = delete_comment(path(), editor.clone(), actor(&editor), data.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = update_comment(
+            path(),
+            body("bring two bags"),
+            owner.clone(),
+            actor(&owner),
+            data.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let comments = data.store.comments(item.id).unwrap();
+        assert_eq!(comments[0].body, "bring two bags");
+        assert!(comments[0].edited_at.is_some());
+    }
+}
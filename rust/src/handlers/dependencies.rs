@@ -0,0 +1,145 @@
+// HTTP handlers for working with the "blocked by" links between to-do items
+use actix_web::{web, HttpResponse};
+use uuid::Uuid;
+
+use super::todos::find_live;
+use crate::dependencies::Dependencies;
+use crate::models::{Role, User};
+use crate::sharing;
+use crate::state::AppState;
+use crate::store::StoreError;
+
+// GET /todos/actionable: open items whose blocking tasks are all completed, oldest first
+pub async fn get_actionable(
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let visible = sharing::visibility(data.store.as_ref(), &user, Role::Viewer)?;
+    let dependencies = Dependencies::load(data.store.as_ref())?;
+    let mut actionable = dependencies.actionable();
+    actionable.retain(|todo| visible.covers(todo));
+    Ok(HttpResponse::Ok().json(actionable))
+}
+
+// GET /todos/{id}/prerequisites: every item the given one waits for, directly or through other
+// items, ordered so that each item comes after the ones blocking it
+pub async fn get_prerequisites(
+    path: web::Path<Uuid>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    if find_live(&data, &user, *path)?.is_none() {
+        return Ok(HttpResponse::NotFound().body("Todo item not found"));
+    }
+    // Blockers may have moved into lists the account cannot see since they were linked
+    let visible = sharing::visibility(data.store.as_ref(), &user, Role::Viewer)?;
+    let dependencies = Dependencies::load(data.store.as_ref())?;
+    let mut prerequisites = dependencies.prerequisites(*path);
+    prerequisites.retain(|todo| visible.covers(todo));
+    Ok(HttpResponse::Ok().json(prerequisites))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::todos::update_todo;
+    use crate::handlers::Actor;
+    use crate::models::{CascadeParams, TodoItem};
+    use crate::store::Event;
+    use crate::testing::{app_state, todo, user, TempDir};
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+    use serde_json::json;
+
+    fn owned_by(owner: &User) -> TodoItem {
+        TodoItem {
+            owner_id: Some(owner.id),
+            ..todo(None)
+        }
+    }
+
+    #[actix_web::test]
+    async fn blocked_items_wait_for_their_blockers() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let owner = user("owner");
+        let blocker = owned_by(&owner);
+        let blocked = TodoItem {
+            blocked_by: [blocker.id].iter().copied().collect(),
+            ..owned_by(&owner)
+        };
+        data.commit(&[
+            Event::UserCreated(owner.clone()),
+            Event::TodoCreated(blocker.clone()),
+            Event::TodoCreated(blocked.clone()),
+        ])
+        .unwrap();
+
+        let response = get_actionable(owner.clone(), data.clone()).await.unwrap();
+        let actionable: Vec<TodoItem> =
+            serde_json::from_slice(&to_bytes(response.into_body()).await.unwrap()).unwrap();
+        let ids: Vec<Uuid> = actionable.iter().map(|todo| todo.id).collect();
+        assert_eq!(ids, [blocker.id]);
+
+        // Completing the blocked item is refused while its blocker is open, unless forced
+        let update = |id, change: serde_json::Value, force| {
+            let params = CascadeParams {
+                cascade: false,
+                force,
+            };
+            update_todo(
+                web::Path::from(id),
+                web::Query(params),
+                web::Json(serde_json::from_value(change).unwrap()),
+                owner.clone(),
+                Actor(owner.username.clone()),
+                data.clone(),
+            )
+        };
+        let response = update(blocked.id, json!({"completed": true}), false)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = update(blocked.id, json!({"completed": true}), true)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn links_closing_a_cycle_are_refused() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let owner = user("owner");
+        let first = owned_by(&owner);
+        let second = TodoItem {
+            blocked_by: [first.id].iter().copied().collect(),
+            ..owned_by(&owner)
+        };
+        data.commit(&[
+            Event::UserCreated(owner.clone()),
+            Event::TodoCreated(first.clone()),
+            Event::TodoCreated(second.clone()),
+        ])
+        .unwrap();
+
+        let params = CascadeParams {
+            cascade: false,
+            force: false,
+        };
+        let change = serde_json::from_value(json!({"blocked_by": [second.id]})).unwrap();
+        let response = update_todo(
+            web::Path::from(first.id),
+            web::Query(params),
+            web::Json(change),
+            owner.clone(),
+            Actor(owner.username.clone()),
+            data.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let first = data.store.get(first.id).unwrap().unwrap();
+        assert!(first.blocked_by.is_empty());
+    }
+}
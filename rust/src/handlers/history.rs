@@ -0,0 +1,130 @@
+// HTTP handlers for the revision history of a to-do item
+use actix_web::{web, HttpResponse};
+use uuid::Uuid;
+
+use super::todos::{find_editable, not_found};
+use super::Actor;
+use crate::history;
+use crate::models::{RevisionAction, User};
+use crate::sharing;
+use crate::state::AppState;
+use crate::store::{Event, StoreError};
+
+// GET /todos/{id}/history: every recorded revision of the item, oldest first. The history of a
+// purged item stays readable by whoever could see it last.
+pub async fn get_history(
+    path: web::Path<Uuid>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let revisions = data.store.revisions(*path)?;
+    let todo = match data.store.get(*path)? {
+        Some(todo) => todo,
+        None => match revisions.last() {
+            Some(revision) => revision.snapshot.clone(),
+            None => return Ok(not_found()),
+        },
+    };
+    if sharing::todo_role(data.store.as_ref(), &user, &todo)?.is_none() {
+        return Ok(not_found());
+    }
+    Ok(HttpResponse::Ok().json(revisions))
+}
+
+// POST /todos/{id}/history/{revision}/revert: restore the item to the state it had at that revision
+pub async fn revert_todo(
+    path: web::Path<(Uuid, u32)>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let (id, number) = path.into_inner();
+    let _guard = data.writes.lock().unwrap();
+    let before = match find_editable(&data, &user, id)? {
+        Ok(todo) => todo,
+        Err(rejection) => return Ok(rejection),
+    };
+    let revisions = data.store.revisions(id)?;
+    let revision = match revisions.iter().find(|revision| revision.number == number) {
+        Some(revision) => revision,
+        None => return Ok(HttpResponse::NotFound().body("Revision not found")),
+    };
+
+    let mut todo = before.clone();
+    todo.restore_from(&revision.snapshot);
+    let mut events = vec![Event::TodoUpdated(todo.clone())];
+    events.extend(history::record(
+        data.store.as_ref(),
+        Some(&before),
+        &todo,
+        RevisionAction::Reverted,
+        &actor.0,
+    )?);
+    data.commit(&events)?;
+    Ok(HttpResponse::Ok().json(todo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::todos::{add_todo, update_todo};
+    use crate::models::{CascadeParams, TodoItem};
+    use crate::testing::{app_state, user, TempDir};
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn reverting_restores_the_contents_but_not_completion() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let owner = user("owner");
+        data.commit(&[Event::UserCreated(owner.clone())]).unwrap();
+        let actor = || Actor(owner.username.clone());
+
+        let item = serde_json::from_value(json!({"title": "milk", "completed": true})).unwrap();
+        let response = add_todo(web::Json(item), owner.clone(), actor(), data.clone())
+            .await
+            .unwrap();
+        let added: TodoItem =
+            serde_json::from_slice(&to_bytes(response.into_body()).await.unwrap()).unwrap();
+        let params = CascadeParams {
+            cascade: false,
+            force: false,
+        };
+        let change = serde_json::from_value(json!({"title": "eggs", "completed": false})).unwrap();
+        update_todo(
+            web::Path::from(added.id),
+            web::Query(params),
+            web::Json(change),
+            owner.clone(),
+            actor(),
+            data.clone(),
+        )
+        .await
+        .unwrap();
+
+        let response = revert_todo(
+            web::Path::from((added.id, 1)),
+            owner.clone(),
+            actor(),
+            data.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let reverted = data.store.get(added.id).unwrap().unwrap();
+        assert_eq!(reverted.title, "milk");
+        assert!(!reverted.completed);
+
+        let response = revert_todo(
+            web::Path::from((added.id, 99)),
+            owner.clone(),
+            actor(),
+            data.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
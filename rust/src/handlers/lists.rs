@@ -0,0 +1,357 @@
+// HTTP handlers for lists (projects): named groups of items with their own order, color and archive flag
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::todos::{create_todo, fetch_page, list_query, render_page};
+use super::Actor;
+use crate::history;
+use crate::models::{
+    normalize_color, CascadeParams, CreateTodoItem, CreateTodoList, ListTodosParams, ListView,
+    ListsParams, RevisionAction, Role, TodoList, UpdateTodoList, User,
+};
+use crate::sharing;
+use crate::state::AppState;
+use crate::store::{Event, StoreError, StoreResult, TodoQuery};
+
+// Every list of `user`, in order
+fn owned_lists(data: &AppState, user: &User) -> StoreResult<Vec<TodoList>> {
+    Ok(data
+        .store
+        .todo_lists()?
+        .into_iter()
+        .filter(|list| user.owns(list.owner_id))
+        .collect())
+}
+
+// Every list shared with `user`, in the order they joined them
+fn shared_lists(data: &AppState, user: &User) -> StoreResult<Vec<ListView>> {
+    let mut memberships = data.store.memberships(user.id)?;
+    memberships.sort_by_key(|member| member.added_at);
+    let mut lists = Vec::new();
+    for member in memberships {
+        if let Some(list) = data.store.todo_list(member.list_id)? {
+            lists.push(ListView {
+                list,
+                role: member.role,
+            });
+        }
+    }
+    Ok(lists)
+}
+
+// Look up a list `user` has access to, with their role on it
+pub(super) fn find_list(data: &AppState, user: &User, id: Uuid) -> StoreResult<Option<ListView>> {
+    let list = match data.store.todo_list(id)? {
+        Some(list) => list,
+        None => return Ok(None),
+    };
+    Ok(sharing::list_role(data.store.as_ref(), user, &list)?.map(|role| ListView { list, role }))
+}
+
+// Look up a list of `user` for a change only its owner may make, or the response refusing it: 404
+// when they have no access to it, 403 when it is only shared with them
+pub(super) fn find_owned_list(
+    data: &AppState,
+    user: &User,
+    id: Uuid,
+) -> Result<Result<TodoList, HttpResponse>, StoreError> {
+    Ok(match find_list(data, user, id)? {
+        Some(view) if view.role == Role::Owner => Ok(view.list),
+        Some(_) => Err(HttpResponse::Forbidden().body("Only the owner of a list can do this")),
+        None => Err(HttpResponse::NotFound().body("List not found")),
+    })
+}
+
+// Events saving `list` at `position` among `lists`, those of its owner (at its current place, or last
+// for a new list, when there is none). The others are renumbered around it so that positions run from
+// 0 without gaps.
+fn place(lists: &[TodoList], mut list: TodoList, position: Option<u32>) -> (TodoList, Vec<Event>) {
+    let current = lists.iter().position(|other| other.id == list.id);
+    let others: Vec<&TodoList> = lists.iter().filter(|other| other.id != list.id).collect();
+    let index = position
+        .map(|position| position as usize)
+        .or(current)
+        .unwrap_or(others.len())
+        .min(others.len());
+    list.position = index as u32;
+    let mut events = vec![match current {
+        Some(_) => Event::ListUpdated(list.clone()),
+        None => Event::ListCreated(list.clone()),
+    }];
+    for (n, other) in others.into_iter().enumerate() {
+        let position = if n < index { n } else { n + 1 } as u32;
+        if other.position != position {
+            // Bookkeeping only: no new update time
+            let mut other = other.clone();
+            other.position = position;
+            events.push(Event::ListUpdated(other));
+        }
+    }
+    (list, events)
+}
+
+// GET /lists: every list of the account in order, then those shared with it, optionally only the
+// archived or only the active ones
+pub async fn get_lists(
+    params: web::Query<ListsParams>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let owned = owned_lists(&data, &user)?.into_iter().map(|list| ListView {
+        list,
+        role: Role::Owner,
+    });
+    let lists: Vec<ListView> = owned
+        .chain(shared_lists(&data, &user)?)
+        .filter(|ListView { list, .. }| {
+            params
+                .archived
+                .is_none_or(|archived| list.archived == archived)
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(lists))
+}
+
+// POST /lists: add a list, last unless a position is given
+pub async fn add_list(
+    item: web::Json<CreateTodoList>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    if item
+        .color
+        .as_deref()
+        .is_some_and(|color| normalize_color(color).is_none())
+    {
+        return Ok(HttpResponse::BadRequest().body("Invalid color"));
+    }
+    let _guard = data.writes.lock().unwrap();
+    let lists = owned_lists(&data, &user)?;
+    let new_list = TodoList::new(&item, lists.len() as u32, user.id);
+    let (list, events) = place(&lists, new_list, item.position);
+    data.commit(&events)?;
+    Ok(HttpResponse::Ok().json(list))
+}
+
+// GET /lists/{id}
+pub async fn get_list(
+    path: web::Path<Uuid>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    match find_list(&data, &user, *path)? {
+        Some(view) => Ok(HttpResponse::Ok().json(view)),
+        None => Ok(HttpResponse::NotFound().body("List not found")),
+    }
+}
+
+// PUT /lists/{id}: rename, recolor, archive or move a list. Only its owner may.
+pub async fn update_list(
+    path: web::Path<Uuid>,
+    item: web::Json<UpdateTodoList>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    if let Some(Some(color)) = &item.color {
+        if normalize_color(color).is_none() {
+            return Ok(HttpResponse::BadRequest().body("Invalid color"));
+        }
+    }
+    let _guard = data.writes.lock().unwrap();
+    let mut list = match find_owned_list(&data, &user, *path)? {
+        Ok(list) => list,
+        Err(rejection) => return Ok(rejection),
+    };
+    let lists = owned_lists(&data, &user)?;
+    list.apply(&item);
+    let (list, events) = place(&lists, list, item.position);
+    data.commit(&events)?;
+    Ok(HttpResponse::Ok().json(list))
+}
+
+// DELETE /lists/{id}: remove a list. Its items are moved out of it, into no list, or with
+// `cascade=true` moved to the trash along with it. Items already in the trash are moved out of it
+// as well, so that they are restored into no list. Only its owner may; its members, pending
+// invitations and every share link to it go with it.
+pub async fn delete_list(
+    path: web::Path<Uuid>,
+    params: web::Query<CascadeParams>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    if let Err(rejection) = find_owned_list(&data, &user, *path)? {
+        return Ok(rejection);
+    }
+    let query = TodoQuery {
+        list: Some(Some(*path)),
+        ..TodoQuery::default()
+    };
+    let trashed = TodoQuery {
+        in_trash: true,
+        ..query.clone()
+    };
+    let now = Utc::now();
+    let mut events = Vec::new();
+    for before in data.store.list(&trashed)? {
+        let mut todo = before.clone();
+        todo.list_id = None;
+        events.push(Event::TodoUpdated(todo.clone()));
+        events.extend(history::record(
+            data.store.as_ref(),
+            Some(&before),
+            &todo,
+            RevisionAction::Updated,
+            &actor.0,
+        )?);
+    }
+    for before in data.store.list(&query)? {
+        let mut todo = before.clone();
+        let action = if params.cascade {
+            todo.deleted_at = Some(now);
+            RevisionAction::Deleted
+        } else {
+            todo.list_id = None;
+            todo.updated_at = Some(now);
+            RevisionAction::Updated
+        };
+        events.push(Event::TodoUpdated(todo.clone()));
+        events.extend(history::record(
+            data.store.as_ref(),
+            Some(&before),
+            &todo,
+            action,
+            &actor.0,
+        )?);
+    }
+    for member in data.store.members(*path)? {
+        events.push(Event::MemberRemoved {
+            list_id: *path,
+            user_id: member.user_id,
+        });
+    }
+    for invitation in data.store.list_invitations(*path)? {
+        events.push(Event::InvitationDeleted { id: invitation.id });
+    }
+    for link in data.store.list_share_links(*path)? {
+        events.push(Event::ShareLinkDeleted { id: link.id });
+    }
+    events.push(Event::ListDeleted { id: *path });
+    // Close the gap the list leaves behind
+    let remaining = owned_lists(&data, &user)?
+        .into_iter()
+        .filter(|list| list.id != *path);
+    for (position, mut list) in remaining.enumerate() {
+        if list.position != position as u32 {
+            list.position = position as u32;
+            events.push(Event::ListUpdated(list));
+        }
+    }
+    data.commit(&events)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// GET /lists/{id}/todos: one page of the items in a list. Accepts the same query string as GET /todos.
+pub async fn get_list_todos(
+    path: web::Path<Uuid>,
+    params: web::Query<ListTodosParams>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    if find_list(&data, &user, *path)?.is_none() {
+        return Ok(HttpResponse::NotFound().body("List not found"));
+    }
+    let visible = sharing::visibility(data.store.as_ref(), &user, Role::Viewer)?;
+    let mut query = match list_query(&params, Some(visible)) {
+        Ok(query) => query,
+        Err(err) => return Ok(err.response()),
+    };
+    query.list = Some(Some(*path));
+    let page = fetch_page(&data, query, params.limit)?;
+    let page = render_page(&data, page, params.render)?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+// POST /lists/{id}/todos: add an item to a list, returning the new item. Viewers of a shared list
+// may not.
+pub async fn add_list_todo(
+    path: web::Path<Uuid>,
+    item: web::Json<CreateTodoItem>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    if find_list(&data, &user, *path)?.is_none() {
+        return Ok(HttpResponse::NotFound().body("List not found"));
+    }
+    let mut item = item.into_inner();
+    item.list_id = Some(*path);
+    match create_todo(&data, &item, &user, &actor)? {
+        Ok(todo) => Ok(HttpResponse::Created().json(todo)),
+        Err(rejection) => Ok(rejection),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ShareLink;
+    use crate::testing::{app_state, list, todo, user, TempDir};
+    use actix_web::http::StatusCode;
+    use chrono::Duration;
+
+    #[actix_web::test]
+    async fn deleting_a_list_leaves_nothing_pointing_at_it() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let (owner, former) = (user("owner"), user("former"));
+        let list = list(&owner);
+        let item = |deleted| {
+            let mut item = todo(Some(list.id));
+            item.owner_id = Some(owner.id);
+            item.deleted_at = deleted;
+            item
+        };
+        let (live, trashed) = (item(None), item(Some(Utc::now())));
+        // A link made while someone else still owned the list
+        let link = |maker: &User| ShareLink {
+            id: Uuid::new_v4(),
+            owner_id: maker.id,
+            list_id: Some(list.id),
+            todo_id: None,
+            created_at: Utc::now(),
+            expires_at: Utc::now() + Duration::days(1),
+        };
+        data.commit(&[
+            Event::UserCreated(owner.clone()),
+            Event::UserCreated(former.clone()),
+            Event::ListCreated(list.clone()),
+            Event::TodoCreated(live.clone()),
+            Event::TodoCreated(trashed.clone()),
+            Event::ShareLinkCreated(link(&owner)),
+            Event::ShareLinkCreated(link(&former)),
+        ])
+        .unwrap();
+
+        let params = CascadeParams {
+            cascade: false,
+            force: false,
+        };
+        let actor = Actor(owner.username.clone());
+        let path = web::Path::from(list.id);
+        let response = delete_list(path, web::Query(params), owner, actor, data.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let stored = |id| data.store.get(id).unwrap().unwrap();
+        for id in [live.id, trashed.id] {
+            assert_eq!(stored(id).list_id, None);
+        }
+        assert!(stored(trashed.id).deleted_at.is_some());
+        assert!(data.store.list_share_links(list.id).unwrap().is_empty());
+        assert!(data.store.share_links(former.id).unwrap().is_empty());
+    }
+}
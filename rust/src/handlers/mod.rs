@@ -0,0 +1,91 @@
+// HTTP handlers, grouped by the resource they serve
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use std::future::{ready, Ready};
+
+use crate::auth;
+use crate::models::User;
+
+mod accounts;
+mod agenda;
+mod attachments;
+mod checklist;
+mod comments;
+mod dependencies;
+mod history;
+mod lists;
+mod search;
+mod share_links;
+mod sharing;
+mod smart_lists;
+mod tags;
+mod todos;
+mod tokens;
+mod trash;
+mod two_factor;
+
+pub use accounts::{get_me, login, logout, register};
+pub use agenda::{get_due_today, get_overdue, get_upcoming};
+pub use attachments::{
+    delete_attachment, download_attachment, get_attachments, upload_attachments,
+};
+pub use checklist::update_checklist_item;
+pub use comments::{add_comment, delete_comment, get_comments, update_comment};
+pub use dependencies::{get_actionable, get_prerequisites};
+pub use history::{get_history, revert_todo};
+pub use lists::{
+    add_list, add_list_todo, delete_list, get_list, get_list_todos, get_lists, update_list,
+};
+pub use search::search_todos;
+pub use share_links::{create_share_link, get_share_links, get_shared, revoke_share_link};
+pub use sharing::{
+    accept_invitation, cancel_invitation, change_member_role, decline_invitation, get_audit_log,
+    get_invitations, get_list_invitations, get_members, invite, remove_member,
+};
+pub use smart_lists::{
+    add_smart_list, delete_smart_list, get_smart_list, get_smart_list_todos, get_smart_lists,
+    update_smart_list,
+};
+pub use tags::{delete_tag, get_tags, merge_tag, rename_tag};
+pub use todos::{add_todo, delete_todo, get_todo_tree, get_todos, move_todo, update_todo};
+pub use tokens::{create_token, get_tokens, revoke_token};
+pub use trash::{get_trash, purge_todo, restore_todo};
+pub use two_factor::{confirm_totp, disable_totp, enroll_totp, get_totp, renew_recovery_codes};
+
+// A 429 response saying `why`, asking the client to come back at `until`
+fn try_again_at(until: DateTime<Utc>, why: &str) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header((RETRY_AFTER, (until - Utc::now()).num_seconds().max(1)))
+        .body(why.to_string())
+}
+
+// The signed-in account, put into the request by `auth::authenticate`
+impl FromRequest for User {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(req.extensions().get::<User>().cloned().ok_or_else(|| {
+            InternalError::from_response("not signed in", auth::unauthorized()).into()
+        }))
+    }
+}
+
+// Name recorded as the author of a change: the username of the signed-in account
+pub struct Actor(pub String);
+
+impl FromRequest for Actor {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let name = req
+            .extensions()
+            .get::<User>()
+            .map_or_else(|| "anonymous".to_string(), |user| user.username.clone());
+        ready(Ok(Actor(name)))
+    }
+}
@@ -0,0 +1,122 @@
+// HTTP handler for full-text search over the live to-do items
+use actix_web::{web, HttpResponse};
+
+use crate::models::{Role, SearchParams, SearchResult, TodoView, User};
+use crate::sharing;
+use crate::state::AppState;
+use crate::store::StoreError;
+
+// Number of results returned when the client does not ask for a number, and the most it may ask for
+const DEFAULT_RESULTS: usize = 20;
+const MAX_RESULTS: usize = 100;
+
+// GET /todos/search?q=: items matching every word of `q`, most relevant first
+pub async fn search_todos(
+    params: web::Query<SearchParams>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_RESULTS)
+        .clamp(1, MAX_RESULTS);
+    // Only the parts of the index holding the signed-in account's items and the lists shared with
+    // it are searched
+    let visible = sharing::visibility(data.store.as_ref(), &user, Role::Viewer)?;
+    let mut owners = vec![Some(user.id)];
+    for list_id in &visible.lists {
+        if let Some(list) = data.store.todo_list(*list_id)? {
+            owners.push(list.owner_id);
+        }
+    }
+    owners.sort_unstable();
+    owners.dedup();
+    let hits = data
+        .search
+        .read()
+        .unwrap()
+        .search(&params.q, limit, &visible, &owners);
+    let ids: Vec<_> = hits.iter().map(|hit| hit.id).collect();
+    let comment_counts = data.store.comment_counts(&ids)?;
+    let mut results = Vec::with_capacity(hits.len());
+    for hit in hits {
+        if let Some(todo) = data.store.get(hit.id)? {
+            let comment_count = comment_counts.get(&todo.id).copied().unwrap_or(0);
+            results.push(SearchResult {
+                score: hit.score,
+                todo: TodoView::new(todo, params.render, comment_count),
+            });
+        }
+    }
+    Ok(HttpResponse::Ok().json(results))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ListMember, TodoItem};
+    use crate::store::Event;
+    use crate::testing::{app_state, list, todo, user, TempDir};
+    use actix_web::body::to_bytes;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn milk(owner: &User, list_id: Option<Uuid>) -> TodoItem {
+        TodoItem {
+            owner_id: Some(owner.id),
+            ..todo(list_id)
+        }
+    }
+
+    #[actix_web::test]
+    async fn search_finds_own_items_and_shared_lists_only() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let (me, other, stranger) = (user("me"), user("other"), user("stranger"));
+        let shared = list(&other);
+        let (mine, in_shared, private, strangers) = (
+            milk(&me, None),
+            milk(&other, Some(shared.id)),
+            milk(&other, None),
+            milk(&stranger, None),
+        );
+        let member = ListMember {
+            list_id: shared.id,
+            user_id: me.id,
+            role: Role::Viewer,
+            added_at: Utc::now(),
+        };
+        let mut events = vec![
+            Event::UserCreated(me.clone()),
+            Event::UserCreated(other),
+            Event::UserCreated(stranger),
+            Event::ListCreated(shared),
+            Event::MemberAdded(member),
+        ];
+        for item in [&mine, &in_shared, &private, &strangers] {
+            events.push(Event::TodoCreated(item.clone()));
+        }
+        data.commit(&events).unwrap();
+
+        let params = SearchParams {
+            q: "milk".to_string(),
+            limit: None,
+            render: None,
+        };
+        let response = search_todos(web::Query(params), me, data.clone())
+            .await
+            .unwrap();
+        let results: serde_json::Value =
+            serde_json::from_slice(&to_bytes(response.into_body()).await.unwrap()).unwrap();
+        let mut found: Vec<String> = results
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["id"].as_str().unwrap().to_string())
+            .collect();
+        found.sort_unstable();
+        let mut expected = vec![mine.id.to_string(), in_shared.id.to_string()];
+        expected.sort_unstable();
+        assert_eq!(found, expected);
+    }
+}
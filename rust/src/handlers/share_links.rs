@@ -0,0 +1,234 @@
+// HTTP handlers for share links: making, listing and revoking them, and opening one without an
+// account
+use actix_web::{web, HttpResponse};
+use chrono::{Duration, DurationRound, Utc};
+use uuid::Uuid;
+
+use super::todos::{fetch_page, find_live, list_query};
+use crate::models::{
+    ListTodosParams, NewShareLink, Role, ShareLink, ShareLinkView, SharedTodo, TodoPage, User,
+};
+use crate::sharing;
+use crate::state::AppState;
+use crate::store::{Event, StoreError};
+use crate::tree::Children;
+
+// How long a share link works when its payload does not say
+const DEFAULT_LIFETIME_HOURS: u32 = 24 * 7;
+
+// Longest a share link may work
+const MAX_LIFETIME_HOURS: u32 = 24 * 365;
+
+fn view(data: &AppState, link: ShareLink) -> ShareLinkView {
+    let path = format!("/shared/{}", data.share_signer.token(&link));
+    ShareLinkView { link, path }
+}
+
+// Whether `user` owns what `link` would show. Only owners decide who else sees their items.
+fn owns_target(data: &AppState, user: &User, link: &NewShareLink) -> Result<bool, StoreError> {
+    let role = match (link.list_id, link.todo_id) {
+        (Some(list_id), None) => match data.store.todo_list(list_id)? {
+            Some(list) => sharing::list_role(data.store.as_ref(), user, &list)?,
+            None => None,
+        },
+        (None, Some(todo_id)) => match find_live(data, user, todo_id)? {
+            Some(todo) => sharing::todo_role(data.store.as_ref(), user, &todo)?,
+            None => None,
+        },
+        _ => None,
+    };
+    Ok(role == Some(Role::Owner))
+}
+
+// POST /share-links: make a link showing a list or an item to anyone who has it, returning it with
+// its path
+pub async fn create_share_link(
+    item: web::Json<NewShareLink>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    if item.list_id.is_some() == item.todo_id.is_some() {
+        return Ok(HttpResponse::BadRequest().body("Give either a list_id or a todo_id"));
+    }
+    let hours = item.expires_in_hours.unwrap_or(DEFAULT_LIFETIME_HOURS);
+    if hours == 0 || hours > MAX_LIFETIME_HOURS {
+        return Ok(HttpResponse::BadRequest().body(format!(
+            "expires_in_hours must be between 1 and {}",
+            MAX_LIFETIME_HOURS
+        )));
+    }
+    let _guard = data.writes.lock().unwrap();
+    if !owns_target(&data, &user, &item)? {
+        return Ok(HttpResponse::NotFound().body("Only lists and todos you own can be shared"));
+    }
+    // Tokens carry the expiry in whole seconds
+    let now = Utc::now().duration_trunc(Duration::seconds(1)).unwrap();
+    let link = ShareLink {
+        id: Uuid::new_v4(),
+        owner_id: user.id,
+        list_id: item.list_id,
+        todo_id: item.todo_id,
+        created_at: now,
+        expires_at: now + Duration::hours(hours.into()),
+    };
+    data.commit(&[Event::ShareLinkCreated(link.clone())])?;
+    Ok(HttpResponse::Ok().json(view(&data, link)))
+}
+
+// GET /share-links: the share links of the signed-in account, oldest first, expired ones included
+pub async fn get_share_links(
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let links: Vec<ShareLinkView> = data
+        .store
+        .share_links(user.id)?
+        .into_iter()
+        .map(|link| view(&data, link))
+        .collect();
+    Ok(HttpResponse::Ok().json(links))
+}
+
+// DELETE /share-links/{id}: revoke a share link; its token stops working right away
+pub async fn revoke_share_link(
+    path: web::Path<Uuid>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    match data.store.share_link(*path)? {
+        Some(link) if link.owner_id == user.id => {
+            data.commit(&[Event::ShareLinkDeleted { id: link.id }])?;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        _ => Ok(HttpResponse::NotFound().body("Share link not found")),
+    }
+}
+
+// GET /shared/{token}: what a share link shows, in the shape of GET /todos without the fields only
+// the owner needs. A list is paged through with the same query string as GET /todos; an item comes
+// on one page with its subtasks. Needs no account.
+pub async fn get_shared(
+    path: web::Path<String>,
+    params: web::Query<ListTodosParams>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let not_found = || HttpResponse::NotFound().body("Share link not found");
+    let (id, expires_at) = match data.share_signer.verify(&path) {
+        Some(signed) => signed,
+        None => return Ok(not_found()),
+    };
+    if expires_at <= Utc::now() {
+        return Ok(HttpResponse::Gone().body("Share link has expired"));
+    }
+    let link = match data.store.share_link(id)? {
+        Some(link) if link.expires_at == expires_at => link,
+        _ => return Ok(not_found()),
+    };
+    let owned = |owner_id: Option<Uuid>| owner_id == Some(link.owner_id);
+    let page = match (link.list_id, link.todo_id) {
+        (Some(list_id), _) => {
+            if !data
+                .store
+                .todo_list(list_id)?
+                .is_some_and(|list| owned(list.owner_id))
+            {
+                return Ok(not_found());
+            }
+            // The link stands in for the owner, so nothing is hidden
+            let mut query = match list_query(&params, None) {
+                Ok(query) => query,
+                Err(err) => return Ok(err.response()),
+            };
+            query.list = Some(Some(list_id));
+            fetch_page(&data, query, params.limit)?
+        }
+        (None, Some(todo_id)) => {
+            let todo = match data.store.get(todo_id)? {
+                Some(todo) if todo.deleted_at.is_none() && owned(todo.owner_id) => todo,
+                _ => return Ok(not_found()),
+            };
+            let mut items = vec![todo];
+            items.extend(
+                Children::below(data.store.as_ref(), todo_id, false)?
+                    .descendants(todo_id)
+                    .into_iter()
+                    .filter(|todo| owned(todo.owner_id)),
+            );
+            TodoPage {
+                items,
+                next_cursor: None,
+            }
+        }
+        (None, None) => return Ok(not_found()),
+    };
+    Ok(HttpResponse::Ok().json(TodoPage {
+        items: page
+            .items
+            .into_iter()
+            .map(|todo| SharedTodo::new(todo, params.render))
+            .collect(),
+        next_cursor: page.next_cursor,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app_state, list, user, TempDir};
+    use actix_web::http::StatusCode;
+    use chrono::DateTime;
+
+    // A link to a list of `owner`, made an hour ago and expiring at `expires_at`
+    fn share(data: &AppState, owner: &User, expires_at: DateTime<Utc>) -> ShareLink {
+        let list = list(owner);
+        let link = ShareLink {
+            id: Uuid::new_v4(),
+            owner_id: owner.id,
+            list_id: Some(list.id),
+            todo_id: None,
+            created_at: expires_at - Duration::hours(1),
+            expires_at: expires_at.duration_trunc(Duration::seconds(1)).unwrap(),
+        };
+        data.commit(&[
+            Event::ListCreated(list),
+            Event::ShareLinkCreated(link.clone()),
+        ])
+        .unwrap();
+        link
+    }
+
+    async fn open(data: &web::Data<AppState>, link: &ShareLink) -> StatusCode {
+        let token = data.share_signer.token(link);
+        let params = serde_json::from_value(serde_json::json!({})).unwrap();
+        get_shared(web::Path::from(token), web::Query(params), data.clone())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[actix_web::test]
+    async fn an_expired_link_is_gone() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let owner = user("owner");
+        data.commit(&[Event::UserCreated(owner.clone())]).unwrap();
+        let link = share(&data, &owner, Utc::now() - Duration::seconds(1));
+        assert_eq!(open(&data, &link).await, StatusCode::GONE);
+    }
+
+    #[actix_web::test]
+    async fn a_revoked_link_is_not_found() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let owner = user("owner");
+        data.commit(&[Event::UserCreated(owner.clone())]).unwrap();
+        let link = share(&data, &owner, Utc::now() + Duration::hours(1));
+        assert_eq!(open(&data, &link).await, StatusCode::OK);
+
+        let path = web::Path::from(link.id);
+        let revoked = revoke_share_link(path, owner, data.clone()).await.unwrap();
+        assert_eq!(revoked.status(), StatusCode::NO_CONTENT);
+        assert_eq!(open(&data, &link).await, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,520 @@
+// HTTP handlers for sharing lists: members, invitations and the audit log of who had access
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::lists::{find_list, find_owned_list};
+use super::Actor;
+use crate::models::{
+    normalize_username, AccessAction, AccessChange, AccessEntry, ChangeRole, Invitation,
+    InvitationView, Invite, ListMember, ListView, Role, TodoList, User,
+};
+use crate::state::AppState;
+use crate::store::{Event, StoreError, StoreResult};
+
+// Username of the account `id`, or the id itself if there is no such account
+fn username(data: &AppState, id: Uuid) -> StoreResult<String> {
+    Ok(data
+        .store
+        .user(id)?
+        .map_or_else(|| id.to_string(), |user| user.username))
+}
+
+// Audit log entry for `action` by `actor` on the access of `username` to `list_id`
+fn record(
+    list_id: Uuid,
+    actor: &Actor,
+    action: AccessAction,
+    username: String,
+    role: Option<Role>,
+) -> Event {
+    Event::AccessRecorded(AccessChange {
+        id: Uuid::new_v4(),
+        list_id,
+        actor: actor.0.clone(),
+        action,
+        username,
+        role,
+        at: Utc::now(),
+    })
+}
+
+// 400 response for sharing a list with the owner role, which stays with the account that made it
+fn owner_role() -> HttpResponse {
+    HttpResponse::BadRequest().body("Lists can only be shared with the editor or viewer role")
+}
+
+fn invitation_view(data: &AppState, invitation: &Invitation) -> StoreResult<InvitationView> {
+    let list_name = data
+        .store
+        .todo_list(invitation.list_id)?
+        .map(|list| list.name)
+        .unwrap_or_default();
+    Ok(InvitationView {
+        id: invitation.id,
+        list_id: invitation.list_id,
+        list_name,
+        username: username(data, invitation.user_id)?,
+        role: invitation.role,
+        invited_by: username(data, invitation.invited_by)?,
+        created_at: invitation.created_at,
+    })
+}
+
+// Who has access to `list`: its owner, then its members in the order they joined
+fn access_entries(data: &AppState, list: &TodoList) -> StoreResult<Vec<AccessEntry>> {
+    let mut entries = Vec::new();
+    if let Some(owner_id) = list.owner_id {
+        entries.push(AccessEntry {
+            user_id: owner_id,
+            username: username(data, owner_id)?,
+            role: Role::Owner,
+            since: list.created_at,
+        });
+    }
+    for member in data.store.members(list.id)? {
+        entries.push(AccessEntry {
+            user_id: member.user_id,
+            username: username(data, member.user_id)?,
+            role: member.role,
+            since: member.added_at,
+        });
+    }
+    Ok(entries)
+}
+
+// GET /lists/{id}/members: everyone with access to a list, owner first. Members see each other.
+pub async fn get_members(
+    path: web::Path<Uuid>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    match find_list(&data, &user, *path)? {
+        Some(view) => Ok(HttpResponse::Ok().json(access_entries(&data, &view.list)?)),
+        None => Ok(HttpResponse::NotFound().body("List not found")),
+    }
+}
+
+// PUT /lists/{id}/members/{user_id}: give a member another role. Only the owner may.
+pub async fn change_member_role(
+    path: web::Path<(Uuid, Uuid)>,
+    item: web::Json<ChangeRole>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let (list_id, user_id) = path.into_inner();
+    if item.role == Role::Owner {
+        return Ok(owner_role());
+    }
+    let _guard = data.writes.lock().unwrap();
+    if let Err(rejection) = find_owned_list(&data, &user, list_id)? {
+        return Ok(rejection);
+    }
+    let mut member = match data
+        .store
+        .members(list_id)?
+        .into_iter()
+        .find(|member| member.user_id == user_id)
+    {
+        Some(member) => member,
+        None => return Ok(HttpResponse::NotFound().body("Member not found")),
+    };
+    let username = username(&data, user_id)?;
+    if member.role != item.role {
+        member.role = item.role;
+        data.commit(&[
+            Event::MemberUpdated(member.clone()),
+            record(
+                list_id,
+                &actor,
+                AccessAction::RoleChanged,
+                username.clone(),
+                Some(member.role),
+            ),
+        ])?;
+    }
+    Ok(HttpResponse::Ok().json(AccessEntry {
+        user_id,
+        username,
+        role: member.role,
+        since: member.added_at,
+    }))
+}
+
+// DELETE /lists/{id}/members/{user_id}: take a member's access away, which only the owner may, or
+// leave a list shared with the signed-in account
+pub async fn remove_member(
+    path: web::Path<(Uuid, Uuid)>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let (list_id, user_id) = path.into_inner();
+    let _guard = data.writes.lock().unwrap();
+    let view = match find_list(&data, &user, list_id)? {
+        Some(view) => view,
+        None => return Ok(HttpResponse::NotFound().body("List not found")),
+    };
+    let action = if user_id == user.id {
+        if view.role == Role::Owner {
+            return Ok(HttpResponse::BadRequest().body("The owner cannot leave their own list"));
+        }
+        AccessAction::Left
+    } else if view.role == Role::Owner {
+        AccessAction::Removed
+    } else {
+        return Ok(HttpResponse::Forbidden().body("Only the owner of a list can do this"));
+    };
+    if !data
+        .store
+        .members(list_id)?
+        .iter()
+        .any(|member| member.user_id == user_id)
+    {
+        return Ok(HttpResponse::NotFound().body("Member not found"));
+    }
+    data.commit(&[
+        Event::MemberRemoved { list_id, user_id },
+        record(list_id, &actor, action, username(&data, user_id)?, None),
+    ])?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// GET /lists/{id}/invitations: the open invitations to a list, oldest first. Only the owner may.
+pub async fn get_list_invitations(
+    path: web::Path<Uuid>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    if let Err(rejection) = find_owned_list(&data, &user, *path)? {
+        return Ok(rejection);
+    }
+    let invitations = data
+        .store
+        .list_invitations(*path)?
+        .iter()
+        .map(|invitation| invitation_view(&data, invitation))
+        .collect::<StoreResult<Vec<_>>>()?;
+    Ok(HttpResponse::Ok().json(invitations))
+}
+
+// POST /lists/{id}/invitations: invite an account to a list with the editor or viewer role. The
+// list is shared once they accept. Only the owner may invite.
+pub async fn invite(
+    path: web::Path<Uuid>,
+    item: web::Json<Invite>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    if item.role == Role::Owner {
+        return Ok(owner_role());
+    }
+    let _guard = data.writes.lock().unwrap();
+    let list = match find_owned_list(&data, &user, *path)? {
+        Ok(list) => list,
+        Err(rejection) => return Ok(rejection),
+    };
+    let invitee = match normalize_username(&item.username)
+        .map(|username| data.store.user_by_name(&username))
+        .transpose()?
+        .flatten()
+    {
+        Some(invitee) => invitee,
+        None => return Ok(HttpResponse::NotFound().body("User not found")),
+    };
+    if invitee.owns(list.owner_id) {
+        return Ok(HttpResponse::BadRequest().body("The owner already has access to the list"));
+    }
+    if data
+        .store
+        .members(list.id)?
+        .iter()
+        .any(|member| member.user_id == invitee.id)
+    {
+        return Ok(HttpResponse::Conflict().body("The list is already shared with this user"));
+    }
+    if data
+        .store
+        .list_invitations(list.id)?
+        .iter()
+        .any(|invitation| invitation.user_id == invitee.id)
+    {
+        return Ok(HttpResponse::Conflict().body("This user is already invited"));
+    }
+    let invitation = Invitation {
+        id: Uuid::new_v4(),
+        list_id: list.id,
+        user_id: invitee.id,
+        role: item.role,
+        invited_by: user.id,
+        created_at: Utc::now(),
+    };
+    data.commit(&[
+        Event::InvitationCreated(invitation.clone()),
+        record(
+            list.id,
+            &actor,
+            AccessAction::Invited,
+            invitee.username,
+            Some(invitation.role),
+        ),
+    ])?;
+    Ok(HttpResponse::Ok().json(invitation_view(&data, &invitation)?))
+}
+
+// DELETE /lists/{id}/invitations/{invitation_id}: take an invitation back. Only the owner may.
+pub async fn cancel_invitation(
+    path: web::Path<(Uuid, Uuid)>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let (list_id, invitation_id) = path.into_inner();
+    let _guard = data.writes.lock().unwrap();
+    if let Err(rejection) = find_owned_list(&data, &user, list_id)? {
+        return Ok(rejection);
+    }
+    let invitation = match data.store.invitation(invitation_id)? {
+        Some(invitation) if invitation.list_id == list_id => invitation,
+        _ => return Ok(HttpResponse::NotFound().body("Invitation not found")),
+    };
+    data.commit(&[
+        Event::InvitationDeleted { id: invitation.id },
+        record(
+            list_id,
+            &actor,
+            AccessAction::InvitationCanceled,
+            username(&data, invitation.user_id)?,
+            None,
+        ),
+    ])?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// GET /invitations: the open invitations sent to the signed-in account, oldest first
+pub async fn get_invitations(
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let invitations = data
+        .store
+        .user_invitations(user.id)?
+        .iter()
+        .map(|invitation| invitation_view(&data, invitation))
+        .collect::<StoreResult<Vec<_>>>()?;
+    Ok(HttpResponse::Ok().json(invitations))
+}
+
+// Look up an open invitation sent to `user`
+fn find_invitation(data: &AppState, user: &User, id: Uuid) -> StoreResult<Option<Invitation>> {
+    Ok(data
+        .store
+        .invitation(id)?
+        .filter(|invitation| invitation.user_id == user.id))
+}
+
+// POST /invitations/{id}/accept: join the list of an invitation, returning the list
+pub async fn accept_invitation(
+    path: web::Path<Uuid>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    let (invitation, list) = match find_invitation(&data, &user, *path)? {
+        Some(invitation) => match data.store.todo_list(invitation.list_id)? {
+            Some(list) => (invitation, list),
+            None => return Ok(HttpResponse::NotFound().body("Invitation not found")),
+        },
+        None => return Ok(HttpResponse::NotFound().body("Invitation not found")),
+    };
+    let member = ListMember {
+        list_id: list.id,
+        user_id: user.id,
+        role: invitation.role,
+        added_at: Utc::now(),
+    };
+    data.commit(&[
+        Event::InvitationDeleted { id: invitation.id },
+        Event::MemberAdded(member.clone()),
+        record(
+            list.id,
+            &actor,
+            AccessAction::Joined,
+            user.username.clone(),
+            Some(member.role),
+        ),
+    ])?;
+    Ok(HttpResponse::Ok().json(ListView {
+        list,
+        role: member.role,
+    }))
+}
+
+// POST /invitations/{id}/decline: turn an invitation down
+pub async fn decline_invitation(
+    path: web::Path<Uuid>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    let invitation = match find_invitation(&data, &user, *path)? {
+        Some(invitation) => invitation,
+        None => return Ok(HttpResponse::NotFound().body("Invitation not found")),
+    };
+    data.commit(&[
+        Event::InvitationDeleted { id: invitation.id },
+        record(
+            invitation.list_id,
+            &actor,
+            AccessAction::Declined,
+            user.username.clone(),
+            None,
+        ),
+    ])?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// GET /lists/{id}/audit: every change to who has access to a list, oldest first. Only the owner may
+// read it.
+pub async fn get_audit_log(
+    path: web::Path<Uuid>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    if let Err(rejection) = find_owned_list(&data, &user, *path)? {
+        return Ok(rejection);
+    }
+    Ok(HttpResponse::Ok().json(data.store.access_log(*path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::update_todo;
+    use crate::models::{CascadeParams, ListMember};
+    use crate::testing::{app_state, list, todo, user, TempDir};
+    use actix_web::http::StatusCode;
+
+    // A list of `owner` holding one item, shared with `editor` and `viewer` in those roles. An
+    // account called "outsider" has no access yet.
+    struct Setup {
+        data: web::Data<AppState>,
+        owner: User,
+        editor: User,
+        viewer: User,
+        list_id: Uuid,
+        todo_id: Uuid,
+    }
+
+    fn setup(dir: &TempDir) -> Setup {
+        let data = web::Data::new(app_state(dir));
+        let (owner, editor, viewer) = (user("owner"), user("editor"), user("viewer"));
+        let list = list(&owner);
+        let mut item = todo(Some(list.id));
+        item.owner_id = Some(owner.id);
+        let member = |user: &User, role| {
+            Event::MemberAdded(ListMember {
+                list_id: list.id,
+                user_id: user.id,
+                role,
+                added_at: Utc::now(),
+            })
+        };
+        data.commit(&[
+            Event::UserCreated(owner.clone()),
+            Event::UserCreated(editor.clone()),
+            Event::UserCreated(viewer.clone()),
+            Event::UserCreated(user("outsider")),
+            Event::ListCreated(list.clone()),
+            Event::TodoCreated(item.clone()),
+            member(&editor, Role::Editor),
+            member(&viewer, Role::Viewer),
+        ])
+        .unwrap();
+        Setup {
+            data,
+            owner,
+            editor,
+            viewer,
+            list_id: list.id,
+            todo_id: item.id,
+        }
+    }
+
+    async fn rename(setup: &Setup, as_user: &User) -> StatusCode {
+        let params = CascadeParams {
+            cascade: false,
+            force: false,
+        };
+        let update = serde_json::from_value(serde_json::json!({"title": "bread"})).unwrap();
+        let actor = Actor(as_user.username.clone());
+        update_todo(
+            web::Path::from(setup.todo_id),
+            web::Query(params),
+            web::Json(update),
+            as_user.clone(),
+            actor,
+            setup.data.clone(),
+        )
+        .await
+        .unwrap()
+        .status()
+    }
+
+    async fn share(setup: &Setup, as_user: &User) -> StatusCode {
+        let item = Invite {
+            username: "outsider".to_string(),
+            role: Role::Viewer,
+        };
+        let actor = Actor(as_user.username.clone());
+        invite(
+            web::Path::from(setup.list_id),
+            web::Json(item),
+            as_user.clone(),
+            actor,
+            setup.data.clone(),
+        )
+        .await
+        .unwrap()
+        .status()
+    }
+
+    async fn audit_log(setup: &Setup, as_user: &User) -> StatusCode {
+        get_audit_log(
+            web::Path::from(setup.list_id),
+            as_user.clone(),
+            setup.data.clone(),
+        )
+        .await
+        .unwrap()
+        .status()
+    }
+
+    #[actix_web::test]
+    async fn a_viewer_cannot_change_items() {
+        let dir = TempDir::new();
+        let setup = setup(&dir);
+        assert_eq!(rename(&setup, &setup.viewer).await, StatusCode::FORBIDDEN);
+        assert_eq!(rename(&setup, &setup.editor).await, StatusCode::OK);
+        let todo = setup.data.store.get(setup.todo_id).unwrap().unwrap();
+        assert_eq!(todo.title, "bread");
+    }
+
+    #[actix_web::test]
+    async fn only_the_owner_shares_the_list_and_reads_its_audit_log() {
+        let dir = TempDir::new();
+        let setup = setup(&dir);
+        for member in [&setup.editor, &setup.viewer] {
+            assert_eq!(share(&setup, member).await, StatusCode::FORBIDDEN);
+            assert_eq!(audit_log(&setup, member).await, StatusCode::FORBIDDEN);
+        }
+        let invitations = setup.data.store.list_invitations(setup.list_id).unwrap();
+        assert!(invitations.is_empty());
+        assert_eq!(share(&setup, &setup.owner).await, StatusCode::OK);
+        assert_eq!(audit_log(&setup, &setup.owner).await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,130 @@
+// HTTP handlers for smart lists: named filter expressions whose items are worked out on every read
+use actix_web::{web, HttpResponse};
+use chrono_tz::Tz;
+use uuid::Uuid;
+
+use super::todos::{fetch_page, list_query, render_page, time_zone};
+use crate::filter::Filter;
+use crate::models::{CreateSmartList, ListTodosParams, Role, SmartList, UpdateSmartList, User};
+use crate::sharing;
+use crate::state::AppState;
+use crate::store::{Event, StoreError, StoreResult};
+
+// Look up a smart list of `user`
+fn find_smart_list(data: &AppState, user: &User, id: Uuid) -> StoreResult<Option<SmartList>> {
+    Ok(data
+        .store
+        .smart_list(id)?
+        .filter(|list| user.owns(list.owner_id)))
+}
+
+// GET /smart-lists: every smart list, oldest first
+pub async fn get_smart_lists(
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let lists: Vec<SmartList> = data
+        .store
+        .smart_lists()?
+        .into_iter()
+        .filter(|list| user.owns(list.owner_id))
+        .collect();
+    Ok(HttpResponse::Ok().json(lists))
+}
+
+// POST /smart-lists: save a new smart list
+pub async fn add_smart_list(
+    item: web::Json<CreateSmartList>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    if let Err(err) = Filter::parse(&item.filter, Tz::UTC) {
+        return Ok(HttpResponse::BadRequest().json(err));
+    }
+    let _guard = data.writes.lock().unwrap();
+    let list = SmartList::new(&item, user.id);
+    data.commit(&[Event::SmartListCreated(list.clone())])?;
+    Ok(HttpResponse::Ok().json(list))
+}
+
+// GET /smart-lists/{id}
+pub async fn get_smart_list(
+    path: web::Path<Uuid>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    match find_smart_list(&data, &user, *path)? {
+        Some(list) => Ok(HttpResponse::Ok().json(list)),
+        None => Ok(HttpResponse::NotFound().body("Smart list not found")),
+    }
+}
+
+// PUT /smart-lists/{id}: rename a smart list or change its filter
+pub async fn update_smart_list(
+    path: web::Path<Uuid>,
+    item: web::Json<UpdateSmartList>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    if let Some(Err(err)) = item
+        .filter
+        .as_deref()
+        .map(|filter| Filter::parse(filter, Tz::UTC))
+    {
+        return Ok(HttpResponse::BadRequest().json(err));
+    }
+    let _guard = data.writes.lock().unwrap();
+    let mut list = match find_smart_list(&data, &user, *path)? {
+        Some(list) => list,
+        None => return Ok(HttpResponse::NotFound().body("Smart list not found")),
+    };
+    list.apply(&item);
+    data.commit(&[Event::SmartListUpdated(list.clone())])?;
+    Ok(HttpResponse::Ok().json(list))
+}
+
+// DELETE /smart-lists/{id}: remove a smart list; the items it matched are left alone
+pub async fn delete_smart_list(
+    path: web::Path<Uuid>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    if find_smart_list(&data, &user, *path)?.is_none() {
+        return Ok(HttpResponse::NotFound().body("Smart list not found"));
+    }
+    data.commit(&[Event::SmartListDeleted { id: *path }])?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// GET /smart-lists/{id}/todos: one page of the items the smart list matches right now. Accepts the
+// same query string as GET /todos, whose filters are combined with the saved one.
+pub async fn get_smart_list_todos(
+    path: web::Path<Uuid>,
+    params: web::Query<ListTodosParams>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let list = match find_smart_list(&data, &user, *path)? {
+        Some(list) => list,
+        None => return Ok(HttpResponse::NotFound().body("Smart list not found")),
+    };
+    let visible = sharing::visibility(data.store.as_ref(), &user, Role::Viewer)?;
+    let (mut query, tz) = match list_query(&params, Some(visible))
+        .and_then(|query| Ok((query, time_zone(params.tz.as_deref())?)))
+    {
+        Ok(parsed) => parsed,
+        Err(err) => return Ok(err.response()),
+    };
+    let saved = match Filter::parse(&list.filter, tz) {
+        Ok(filter) => filter,
+        Err(err) => return Ok(HttpResponse::BadRequest().json(err)),
+    };
+    query.filter = Some(match query.filter.take() {
+        Some(filter) => saved.and(filter),
+        None => saved,
+    });
+    let page = fetch_page(&data, query, params.limit)?;
+    let page = render_page(&data, page, params.render)?;
+    Ok(HttpResponse::Ok().json(page))
+}
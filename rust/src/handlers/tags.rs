@@ -0,0 +1,234 @@
+// HTTP handlers for managing tags across every to-do item
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use std::collections::BTreeMap;
+
+use super::Actor;
+use crate::history;
+use crate::models::{
+    normalize_tag, MergeTag, RenameTag, RevisionAction, Role, TagCount, TodoItem, User,
+};
+use crate::sharing;
+use crate::state::AppState;
+use crate::store::{Event, StoreError, StoreResult, TodoQuery};
+
+// GET /tags: every tag carried by a live item, with how many carry it, ordered by name
+pub async fn get_tags(user: User, data: web::Data<AppState>) -> Result<HttpResponse, StoreError> {
+    let query = TodoQuery {
+        visible: Some(sharing::visibility(
+            data.store.as_ref(),
+            &user,
+            Role::Viewer,
+        )?),
+        ..TodoQuery::default()
+    };
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for todo in data.store.list(&query)? {
+        for tag in todo.tags {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    let tags: Vec<TagCount> = counts
+        .into_iter()
+        .map(|(name, count)| TagCount { name, count })
+        .collect();
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+// PUT /tags/{name}: rename a tag on every item; use merge when the new name is already taken
+pub async fn rename_tag(
+    path: web::Path<String>,
+    item: web::Json<RenameTag>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let (from, to) = match (normalize_tag(&path), normalize_tag(&item.name)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Ok(HttpResponse::BadRequest().body("Invalid tag")),
+    };
+    let _guard = data.writes.lock().unwrap();
+    if from != to && !tagged(&data, &user, &to)?.is_empty() {
+        return Ok(HttpResponse::Conflict().body("Tag already exists; merge into it instead"));
+    }
+    replace_tag(&data, &user, &from, Some(&to), &actor.0)
+}
+
+// POST /tags/{name}/merge: replace a tag with another one on every item carrying it
+pub async fn merge_tag(
+    path: web::Path<String>,
+    item: web::Json<MergeTag>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let (from, into) = match (normalize_tag(&path), normalize_tag(&item.into)) {
+        (Some(from), Some(into)) => (from, into),
+        _ => return Ok(HttpResponse::BadRequest().body("Invalid tag")),
+    };
+    if from == into {
+        return Ok(HttpResponse::BadRequest().body("Cannot merge a tag into itself"));
+    }
+    let _guard = data.writes.lock().unwrap();
+    replace_tag(&data, &user, &from, Some(&into), &actor.0)
+}
+
+// DELETE /tags/{name}: remove a tag from every item; the items themselves stay
+pub async fn delete_tag(
+    path: web::Path<String>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let tag = match normalize_tag(&path) {
+        Some(tag) => tag,
+        None => return Ok(HttpResponse::BadRequest().body("Invalid tag")),
+    };
+    let _guard = data.writes.lock().unwrap();
+    let response = replace_tag(&data, &user, &tag, None, &actor.0)?;
+    if response.status().is_success() {
+        return Ok(HttpResponse::NoContent().finish());
+    }
+    Ok(response)
+}
+
+// Every item `user` may change carrying `tag`, trashed ones included so that restoring them does
+// not bring an old name back. Items of lists they only view keep their tags.
+fn tagged(data: &AppState, user: &User, tag: &str) -> StoreResult<Vec<TodoItem>> {
+    let visible = sharing::visibility(data.store.as_ref(), user, Role::Editor)?;
+    let mut todos = Vec::new();
+    for in_trash in [false, true].iter().copied() {
+        todos.extend(data.store.list(&TodoQuery {
+            in_trash,
+            tags: vec![tag.to_string()],
+            visible: Some(visible.clone()),
+            ..TodoQuery::default()
+        })?);
+    }
+    Ok(todos)
+}
+
+// Swap `from` for `to`, or drop it when `to` is None, on every item `user` may change carrying it.
+// All items change in one batch, each with its own revision. Answers with the resulting tag and its
+// count.
+fn replace_tag(
+    data: &AppState,
+    user: &User,
+    from: &str,
+    to: Option<&str>,
+    actor: &str,
+) -> Result<HttpResponse, StoreError> {
+    let todos = tagged(data, user, from)?;
+    if todos.is_empty() {
+        return Ok(HttpResponse::NotFound().body("Tag not found"));
+    }
+    let mut events = Vec::new();
+    for before in &todos {
+        let mut todo = before.clone();
+        todo.tags.remove(from);
+        if let Some(to) = to {
+            todo.tags.insert(to.to_string());
+        }
+        todo.updated_at = Some(Utc::now());
+        events.push(Event::TodoUpdated(todo.clone()));
+        events.extend(history::record(
+            data.store.as_ref(),
+            Some(before),
+            &todo,
+            RevisionAction::Updated,
+            actor,
+        )?);
+    }
+    data.commit(&events)?;
+
+    let name = to.unwrap_or(from).to_string();
+    let count = data
+        .store
+        .list(&TodoQuery {
+            tags: vec![name.clone()],
+            visible: Some(sharing::visibility(
+                data.store.as_ref(),
+                user,
+                Role::Editor,
+            )?),
+            ..TodoQuery::default()
+        })?
+        .len();
+    Ok(HttpResponse::Ok().json(TagCount { name, count }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app_state, todo, user, TempDir};
+    use actix_web::http::StatusCode;
+
+    fn tagged_with(owner: &User, tags: &[&str]) -> TodoItem {
+        TodoItem {
+            owner_id: Some(owner.id),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..todo(None)
+        }
+    }
+
+    #[actix_web::test]
+    async fn renaming_a_tag_changes_only_the_accounts_own_items() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let (owner, other) = (user("owner"), user("other"));
+        let mine = tagged_with(&owner, &["errands"]);
+        let theirs = tagged_with(&other, &["errands"]);
+        data.commit(&[
+            Event::UserCreated(owner.clone()),
+            Event::UserCreated(other),
+            Event::TodoCreated(mine.clone()),
+            Event::TodoCreated(theirs.clone()),
+        ])
+        .unwrap();
+
+        let rename = RenameTag {
+            name: "Chores".to_string(),
+        };
+        let response = rename_tag(
+            web::Path::from("errands".to_string()),
+            web::Json(rename),
+            owner.clone(),
+            Actor(owner.username.clone()),
+            data.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let tags = |id| data.store.get(id).unwrap().unwrap().tags;
+        assert!(tags(mine.id).contains("chores") && !tags(mine.id).contains("errands"));
+        assert!(tags(theirs.id).contains("errands"));
+    }
+
+    #[actix_web::test]
+    async fn renaming_into_a_tag_in_use_is_refused() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let owner = user("owner");
+        let item = tagged_with(&owner, &["errands", "chores"]);
+        data.commit(&[
+            Event::UserCreated(owner.clone()),
+            Event::TodoCreated(item.clone()),
+        ])
+        .unwrap();
+
+        let rename = RenameTag {
+            name: "chores".to_string(),
+        };
+        let response = rename_tag(
+            web::Path::from("errands".to_string()),
+            web::Json(rename),
+            owner.clone(),
+            Actor(owner.username.clone()),
+            data.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(data.store.get(item.id).unwrap().unwrap().tags, item.tags);
+    }
+}
@@ -0,0 +1,600 @@
+// HTTP handlers for the /todos routes
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use chrono_tz::Tz;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use super::Actor;
+use crate::dependencies;
+use crate::filter::{Filter, ParseError};
+use crate::history;
+use crate::models::{
+    checklist_error, normalize_tag, CascadeParams, CreateTodoItem, ListTodosParams, MoveTodo,
+    Render, RevisionAction, Role, SortOrder, TodoItem, TodoList, TodoPage, TodoView,
+    UpdateTodoItem, User,
+};
+use crate::rank;
+use crate::recurrence;
+use crate::sharing;
+use crate::state::AppState;
+use crate::store::{Cursor, Event, StoreError, TimeRange, TodoQuery, Visibility};
+use crate::tree::{self, Children};
+
+// Look up an item that `user` can see and that is not in the trash
+pub(super) fn find_live(
+    data: &AppState,
+    user: &User,
+    id: Uuid,
+) -> Result<Option<TodoItem>, StoreError> {
+    match data.store.get(id)?.filter(|todo| todo.deleted_at.is_none()) {
+        Some(todo) if sharing::todo_role(data.store.as_ref(), user, &todo)?.is_some() => {
+            Ok(Some(todo))
+        }
+        _ => Ok(None),
+    }
+}
+
+// Look up an item that `user` may change and that is not in the trash, or the response refusing it:
+// 404 when they cannot see it, 403 when they only view the list it is in
+pub(super) fn find_editable(
+    data: &AppState,
+    user: &User,
+    id: Uuid,
+) -> Result<Result<TodoItem, HttpResponse>, StoreError> {
+    let todo = match data.store.get(id)?.filter(|todo| todo.deleted_at.is_none()) {
+        Some(todo) => todo,
+        None => return Ok(Err(not_found())),
+    };
+    Ok(
+        match sharing::todo_role(data.store.as_ref(), user, &todo)? {
+            Some(role) if role >= Role::Editor => Ok(todo),
+            Some(_) => Err(read_only()),
+            None => Err(not_found()),
+        },
+    )
+}
+
+pub(super) fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().body("Todo item not found")
+}
+
+// 403 response for a viewer trying to change an item of a shared list
+pub(super) fn read_only() -> HttpResponse {
+    HttpResponse::Forbidden().body("Viewers cannot change the todos of a shared list")
+}
+
+// Page size used when the client does not ask for one, and the largest it may ask for
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+// Why the query string of a listing was rejected
+pub(super) enum InvalidQuery {
+    Cursor,             // The cursor is garbled or was produced for another sort field
+    Filter(ParseError), // The filter expression does not parse
+    TimeZone,           // The time zone is not a known IANA name
+    List,               // The list is neither an id nor `none`
+}
+
+impl InvalidQuery {
+    // 400 response telling the client what to fix
+    pub(super) fn response(&self) -> HttpResponse {
+        match self {
+            InvalidQuery::Cursor => HttpResponse::BadRequest().body("Invalid cursor"),
+            InvalidQuery::Filter(err) => HttpResponse::BadRequest().json(err),
+            InvalidQuery::TimeZone => HttpResponse::BadRequest().body("Invalid time zone"),
+            InvalidQuery::List => HttpResponse::BadRequest().body("Invalid list"),
+        }
+    }
+}
+
+// Time zone named by a `tz` query parameter, UTC when there is none
+pub(super) fn time_zone(name: Option<&str>) -> Result<Tz, InvalidQuery> {
+    match name {
+        Some(name) => name.parse().map_err(|_| InvalidQuery::TimeZone),
+        None => Ok(Tz::UTC),
+    }
+}
+
+// Turn the query string of GET /todos into a store query over the items in `visible`, or over every
+// item when it is None
+pub(super) fn list_query(
+    params: &ListTodosParams,
+    visible: Option<Visibility>,
+) -> Result<TodoQuery, InvalidQuery> {
+    let after = match &params.cursor {
+        Some(cursor) => Some(Cursor::decode(params.sort, cursor).ok_or(InvalidQuery::Cursor)?),
+        None => None,
+    };
+    let filter = match params
+        .filter
+        .as_deref()
+        .filter(|filter| !filter.trim().is_empty())
+    {
+        Some(filter) => Some(
+            Filter::parse(filter, time_zone(params.tz.as_deref())?)
+                .map_err(InvalidQuery::Filter)?,
+        ),
+        None => None,
+    };
+    let list = match params.list.as_deref() {
+        Some("none") => Some(None),
+        Some(id) => Some(Some(Uuid::parse_str(id).map_err(|_| InvalidQuery::List)?)),
+        None => None,
+    };
+    Ok(TodoQuery {
+        completed: params.completed,
+        title_contains: params.q.clone().filter(|q| !q.is_empty()),
+        created: TimeRange {
+            from: params.created_after,
+            to: params.created_before,
+        },
+        updated: TimeRange {
+            from: params.updated_after,
+            to: params.updated_before,
+        },
+        tags: params
+            .tag
+            .as_deref()
+            .map(|tags| tags.split(',').filter_map(normalize_tag).collect())
+            .unwrap_or_default(),
+        priority: params.priority,
+        filter,
+        list,
+        visible,
+        sort: params.sort,
+        descending: params.order == SortOrder::Desc,
+        after,
+        ..TodoQuery::default()
+    })
+}
+
+// Run `query` for one page of at most `limit` items, working out the cursor of the next page
+pub(super) fn fetch_page(
+    data: &AppState,
+    mut query: TodoQuery,
+    limit: Option<usize>,
+) -> Result<TodoPage, StoreError> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    query.limit = Some(limit + 1); // One extra item tells us whether there is another page
+    let mut items = data.store.list(&query)?;
+    let next_cursor = if items.len() > limit {
+        items.truncate(limit);
+        items
+            .last()
+            .map(|last| Cursor::after(query.sort, last).encode())
+    } else {
+        None
+    };
+    Ok(TodoPage { items, next_cursor })
+}
+
+// Wrap every item of `page` for the response, along with its comment count
+pub(super) fn render_page(
+    data: &AppState,
+    page: TodoPage,
+    render: Option<Render>,
+) -> Result<TodoPage<TodoView>, StoreError> {
+    let comment_counts = data.store.comment_counts(&page.ids())?;
+    Ok(page.render(render, &comment_counts))
+}
+
+// Asynchronous function to handle GET requests for fetching a page of to-do items
+pub async fn get_todos(
+    params: web::Query<ListTodosParams>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let visible = sharing::visibility(data.store.as_ref(), &user, Role::Viewer)?;
+    let query = match list_query(&params, Some(visible)) {
+        Ok(query) => query,
+        Err(err) => return Ok(err.response()),
+    };
+    let page = fetch_page(&data, query, params.limit)?;
+    let page = render_page(&data, page, params.render)?;
+
+    // Return an HTTP response with the page of to-do items serialized as JSON
+    Ok(HttpResponse::Ok().json(page))
+}
+
+// The list `list_id` that `user` wants to put an item into, or the response refusing it unless they
+// own or edit the list and it is not archived
+fn target_list(
+    data: &AppState,
+    user: &User,
+    list_id: Option<Uuid>,
+) -> Result<Result<Option<TodoList>, HttpResponse>, StoreError> {
+    let list = match list_id
+        .map(|list_id| data.store.todo_list(list_id))
+        .transpose()?
+    {
+        Some(list) => list,
+        None => return Ok(Ok(None)),
+    };
+    Ok(match list {
+        Some(list) => match sharing::list_role(data.store.as_ref(), user, &list)? {
+            None => Err(HttpResponse::BadRequest().body("List not found")),
+            Some(Role::Viewer) => Err(read_only()),
+            Some(_) if list.archived => Err(HttpResponse::Conflict().body("List is archived")),
+            Some(_) => Ok(Some(list)),
+        },
+        None => Err(HttpResponse::BadRequest().body("List not found")),
+    })
+}
+
+// Validate and save a new item for `user`, returning it, or the response rejecting it. A subtask
+// goes into the list of its parent. An item added to a list belongs to the list's owner.
+pub(super) fn create_todo(
+    data: &AppState,
+    item: &CreateTodoItem,
+    user: &User,
+    actor: &Actor,
+) -> Result<Result<TodoItem, HttpResponse>, StoreError> {
+    if item.tags.iter().any(|tag| normalize_tag(tag).is_none()) {
+        return Ok(Err(HttpResponse::BadRequest().body("Invalid tag")));
+    }
+    if let Some(err) = checklist_error(&item.checklist) {
+        return Ok(Err(HttpResponse::BadRequest().body(err)));
+    }
+    let mut new_todo = TodoItem::new(item, user.id);
+    if new_todo.starts_after_due() {
+        return Ok(Err(
+            HttpResponse::BadRequest().body("start_at must not be after due_at")
+        ));
+    }
+    if let Some(parent_id) = new_todo.parent_id {
+        let parent = match find_live(data, user, parent_id)? {
+            Some(parent) => parent,
+            None => return Ok(Err(HttpResponse::BadRequest().body("Parent todo not found"))),
+        };
+        match item.list_id {
+            Some(list_id) if parent.list_id != Some(list_id) => {
+                return Ok(Err(HttpResponse::BadRequest()
+                    .body("A subtask must be in the same list as its parent")))
+            }
+            _ => new_todo.list_id = parent.list_id,
+        }
+    }
+    match target_list(data, user, new_todo.list_id)? {
+        Ok(list) => new_todo.owner_id = list.map_or(new_todo.owner_id, |list| list.owner_id),
+        Err(refusal) => return Ok(Err(refusal)),
+    }
+    for blocker_id in &new_todo.blocked_by {
+        if find_live(data, user, *blocker_id)?.is_none() {
+            return Ok(Err(
+                HttpResponse::BadRequest().body("Blocking todo not found")
+            ));
+        }
+    }
+    new_todo.rank = rank::last(data.store.as_ref())?;
+    let mut events = vec![Event::TodoCreated(new_todo.clone())]; // Add the new to-do item to the list
+    events.extend(history::record(
+        data.store.as_ref(),
+        None,
+        &new_todo,
+        RevisionAction::Created,
+        &actor.0,
+    )?);
+    data.commit(&events)?;
+    Ok(Ok(new_todo))
+}
+
+// Asynchronous function to handle POST requests for creating a new to-do item
+pub async fn add_todo(
+    item: web::Json<CreateTodoItem>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    match create_todo(&data, &item, &user, &actor)? {
+        Ok(todo) => Ok(HttpResponse::Created().json(todo)), // Return the new to-do item
+        Err(rejection) => Ok(rejection),
+    }
+}
+
+// With `cascade=true`, a change of the completion status is applied to every subtask as well.
+// Completing an item that still waits for open blocking tasks is refused unless `force=true`.
+// Completing an instance of a recurring item adds its next instance. Moving an item to another list,
+// or under a parent in another list, takes its subtasks along and hands them to the list's owner.
+pub async fn update_todo(
+    path: web::Path<Uuid>,
+    params: web::Query<CascadeParams>,
+    item: web::Json<UpdateTodoItem>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    if item
+        .tags
+        .iter()
+        .flatten()
+        .any(|tag| normalize_tag(tag).is_none())
+    {
+        return Ok(HttpResponse::BadRequest().body("Invalid tag"));
+    }
+    if let Some(err) = item.checklist.as_deref().and_then(checklist_error) {
+        return Ok(HttpResponse::BadRequest().body(err));
+    }
+    let _guard = data.writes.lock().unwrap();
+    let before = match find_editable(&data, &user, *path)? {
+        Ok(todo) => todo,
+        Err(rejection) => return Ok(rejection),
+    };
+    if let Some(Some(parent_id)) = item.parent_id {
+        if find_live(&data, &user, parent_id)?.is_none() {
+            return Ok(HttpResponse::BadRequest().body("Parent todo not found"));
+        }
+        if tree::creates_cycle(data.store.as_ref(), before.id, parent_id)? {
+            return Ok(HttpResponse::Conflict()
+                .body("A todo cannot become a subtask of itself or of one of its subtasks"));
+        }
+    }
+    if let Some(blocked_by) = &item.blocked_by {
+        for blocker_id in blocked_by {
+            if find_live(&data, &user, *blocker_id)?.is_none() {
+                return Ok(HttpResponse::BadRequest().body("Blocking todo not found"));
+            }
+        }
+        if dependencies::creates_cycle(data.store.as_ref(), before.id, blocked_by)? {
+            return Ok(HttpResponse::Conflict()
+                .body("A todo cannot be blocked by itself or by a todo that waits for it"));
+        }
+    }
+    let mut todo = before.clone();
+    todo.apply(&item);
+    if todo.starts_after_due() {
+        return Ok(HttpResponse::BadRequest().body("start_at must not be after due_at"));
+    }
+    if let Some(parent) = todo
+        .parent_id
+        .map(|id| find_live(&data, &user, id))
+        .transpose()?
+        .flatten()
+    {
+        if parent.list_id != todo.list_id {
+            if item.list_id.is_some() {
+                return Ok(HttpResponse::BadRequest()
+                    .body("A subtask must be in the same list as its parent"));
+            }
+            todo.list_id = parent.list_id;
+        }
+    }
+    if todo.list_id != before.list_id {
+        match target_list(&data, &user, todo.list_id)? {
+            Ok(list) => todo.owner_id = list.map_or(todo.owner_id, |list| list.owner_id),
+            Err(refusal) => return Ok(refusal),
+        }
+    }
+
+    // Pairs of the state before and after for the item and every subtask the change cascades to
+    let cascade_completion = item.completed.filter(|_| params.cascade);
+    let moved = todo.list_id != before.list_id;
+    let mut changed = vec![(before, todo.clone())];
+    if cascade_completion.is_some() || moved {
+        let children = Children::below(data.store.as_ref(), todo.id, false)?;
+        for before in children.descendants(todo.id) {
+            let mut subtask = before.clone();
+            subtask.completed = cascade_completion.unwrap_or(subtask.completed);
+            subtask.list_id = todo.list_id;
+            subtask.owner_id = todo.owner_id;
+            if subtask.completed != before.completed || subtask.list_id != before.list_id {
+                subtask.updated_at = todo.updated_at;
+                changed.push((before, subtask));
+            }
+        }
+    }
+    if !params.force {
+        let completing: Vec<&TodoItem> = changed
+            .iter()
+            .filter(|(before, after)| !before.completed && after.completed)
+            .map(|(_, after)| after)
+            .collect();
+        if !completing.is_empty() {
+            // Blockers completed by this very request do not hold it up
+            let ids: HashSet<Uuid> = completing.iter().map(|todo| todo.id).collect();
+            let mut open = Vec::new();
+            for todo in &completing {
+                for blocker in dependencies::open_blockers(data.store.as_ref(), todo)? {
+                    if !ids.contains(&blocker.id) {
+                        open.push(blocker.title);
+                    }
+                }
+            }
+            if !open.is_empty() {
+                open.sort_unstable();
+                open.dedup();
+                return Ok(HttpResponse::Conflict().body(format!(
+                    "Todo item is blocked by open todos ({}); complete with force=true to override",
+                    open.join(", ")
+                )));
+            }
+        }
+    }
+
+    // Completing an instance of a recurring task brings up the next one. The completed instance stops
+    // recurring, so reopening and completing it again does not bring up a second one.
+    let now = Utc::now();
+    let mut spawned = Vec::new();
+    for (before, after) in &mut changed {
+        if !before.completed && after.completed && after.recurrence.is_some() {
+            spawned.extend(recurrence::next_instance(after, now));
+            after.recurrence = None;
+        }
+    }
+
+    let mut events = Vec::new();
+    for (before, after) in &changed {
+        events.push(Event::TodoUpdated(after.clone()));
+        events.extend(history::record(
+            data.store.as_ref(),
+            Some(before),
+            after,
+            RevisionAction::Updated,
+            &actor.0,
+        )?);
+    }
+    for mut next in spawned {
+        // Ranked right after the completed instance, whose rank it was given
+        next.rank = rank::after(data.store.as_ref(), &next)?;
+        events.push(Event::TodoCreated(next.clone()));
+        events.extend(history::record(
+            data.store.as_ref(),
+            None,
+            &next,
+            RevisionAction::Created,
+            &actor.0,
+        )?);
+    }
+    data.commit(&events)?;
+    Ok(HttpResponse::Ok().json(&changed[0].1))
+}
+
+// Deleting only moves the item to the trash; it is purged for good once the retention period is over.
+// Items with subtasks are only deleted with `cascade=true`, which moves the subtasks to the trash too.
+pub async fn delete_todo(
+    path: web::Path<Uuid>,
+    params: web::Query<CascadeParams>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    let root = match find_editable(&data, &user, *path)? {
+        Ok(todo) => todo,
+        Err(rejection) => return Ok(rejection),
+    };
+    let subtasks = Children::below(data.store.as_ref(), root.id, false)?.descendants(root.id);
+    if !subtasks.is_empty() && !params.cascade {
+        return Ok(HttpResponse::Conflict().body(
+            "Todo item has subtasks; delete with cascade=true to move them to the trash too",
+        ));
+    }
+    let deleted_at = Utc::now();
+    let mut events = Vec::new();
+    for before in std::iter::once(root).chain(subtasks) {
+        let mut todo = before.clone();
+        todo.deleted_at = Some(deleted_at);
+        events.push(Event::TodoUpdated(todo.clone()));
+        events.extend(history::record(
+            data.store.as_ref(),
+            Some(&before),
+            &todo,
+            RevisionAction::Deleted,
+            &actor.0,
+        )?);
+    }
+    data.commit(&events)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// POST /todos/{id}/move: put an item right before or right after another one in the manual order.
+// Only the moved item gets a new rank.
+pub async fn move_todo(
+    path: web::Path<Uuid>,
+    item: web::Json<MoveTodo>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let (target_id, after) = match (item.before, item.after) {
+        (Some(id), None) => (id, false),
+        (None, Some(id)) => (id, true),
+        _ => {
+            return Ok(HttpResponse::BadRequest().body("Give exactly one of before and after"));
+        }
+    };
+    if target_id == *path {
+        return Ok(HttpResponse::BadRequest().body("A todo cannot be moved next to itself"));
+    }
+    let _guard = data.writes.lock().unwrap();
+    let before = match find_editable(&data, &user, *path)? {
+        Ok(todo) => todo,
+        Err(rejection) => return Ok(rejection),
+    };
+    let target = match find_live(&data, &user, target_id)? {
+        Some(todo) => todo,
+        None => return Ok(HttpResponse::BadRequest().body("Target todo not found")),
+    };
+    let mut todo = before.clone();
+    todo.rank = if after {
+        rank::after(data.store.as_ref(), &target)?
+    } else {
+        rank::before(data.store.as_ref(), &target)?
+    };
+    todo.updated_at = Some(Utc::now());
+    let mut events = vec![Event::TodoUpdated(todo.clone())];
+    events.extend(history::record(
+        data.store.as_ref(),
+        Some(&before),
+        &todo,
+        RevisionAction::Moved,
+        &actor.0,
+    )?);
+    data.commit(&events)?;
+    Ok(HttpResponse::Ok().json(todo))
+}
+
+// GET /todos/{id}/tree: the item with all of its subtasks nested below it and completion rolled up
+pub async fn get_todo_tree(
+    path: web::Path<Uuid>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let root = match find_live(&data, &user, *path)? {
+        Some(todo) => todo,
+        None => return Ok(HttpResponse::NotFound().body("Todo item not found")),
+    };
+    let children = Children::below(data.store.as_ref(), root.id, false)?;
+    Ok(HttpResponse::Ok().json(children.tree(root)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app_state, user, TempDir};
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+
+    #[actix_web::test]
+    async fn adding_answers_with_the_item_and_deleting_with_no_content() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let owner = user("owner");
+        data.commit(&[Event::UserCreated(owner.clone())]).unwrap();
+        let actor = || Actor(owner.username.clone());
+
+        let item = serde_json::from_value(serde_json::json!({"title": "milk", "completed": false}));
+        let response = add_todo(
+            web::Json(item.unwrap()),
+            owner.clone(),
+            actor(),
+            data.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let added: TodoItem =
+            serde_json::from_slice(&to_bytes(response.into_body()).await.unwrap()).unwrap();
+        assert_eq!(added.title, "milk");
+        assert_eq!(added.owner_id, Some(owner.id));
+
+        let params = CascadeParams {
+            cascade: false,
+            force: false,
+        };
+        let path = web::Path::from(added.id);
+        let response = delete_todo(
+            path,
+            web::Query(params),
+            owner.clone(),
+            actor(),
+            data.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let trashed = data.store.get(added.id).unwrap().unwrap();
+        assert!(trashed.deleted_at.is_some());
+    }
+}
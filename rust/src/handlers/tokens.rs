@@ -0,0 +1,174 @@
+// HTTP handlers for personal API tokens: creating, listing and revoking them
+use actix_web::{web, HttpResponse};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use crate::auth;
+use crate::models::{ApiToken, ApiTokenInfo, CreatedApiToken, NewApiToken, User};
+use crate::state::AppState;
+use crate::store::{Event, StoreError};
+
+// Longest an API token may work, in days
+const MAX_LIFETIME_DAYS: u32 = 10 * 366;
+
+// GET /auth/tokens: the API tokens of the signed-in account, oldest first, without their secrets
+pub async fn get_tokens(user: User, data: web::Data<AppState>) -> Result<HttpResponse, StoreError> {
+    let tokens: Vec<ApiTokenInfo> = data
+        .store
+        .api_tokens(user.id)?
+        .iter()
+        .map(ApiTokenInfo::from)
+        .collect();
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+// POST /auth/tokens: create an API token, returning its secret. The secret is not shown again.
+pub async fn create_token(
+    item: web::Json<NewApiToken>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let item = item.into_inner();
+    let name = item.name.trim().to_string();
+    if name.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("API tokens must have a name"));
+    }
+    let mut scopes = Vec::new();
+    for scope in item.scopes {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    if scopes.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("API tokens need at least one scope"));
+    }
+    if let Some(days) = item.expires_in_days {
+        if days == 0 || days > MAX_LIFETIME_DAYS {
+            return Ok(HttpResponse::BadRequest().body(format!(
+                "expires_in_days must be between 1 and {}",
+                MAX_LIFETIME_DAYS
+            )));
+        }
+    }
+    let now = Utc::now();
+    let expires_at = item
+        .expires_in_days
+        .map(|days| now + Duration::days(days.into()));
+    let secret = auth::new_api_token();
+    let token = ApiToken {
+        id: Uuid::new_v4(),
+        user_id: user.id,
+        name,
+        hash: auth::token_id(&secret),
+        scopes,
+        created_at: now,
+        expires_at,
+        last_used_at: None,
+    };
+    let _guard = data.writes.lock().unwrap();
+    data.commit(&[Event::ApiTokenCreated(token.clone())])?;
+    Ok(HttpResponse::Ok().json(CreatedApiToken {
+        token: secret,
+        info: ApiTokenInfo::from(&token),
+    }))
+}
+
+// DELETE /auth/tokens/{id}: revoke one of the signed-in account's API tokens
+pub async fn revoke_token(
+    path: web::Path<Uuid>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    match data.store.api_token(*path)? {
+        Some(token) if token.user_id == user.id => {
+            data.commit(&[Event::ApiTokenDeleted { id: token.id }])?;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        _ => Ok(HttpResponse::NotFound().body("API token not found")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::get_me;
+    use crate::models::Scope;
+    use crate::testing::{app_state, user, TempDir};
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::http::StatusCode;
+    use actix_web::{middleware, test, App};
+
+    async fn create(data: &web::Data<AppState>, owner: &User, days: Option<u32>) -> StatusCode {
+        let item = NewApiToken {
+            name: "ci".to_string(),
+            scopes: vec![Scope::TodosRead],
+            expires_in_days: days,
+        };
+        create_token(web::Json(item), owner.clone(), data.clone())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[actix_web::test]
+    async fn a_lifetime_past_the_cap_is_refused() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let owner = user("owner");
+        data.commit(&[Event::UserCreated(owner.clone())]).unwrap();
+        for days in [0, MAX_LIFETIME_DAYS + 1, 4_000_000_000] {
+            assert_eq!(
+                create(&data, &owner, Some(days)).await,
+                StatusCode::BAD_REQUEST
+            );
+        }
+        assert!(data.store.api_tokens(owner.id).unwrap().is_empty());
+        assert_eq!(
+            create(&data, &owner, Some(MAX_LIFETIME_DAYS)).await,
+            StatusCode::OK
+        );
+        assert_eq!(create(&data, &owner, None).await, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn an_expired_token_signs_nobody_in() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let owner = user("owner");
+        let now = Utc::now();
+        let token = |expires_at| {
+            let secret = auth::new_api_token();
+            let token = ApiToken {
+                id: Uuid::new_v4(),
+                user_id: owner.id,
+                name: "ci".to_string(),
+                hash: auth::token_id(&secret),
+                scopes: vec![Scope::TodosRead],
+                created_at: now - Duration::days(2),
+                expires_at: Some(expires_at),
+                last_used_at: None,
+            };
+            (secret, Event::ApiTokenCreated(token))
+        };
+        let (expired, expired_event) = token(now - Duration::seconds(1));
+        let (live, live_event) = token(now + Duration::days(1));
+        data.commit(&[Event::UserCreated(owner.clone()), expired_event, live_event])
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .wrap(middleware::from_fn(auth::authenticate))
+                .route("/auth/me", web::get().to(get_me)),
+        )
+        .await;
+        for (secret, status) in [(expired, StatusCode::UNAUTHORIZED), (live, StatusCode::OK)] {
+            let request = test::TestRequest::get()
+                .uri("/auth/me")
+                .insert_header((AUTHORIZATION, format!("Bearer {}", secret)))
+                .to_request();
+            assert_eq!(test::call_service(&app, request).await.status(), status);
+        }
+    }
+}
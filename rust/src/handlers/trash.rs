@@ -0,0 +1,206 @@
+// HTTP handlers for the trash: listing, restoring and permanently deleting items
+use actix_web::{web, HttpResponse};
+use uuid::Uuid;
+
+use super::todos::{find_live, read_only};
+use super::Actor;
+use crate::history;
+use crate::models::{CascadeParams, RevisionAction, Role, TodoItem, User};
+use crate::sharing;
+use crate::state::AppState;
+use crate::store::{Event, StoreError, TodoQuery};
+use crate::trash;
+use crate::tree::Children;
+
+// Look up an item in the trash that `user` may change, or the response refusing it
+fn find_trashed(
+    data: &AppState,
+    user: &User,
+    id: Uuid,
+) -> Result<Result<TodoItem, HttpResponse>, StoreError> {
+    let not_found = || HttpResponse::NotFound().body("Todo item not found in trash");
+    let todo = match data.store.get(id)?.filter(|todo| todo.deleted_at.is_some()) {
+        Some(todo) => todo,
+        None => return Ok(Err(not_found())),
+    };
+    Ok(
+        match sharing::todo_role(data.store.as_ref(), user, &todo)? {
+            Some(role) if role >= Role::Editor => Ok(todo),
+            Some(_) => Err(read_only()),
+            None => Err(not_found()),
+        },
+    )
+}
+
+// GET /trash: every item currently in the trash that the signed-in account can see
+pub async fn get_trash(user: User, data: web::Data<AppState>) -> Result<HttpResponse, StoreError> {
+    let query = TodoQuery {
+        in_trash: true,
+        visible: Some(sharing::visibility(
+            data.store.as_ref(),
+            &user,
+            Role::Viewer,
+        )?),
+        ..TodoQuery::default()
+    };
+    Ok(HttpResponse::Ok().json(data.store.list(&query)?))
+}
+
+// POST /todos/{id}/restore: take an item back out of the trash. Its parent must be live for it to go
+// back under it, in the parent's list, otherwise it becomes a top-level item. An item whose list was
+// deleted meanwhile comes back in no list. With `cascade=true`, its trashed subtasks are restored
+// as well.
+pub async fn restore_todo(
+    path: web::Path<Uuid>,
+    params: web::Query<CascadeParams>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    let root = match find_trashed(&data, &user, *path)? {
+        Ok(todo) => todo,
+        Err(rejection) => return Ok(rejection),
+    };
+    let mut restoring = vec![root];
+    if params.cascade {
+        let children = Children::below(data.store.as_ref(), *path, true)?;
+        restoring.extend(
+            children
+                .descendants(*path)
+                .into_iter()
+                .filter(|todo| todo.deleted_at.is_some()),
+        );
+    }
+
+    let mut events = Vec::new();
+    let mut restored: Vec<TodoItem> = Vec::new();
+    for before in restoring {
+        let mut todo = before.clone();
+        todo.deleted_at = None;
+        if let Some(parent_id) = todo.parent_id {
+            // Parents come before their subtasks, so a parent restored along is found here
+            let parent_list = match restored.iter().find(|parent| parent.id == parent_id) {
+                Some(parent) => Some(parent.list_id),
+                None => find_live(&data, &user, parent_id)?.map(|parent| parent.list_id),
+            };
+            match parent_list {
+                Some(list_id) => todo.list_id = list_id,
+                None => todo.parent_id = None,
+            }
+        }
+        if let Some(list_id) = todo.list_id {
+            if data.store.todo_list(list_id)?.is_none() {
+                todo.list_id = None;
+            }
+        }
+        events.push(Event::TodoUpdated(todo.clone()));
+        events.extend(history::record(
+            data.store.as_ref(),
+            Some(&before),
+            &todo,
+            RevisionAction::Restored,
+            &actor.0,
+        )?);
+        restored.push(todo);
+    }
+    data.commit(&events)?;
+    Ok(HttpResponse::Ok().json(&restored[0]))
+}
+
+// DELETE /trash/{id}: permanently remove an item without waiting for the purge
+pub async fn purge_todo(
+    path: web::Path<Uuid>,
+    user: User,
+    actor: Actor,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    let todo = match find_trashed(&data, &user, *path)? {
+        Ok(todo) => todo,
+        Err(rejection) => return Ok(rejection),
+    };
+    let events = trash::purge_events(data.store.as_ref(), &todo, &actor.0)?;
+    data.commit(&events)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app_state, todo, user, TempDir};
+    use actix_web::http::StatusCode;
+    use chrono::Utc;
+
+    async fn restore(
+        data: &web::Data<AppState>,
+        owner: &User,
+        id: Uuid,
+        cascade: bool,
+    ) -> StatusCode {
+        let params = CascadeParams {
+            cascade,
+            force: false,
+        };
+        let actor = Actor(owner.username.clone());
+        restore_todo(
+            web::Path::from(id),
+            web::Query(params),
+            owner.clone(),
+            actor,
+            data.clone(),
+        )
+        .await
+        .unwrap()
+        .status()
+    }
+
+    #[actix_web::test]
+    async fn restoring_brings_trashed_items_back_with_their_subtasks() {
+        let dir = TempDir::new();
+        let data = web::Data::new(app_state(&dir));
+        let owner = user("owner");
+        let trashed = |parent_id| {
+            let mut item = todo(None);
+            item.owner_id = Some(owner.id);
+            item.parent_id = parent_id;
+            item.deleted_at = Some(Utc::now());
+            item
+        };
+        let parent = trashed(None);
+        let child = trashed(Some(parent.id));
+        let mut live = trashed(None);
+        live.deleted_at = None;
+        data.commit(&[
+            Event::UserCreated(owner.clone()),
+            Event::TodoCreated(parent.clone()),
+            Event::TodoCreated(child.clone()),
+            Event::TodoCreated(live.clone()),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            restore(&data, &owner, live.id, false).await,
+            StatusCode::NOT_FOUND
+        );
+        let stranger = user("stranger");
+        data.commit(&[Event::UserCreated(stranger.clone())])
+            .unwrap();
+        assert_eq!(
+            restore(&data, &stranger, parent.id, true).await,
+            StatusCode::NOT_FOUND
+        );
+
+        assert_eq!(
+            restore(&data, &owner, parent.id, true).await,
+            StatusCode::OK
+        );
+        for id in [parent.id, child.id] {
+            assert!(data.store.get(id).unwrap().unwrap().deleted_at.is_none());
+        }
+        assert_eq!(
+            restore(&data, &owner, parent.id, true).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+}
@@ -0,0 +1,161 @@
+// HTTP handlers for the second sign-in step: enrolling an authenticator app, confirming it,
+// replacing the recovery codes and turning it off
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+
+use super::try_again_at;
+use crate::auth::Refusal;
+use crate::models::{RecoveryCodes, TotpCode, TotpEnrollment, TwoFactor, TwoFactorStatus, User};
+use crate::state::AppState;
+use crate::store::{Event, StoreError, StoreResult};
+use crate::totp;
+
+// Check `code` against `factor`, answering with `wrong` when it does not match. A refused code is
+// counted right away; on success the caller saves `factor` with the rest of its events.
+pub(super) fn check_code(
+    data: &AppState,
+    factor: &mut TwoFactor,
+    code: &str,
+    recovery: bool,
+    wrong: StatusCode,
+) -> StoreResult<Result<(), HttpResponse>> {
+    let refusal = match totp::check(factor, code, recovery, Utc::now()) {
+        Ok(()) => return Ok(Ok(())),
+        Err(refusal) => refusal,
+    };
+    data.commit(&[Event::TwoFactorUpdated(factor.clone())])?;
+    Ok(Err(match refusal {
+        Refusal::Wrong => HttpResponse::build(wrong).body("Invalid two-factor code"),
+        Refusal::Locked(until) => {
+            try_again_at(until, "Too many invalid two-factor codes; try again later")
+        }
+    }))
+}
+
+// The enabled second step of `user`, or a 404 response
+fn find_enabled(data: &AppState, user: &User) -> StoreResult<Result<TwoFactor, HttpResponse>> {
+    Ok(match data.store.two_factor(user.id)? {
+        Some(factor) if factor.enabled => Ok(factor),
+        _ => Err(HttpResponse::NotFound().body("Two-factor sign-in is not enabled")),
+    })
+}
+
+// GET /auth/totp: whether logging in asks for a code, and how many recovery codes are left
+pub async fn get_totp(user: User, data: web::Data<AppState>) -> Result<HttpResponse, StoreError> {
+    let factor = data.store.two_factor(user.id)?;
+    Ok(HttpResponse::Ok().json(TwoFactorStatus {
+        enabled: factor.as_ref().is_some_and(|factor| factor.enabled),
+        pending: factor.as_ref().is_some_and(|factor| !factor.enabled),
+        recovery_codes_left: factor
+            .filter(|factor| factor.enabled)
+            .map_or(0, |factor| factor.recovery_codes.len()),
+    }))
+}
+
+// POST /auth/totp: start enrolling an authenticator app, returning the secret to give it. Logging
+// in asks for codes once the first one is confirmed. Starting again replaces a pending enrollment.
+pub async fn enroll_totp(
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    let existing = data.store.two_factor(user.id)?;
+    if existing.as_ref().is_some_and(|factor| factor.enabled) {
+        return Ok(HttpResponse::Conflict().body("Two-factor sign-in is already enabled"));
+    }
+    let factor = TwoFactor {
+        user_id: user.id,
+        secret: totp::new_secret(),
+        enabled: false,
+        recovery_codes: Vec::new(),
+        last_step: None,
+        failures: 0,
+        locked_until: None,
+        created_at: Utc::now(),
+    };
+    let enrollment = TotpEnrollment {
+        uri: totp::uri(&user.username, &factor.secret),
+        secret: factor.secret.clone(),
+    };
+    data.commit(&[match existing {
+        Some(_) => Event::TwoFactorUpdated(factor),
+        None => Event::TwoFactorCreated(factor),
+    }])?;
+    Ok(HttpResponse::Ok().json(enrollment))
+}
+
+// POST /auth/totp/confirm: finish enrolling with a first code from the app, turning two-factor
+// sign-in on. Returns the recovery codes, which are not shown again.
+pub async fn confirm_totp(
+    item: web::Json<TotpCode>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    let mut factor = match data.store.two_factor(user.id)? {
+        Some(factor) if factor.enabled => {
+            return Ok(HttpResponse::Conflict().body("Two-factor sign-in is already enabled"))
+        }
+        Some(factor) => factor,
+        None => return Ok(HttpResponse::NotFound().body("Start enrollment first")),
+    };
+    if let Err(rejection) =
+        check_code(&data, &mut factor, &item.code, false, StatusCode::FORBIDDEN)?
+    {
+        return Ok(rejection);
+    }
+    let (recovery_codes, hashes) = totp::new_recovery_codes();
+    factor.enabled = true;
+    factor.recovery_codes = hashes;
+    data.commit(&[Event::TwoFactorUpdated(factor)])?;
+    Ok(HttpResponse::Ok().json(RecoveryCodes { recovery_codes }))
+}
+
+// POST /auth/totp/recovery-codes: replace the recovery codes, given a code from the app. The old
+// ones stop working.
+pub async fn renew_recovery_codes(
+    item: web::Json<TotpCode>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    let mut factor = match find_enabled(&data, &user)? {
+        Ok(factor) => factor,
+        Err(rejection) => return Ok(rejection),
+    };
+    if let Err(rejection) =
+        check_code(&data, &mut factor, &item.code, false, StatusCode::FORBIDDEN)?
+    {
+        return Ok(rejection);
+    }
+    let (recovery_codes, hashes) = totp::new_recovery_codes();
+    factor.recovery_codes = hashes;
+    data.commit(&[Event::TwoFactorUpdated(factor)])?;
+    Ok(HttpResponse::Ok().json(RecoveryCodes { recovery_codes }))
+}
+
+// DELETE /auth/totp: turn two-factor sign-in off, given a code from the app or a recovery code. A
+// pending enrollment is dropped without one.
+pub async fn disable_totp(
+    item: Option<web::Json<TotpCode>>,
+    user: User,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, StoreError> {
+    let _guard = data.writes.lock().unwrap();
+    let mut factor = match data.store.two_factor(user.id)? {
+        Some(factor) => factor,
+        None => return Ok(HttpResponse::NotFound().body("Two-factor sign-in is not enabled")),
+    };
+    if factor.enabled {
+        let code = match &item {
+            Some(item) => item.code.as_str(),
+            None => return Ok(HttpResponse::BadRequest().body("A two-factor code is required")),
+        };
+        if let Err(rejection) = check_code(&data, &mut factor, code, true, StatusCode::FORBIDDEN)? {
+            return Ok(rejection);
+        }
+    }
+    data.commit(&[Event::TwoFactorDeleted { user_id: user.id }])?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,62 @@
+// Revision history: every change to a to-do item is recorded alongside the change itself
+use chrono::Utc;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::models::{FieldChange, Revision, RevisionAction, TodoItem};
+use crate::store::{Event, StoreResult, TodoStore};
+
+// Fields that never change or change on every write; listing them would only add noise
+const UNTRACKED_FIELDS: &[&str] = &["id", "created_at", "updated_at"];
+
+// Build the event recording a revision of `after`, or None when an update did not change anything
+pub fn record(
+    store: &dyn TodoStore,
+    before: Option<&TodoItem>,
+    after: &TodoItem,
+    action: RevisionAction,
+    actor: &str,
+) -> StoreResult<Option<Event>> {
+    let changes = diff(before, after);
+    if changes.is_empty() && action == RevisionAction::Updated {
+        return Ok(None);
+    }
+    let number = store
+        .revisions(after.id)?
+        .last()
+        .map_or(1, |latest| latest.number + 1);
+    Ok(Some(Event::RevisionRecorded(Revision {
+        id: Uuid::new_v4(),
+        todo_id: after.id,
+        number,
+        action,
+        actor: actor.to_string(),
+        at: Utc::now(),
+        changes,
+        snapshot: after.clone(),
+    })))
+}
+
+// Compare the JSON representations of two versions field by field
+fn diff(before: Option<&TodoItem>, after: &TodoItem) -> Vec<FieldChange> {
+    let old = before.map(to_fields).unwrap_or_default();
+    let new = to_fields(after);
+    new.into_iter()
+        .filter(|(field, _)| !UNTRACKED_FIELDS.contains(&field.as_str()))
+        .filter_map(|(field, new)| {
+            let old = old.get(&field).cloned().unwrap_or(Value::Null);
+            if old == new {
+                None
+            } else {
+                Some(FieldChange { field, old, new })
+            }
+        })
+        .collect()
+}
+
+fn to_fields(todo: &TodoItem) -> serde_json::Map<String, Value> {
+    match serde_json::to_value(todo) {
+        Ok(Value::Object(fields)) => fields,
+        _ => serde_json::Map::new(),
+    }
+}
@@ -0,0 +1,287 @@
+// Sign-in with JWTs issued elsewhere, checked against the keys of a local JWKS file
+//
+// Tokens are signed with HS256, RS256 or EdDSA (Ed25519) and name their key with `kid`. Each key is
+// tied to one algorithm, so a token cannot pick a weaker one than its key was made for. The file is
+// read again whenever it changes: a new key can be added before anything signs with it, and the old
+// one dropped once its last tokens have expired, without restarting the server.
+use jsonwebtoken::jwk::{AlgorithmParameters, Jwk, JwkSet, KeyAlgorithm};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+use crate::models::Scope;
+
+pub struct JwtVerifier {
+    path: PathBuf,            // JWKS file holding the verification keys
+    issuer: Option<String>,   // Required `iss`, when set
+    audience: Option<String>, // Required `aud`, when set
+    leeway: u64,              // Seconds of clock skew tolerated on `exp` and `nbf`
+    keys: RwLock<KeySet>,     // Keys as last read from `path`
+}
+
+// The keys of the JWKS file, with the modification time of the version they were read from
+struct KeySet {
+    modified: Option<SystemTime>,
+    keys: Vec<Key>,
+}
+
+struct Key {
+    id: Option<String>,   // `kid` of the key, matched against the token header
+    algorithm: Algorithm, // The only algorithm accepted with this key
+    key: DecodingKey,
+}
+
+// Claims read from a verified token
+#[derive(Deserialize)]
+pub struct Claims {
+    pub sub: String,           // Username or id of the account the token signs in as
+    pub scope: Option<String>, // Space-separated scopes the token is limited to; reading when absent
+}
+
+impl Claims {
+    // Scopes granted by the token; names that are not scopes of this app are ignored. An issuer that
+    // says nothing about scopes only gets to read, so changes always have to be asked for.
+    pub fn scopes(&self) -> Vec<Scope> {
+        match &self.scope {
+            Some(scope) => scope.split_whitespace().filter_map(Scope::parse).collect(),
+            None => vec![Scope::TodosRead, Scope::ListsRead],
+        }
+    }
+}
+
+impl JwtVerifier {
+    // Verifier for the JWKS file in TODO_JWT_JWKS, checking `iss` against TODO_JWT_ISSUER and `aud`
+    // against TODO_JWT_AUDIENCE when they are set, and tolerating TODO_JWT_LEEWAY_SECS (default 60)
+    // of clock skew. None when TODO_JWT_JWKS is unset, which turns JWTs off.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let path = match env::var("TODO_JWT_JWKS") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => return Ok(None),
+        };
+        let leeway = match env::var("TODO_JWT_LEEWAY_SECS") {
+            Ok(value) => value
+                .parse()
+                .map_err(|_| format!("invalid second count `{}` in TODO_JWT_LEEWAY_SECS", value))?,
+            Err(_) => 60,
+        };
+        let modified = modified(&path);
+        let keys = load(&path)?;
+        log::info!("loaded {} JWT keys from {}", keys.len(), path.display());
+        Ok(Some(JwtVerifier {
+            path,
+            issuer: env::var("TODO_JWT_ISSUER").ok(),
+            audience: env::var("TODO_JWT_AUDIENCE").ok(),
+            leeway,
+            keys: RwLock::new(KeySet { modified, keys }),
+        }))
+    }
+
+    // The claims of `token`, or why it is refused
+    pub fn verify(&self, token: &str) -> Result<Claims, String> {
+        self.refresh();
+        let header = decode_header(token).map_err(|err| err.to_string())?;
+        let keys = self.keys.read().unwrap();
+        let candidates = keys.keys.iter().filter(|key| {
+            key.algorithm == header.alg
+                && (header.kid.is_none() || key.id.as_deref() == header.kid.as_deref())
+        });
+        let mut refusal = format!("no {:?} key matches kid {:?}", header.alg, header.kid);
+        for key in candidates {
+            match decode::<Claims>(token, &key.key, &self.validation(key.algorithm)) {
+                Ok(data) => return Ok(data.claims),
+                Err(err) => refusal = err.to_string(),
+            }
+        }
+        Err(refusal)
+    }
+
+    fn validation(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = self.leeway;
+        validation.validate_nbf = true;
+        let mut required = vec!["exp", "sub"];
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+            required.push("iss");
+        }
+        match &self.audience {
+            Some(audience) => {
+                validation.set_audience(&[audience]);
+                required.push("aud");
+            }
+            None => validation.validate_aud = false,
+        }
+        validation.set_required_spec_claims(&required);
+        validation
+    }
+
+    // Read the JWKS file again if it changed since it was last read. A broken file is logged and
+    // the keys read before are kept.
+    fn refresh(&self) {
+        let modified = modified(&self.path);
+        if modified == self.keys.read().unwrap().modified {
+            return;
+        }
+        let mut keys = self.keys.write().unwrap();
+        if modified == keys.modified {
+            return;
+        }
+        keys.modified = modified;
+        match load(&self.path) {
+            Ok(loaded) => {
+                log::info!(
+                    "reloaded {} JWT keys from {}",
+                    loaded.len(),
+                    self.path.display()
+                );
+                keys.keys = loaded;
+            }
+            Err(err) => log::error!("keeping the previous JWT keys: {}", err),
+        }
+    }
+}
+
+// Whether a bearer token is shaped like a JWT: three dot-separated parts
+pub fn is_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// The usable keys of the JWKS file at `path`
+fn load(path: &Path) -> Result<Vec<Key>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("cannot read JWKS file {}: {}", path.display(), err))?;
+    let set: JwkSet = serde_json::from_str(&text)
+        .map_err(|err| format!("invalid JWKS file {}: {}", path.display(), err))?;
+    set.keys
+        .iter()
+        .map(|jwk| {
+            let algorithm = algorithm(jwk).ok_or_else(|| {
+                format!(
+                    "unsupported key {:?} in {}; use HS256, RS256 or EdDSA",
+                    jwk.common.key_id,
+                    path.display()
+                )
+            })?;
+            let key = DecodingKey::from_jwk(jwk).map_err(|err| {
+                format!(
+                    "invalid key {:?} in {}: {}",
+                    jwk.common.key_id,
+                    path.display(),
+                    err
+                )
+            })?;
+            Ok(Key {
+                id: jwk.common.key_id.clone(),
+                algorithm,
+                key,
+            })
+        })
+        .collect()
+}
+
+// Algorithm a key is used with: its `alg`, or the one supported for its key type when it has none
+fn algorithm(jwk: &Jwk) -> Option<Algorithm> {
+    match (&jwk.common.key_algorithm, &jwk.algorithm) {
+        (Some(KeyAlgorithm::HS256) | None, AlgorithmParameters::OctetKey(_)) => {
+            Some(Algorithm::HS256)
+        }
+        (Some(KeyAlgorithm::RS256) | None, AlgorithmParameters::RSA(_)) => Some(Algorithm::RS256),
+        (Some(KeyAlgorithm::EdDSA) | None, AlgorithmParameters::OctetKeyPair(_)) => {
+            Some(Algorithm::EdDSA)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use chrono::Utc;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::{json, Value};
+
+    const SECRET: &[u8] = b"a shared secret of the issuer";
+
+    // A verifier trusting one HS256 key with kid "k1", without leeway
+    fn verifier(dir: &TempDir) -> JwtVerifier {
+        let path = PathBuf::from(dir.path("jwks.json"));
+        let jwks =
+            json!({"keys": [{"kty": "oct", "kid": "k1", "k": URL_SAFE_NO_PAD.encode(SECRET)}]});
+        fs::write(&path, jwks.to_string()).unwrap();
+        JwtVerifier {
+            keys: RwLock::new(KeySet {
+                modified: modified(&path),
+                keys: load(&path).unwrap(),
+            }),
+            path,
+            issuer: None,
+            audience: None,
+            leeway: 0,
+        }
+    }
+
+    fn sign(kid: &str, algorithm: Algorithm, secret: &[u8], claims: Value) -> String {
+        let mut header = Header::new(algorithm);
+        header.kid = Some(kid.to_string());
+        encode(&header, &claims, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    fn claims(offset_exp: i64, offset_nbf: i64) -> Value {
+        let now = Utc::now().timestamp();
+        json!({"sub": "ada", "exp": now + offset_exp, "nbf": now + offset_nbf})
+    }
+
+    #[test]
+    fn a_token_signed_with_the_named_key_is_accepted() {
+        let dir = TempDir::new();
+        let token = sign("k1", Algorithm::HS256, SECRET, claims(600, -60));
+        assert_eq!(verifier(&dir).verify(&token).unwrap().sub, "ada");
+    }
+
+    #[test]
+    fn a_token_naming_another_key_or_algorithm_is_refused() {
+        let dir = TempDir::new();
+        let verifier = verifier(&dir);
+        let other_kid = sign("k2", Algorithm::HS256, SECRET, claims(600, -60));
+        assert!(verifier.verify(&other_kid).is_err());
+        let other_alg = sign("k1", Algorithm::HS512, SECRET, claims(600, -60));
+        assert!(verifier.verify(&other_alg).is_err());
+        let other_secret = sign("k1", Algorithm::HS256, b"not the secret", claims(600, -60));
+        assert!(verifier.verify(&other_secret).is_err());
+    }
+
+    #[test]
+    fn a_token_outside_its_lifetime_is_refused() {
+        let dir = TempDir::new();
+        let verifier = verifier(&dir);
+        let expired = sign("k1", Algorithm::HS256, SECRET, claims(-60, -600));
+        assert!(verifier.verify(&expired).is_err());
+        let not_yet_valid = sign("k1", Algorithm::HS256, SECRET, claims(600, 60));
+        assert!(verifier.verify(&not_yet_valid).is_err());
+    }
+
+    #[test]
+    fn a_token_without_scopes_may_only_read() {
+        let unscoped = Claims {
+            sub: "ada".to_string(),
+            scope: None,
+        };
+        assert_eq!(unscoped.scopes(), [Scope::TodosRead, Scope::ListsRead]);
+        let scoped = Claims {
+            sub: "ada".to_string(),
+            scope: Some("todos:write unknown".to_string()),
+        };
+        assert_eq!(scoped.scopes(), [Scope::TodosWrite]);
+    }
+}
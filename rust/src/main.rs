@@ -1,98 +1,70 @@
 // Import necessary crates
 use actix_cors::Cors; // Cross-Origin Resource Sharing (CORS) middleware
-use actix_web::{web, App, HttpResponse, HttpServer, Responder}; // Actix Web framework for building web applications
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize}; // Serialization and deserialization for JSON payloads
-use std::sync::Mutex; // Mutex for safe concurrent access to shared state
-use uuid::Uuid; // Universally Unique Identifier (UUID) for unique todo item IDs // Chrono for handling timestamps
+use actix_web::{middleware, web, App, HttpServer}; // Actix Web framework for building web applications
 
-// Define a struct for a single To-Do item
-#[derive(Serialize, Deserialize, Clone)]
-struct TodoItem {
-    id: Uuid,                          // Unique identifier for the to-do item
-    title: String,                     // Title or description of the to-do task
-    completed: bool,                   // Status of the task (true if completed, false otherwise)
-    created_at: DateTime<Utc>,         // Timestamp for when the task was created
-    updated_at: Option<DateTime<Utc>>, // Optional timestamp for when the task was last updated
-}
+mod attachments;
+mod auth;
+mod dependencies;
+mod filter;
+mod handlers;
+mod history;
+mod jwt;
+mod markdown;
+mod models;
+mod rank;
+mod recurrence;
+mod reminders;
+mod search;
+mod share_links;
+mod sharing;
+mod state;
+mod store;
+mod totp;
+mod trash;
+mod tree;
 
-// Struct for handling create to-do request payload
-#[derive(Deserialize)]
-struct CreateTodoItem {
-    title: String,   // Title of the new to-do task
-    completed: bool, // Initial status of the task
-}
+#[cfg(test)]
+mod testing;
 
-// Struct for handling update to-do request payload
-#[derive(Deserialize)]
-struct UpdateTodoItem {
-    title: Option<String>,   // Optional new title for the task
-    completed: Option<bool>, // Optional new completion status
-}
+use handlers::{
+    accept_invitation, add_comment, add_list, add_list_todo, add_smart_list, add_todo,
+    cancel_invitation, change_member_role, confirm_totp, create_share_link, create_token,
+    decline_invitation, delete_attachment, delete_comment, delete_list, delete_smart_list,
+    delete_tag, delete_todo, disable_totp, download_attachment, enroll_totp, get_actionable,
+    get_attachments, get_audit_log, get_comments, get_due_today, get_history, get_invitations,
+    get_list, get_list_invitations, get_list_todos, get_lists, get_me, get_members, get_overdue,
+    get_prerequisites, get_share_links, get_shared, get_smart_list, get_smart_list_todos,
+    get_smart_lists, get_tags, get_todo_tree, get_todos, get_tokens, get_totp, get_trash,
+    get_upcoming, invite, login, logout, merge_tag, move_todo, purge_todo, register, remove_member,
+    rename_tag, renew_recovery_codes, restore_todo, revert_todo, revoke_share_link, revoke_token,
+    search_todos, update_checklist_item, update_comment, update_list, update_smart_list,
+    update_todo, upload_attachments,
+};
+use auth::{needs, session_only};
+use models::Scope::{ListsRead, ListsWrite, TodosRead, TodosWrite};
+use state::AppState;
 
-// Application state shared across multiple requests
-struct AppState {
-    todo_list: Mutex<Vec<TodoItem>>, // Mutex-protected vector to store to-do items safely across multiple threads
-}
-
-// Asynchronous function to handle GET requests for fetching to-do items
-async fn get_todos(data: web::Data<AppState>) -> impl Responder {
-    // Lock the mutex to safely access the shared state across multiple threads
-    let todos = data.todo_list.lock().unwrap();
-
-    // Return an HTTP response with the list of to-do items serialized as JSON
-    HttpResponse::Ok().json(&*todos)
-}
-
-// Asynchronous function to handle POST requests for creating a new to-do item
-async fn add_todo(item: web::Json<CreateTodoItem>, data: web::Data<AppState>) -> impl Responder {
-    let mut todos = data.todo_list.lock().unwrap(); // Lock the mutex to safely access the shared state
-    let new_todo = TodoItem {
-        id: Uuid::new_v4(),        // Generate a new UUID for the to-do item
-        title: item.title.clone(), // Set the title of the to-do item
-        completed: item.completed, // Set the completion status of the to-do item
-        created_at: Utc::now(),    // Set the creation timestamp of the to-do item
-        updated_at: None,          // Set the update timestamp to None initially
-    };
-    todos.push(new_todo); // Add the new to-do item to the list
-    HttpResponse::Ok().json(todos.clone()) // Return an HTTP response with the updated list of to-do items
-}
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
-async fn update_todo(
-    path: web::Path<Uuid>,
-    item: web::Json<UpdateTodoItem>,
-    data: web::Data<AppState>,
-) -> impl Responder {
-    let mut todos = data.todo_list.lock().unwrap();
-    if let Some(todo) = todos.iter_mut().find(|todo| todo.id == *path) {
-        if let Some(title) = &item.title {
-            todo.title = title.clone();
-        }
-        if let Some(completed) = item.completed {
-            todo.completed = completed;
-        }
-        todo.updated_at = Some(Utc::now());
-        HttpResponse::Ok().json(todo.clone())
-    } else {
-        HttpResponse::NotFound().body("Todo item not found")
+    let store = store::open_from_env().map_err(std::io::Error::other)?;
+    let blobs = attachments::BlobStore::from_env()?;
+    let session_ttl = auth::session_ttl_from_env().map_err(std::io::Error::other)?;
+    let jwt = jwt::JwtVerifier::from_env().map_err(std::io::Error::other)?;
+    let share_signer = share_links::ShareSigner::from_env().map_err(std::io::Error::other)?;
+    let app_state = web::Data::new(
+        AppState::new(store, blobs, session_ttl, jwt, share_signer)
+            .map_err(std::io::Error::other)?,
+    );
+    if let Some(retention) = trash::retention_from_env().map_err(std::io::Error::other)? {
+        trash::spawn_purger(app_state.clone(), retention);
     }
-}
-
-async fn delete_todo(path: web::Path<Uuid>, data: web::Data<AppState>) -> impl Responder {
-    let mut todos = data.todo_list.lock().unwrap();
-    if todos.iter().any(|todo| todo.id == *path) {
-        todos.retain(|todo| todo.id != *path);
-        HttpResponse::Ok().json(todos.clone())
-    } else {
-        HttpResponse::NotFound().body("Todo item not found")
+    let reminder_sinks = reminders::sinks_from_env().map_err(std::io::Error::other)?;
+    if !reminder_sinks.is_empty() {
+        let tz = reminders::time_zone_from_env().map_err(std::io::Error::other)?;
+        reminders::spawn_scheduler(app_state.clone(), reminder_sinks, tz);
     }
-}
-
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    let app_state = web::Data::new(AppState {
-        todo_list: Mutex::new(Vec::new()),
-    });
 
     HttpServer::new(move || {
         let cors = Cors::default()
@@ -103,11 +75,81 @@ async fn main() -> std::io::Result<()> {
 
     App::new()
         .app_data(app_state.clone())
+        .wrap(middleware::from_fn(auth::authenticate)) // Every route below needs a session, API token or JWT, except registering and logging in
         .wrap(cors)
-        .route("/todos", web::get().to(get_todos))
-        .route("/todos", web::post().to(add_todo))
-        .route("/todos/{id}", web::put().to(update_todo))
-        .route("/todos/{id}", web::delete().to(delete_todo))
+        .route("/auth/register", web::post().to(register))
+        .route("/auth/login", web::post().to(login))
+        .route("/auth/logout", web::post().guard(session_only()).to(logout))
+        .route("/auth/me", web::get().to(get_me))
+        .route("/auth/tokens", web::get().guard(session_only()).to(get_tokens))
+        .route("/auth/tokens", web::post().guard(session_only()).to(create_token))
+        .route("/auth/tokens/{id}", web::delete().guard(session_only()).to(revoke_token))
+        .route("/auth/totp", web::get().guard(session_only()).to(get_totp))
+        .route("/auth/totp", web::post().guard(session_only()).to(enroll_totp))
+        .route("/auth/totp", web::delete().guard(session_only()).to(disable_totp))
+        .route("/auth/totp/confirm", web::post().guard(session_only()).to(confirm_totp))
+        .route(
+            "/auth/totp/recovery-codes",
+            web::post().guard(session_only()).to(renew_recovery_codes),
+        )
+        .route("/todos", web::get().guard(needs(TodosRead)).to(get_todos))
+        .route("/todos", web::post().guard(needs(TodosWrite)).to(add_todo))
+        .route("/todos/search", web::get().guard(needs(TodosRead)).to(search_todos))
+        .route("/todos/overdue", web::get().guard(needs(TodosRead)).to(get_overdue))
+        .route("/todos/due-today", web::get().guard(needs(TodosRead)).to(get_due_today))
+        .route("/todos/upcoming", web::get().guard(needs(TodosRead)).to(get_upcoming))
+        .route("/todos/actionable", web::get().guard(needs(TodosRead)).to(get_actionable))
+        .route("/todos/{id}", web::put().guard(needs(TodosWrite)).to(update_todo))
+        .route("/todos/{id}", web::delete().guard(needs(TodosWrite)).to(delete_todo))
+        .route("/todos/{id}/tree", web::get().guard(needs(TodosRead)).to(get_todo_tree))
+        .route("/todos/{id}/move", web::post().guard(needs(TodosWrite)).to(move_todo))
+        .route("/todos/{id}/checklist/{item_id}", web::put().guard(needs(TodosWrite)).to(update_checklist_item))
+        .route("/todos/{id}/attachments", web::get().guard(needs(TodosRead)).to(get_attachments))
+        .route("/todos/{id}/attachments", web::post().guard(needs(TodosWrite)).to(upload_attachments))
+        .route("/todos/{id}/attachments/{attachment_id}", web::get().guard(needs(TodosRead)).to(download_attachment))
+        .route("/todos/{id}/attachments/{attachment_id}", web::delete().guard(needs(TodosWrite)).to(delete_attachment))
+        .route("/todos/{id}/comments", web::get().guard(needs(TodosRead)).to(get_comments))
+        .route("/todos/{id}/comments", web::post().guard(needs(TodosWrite)).to(add_comment))
+        .route("/todos/{id}/comments/{comment_id}", web::put().guard(needs(TodosWrite)).to(update_comment))
+        .route("/todos/{id}/comments/{comment_id}", web::delete().guard(needs(TodosWrite)).to(delete_comment))
+        .route("/todos/{id}/prerequisites", web::get().guard(needs(TodosRead)).to(get_prerequisites))
+        .route("/todos/{id}/history", web::get().guard(needs(TodosRead)).to(get_history))
+        .route("/todos/{id}/history/{revision}/revert", web::post().guard(needs(TodosWrite)).to(revert_todo))
+        .route("/todos/{id}/restore", web::post().guard(needs(TodosWrite)).to(restore_todo))
+        .route("/trash", web::get().guard(needs(TodosRead)).to(get_trash))
+        .route("/trash/{id}", web::delete().guard(needs(TodosWrite)).to(purge_todo))
+        .route("/tags", web::get().guard(needs(TodosRead)).to(get_tags))
+        .route("/tags/{name}", web::put().guard(needs(TodosWrite)).to(rename_tag))
+        .route("/tags/{name}", web::delete().guard(needs(TodosWrite)).to(delete_tag))
+        .route("/tags/{name}/merge", web::post().guard(needs(TodosWrite)).to(merge_tag))
+        .route("/smart-lists", web::get().guard(needs(ListsRead)).to(get_smart_lists))
+        .route("/smart-lists", web::post().guard(needs(ListsWrite)).to(add_smart_list))
+        .route("/smart-lists/{id}", web::get().guard(needs(ListsRead)).to(get_smart_list))
+        .route("/smart-lists/{id}", web::put().guard(needs(ListsWrite)).to(update_smart_list))
+        .route("/smart-lists/{id}", web::delete().guard(needs(ListsWrite)).to(delete_smart_list))
+        .route("/smart-lists/{id}/todos", web::get().guard(needs(ListsRead)).to(get_smart_list_todos))
+        .route("/lists", web::get().guard(needs(ListsRead)).to(get_lists))
+        .route("/lists", web::post().guard(needs(ListsWrite)).to(add_list))
+        .route("/lists/{id}", web::get().guard(needs(ListsRead)).to(get_list))
+        .route("/lists/{id}", web::put().guard(needs(ListsWrite)).to(update_list))
+        .route("/lists/{id}", web::delete().guard(needs(ListsWrite)).to(delete_list))
+        .route("/lists/{id}/todos", web::get().guard(needs(ListsRead)).to(get_list_todos))
+        .route("/lists/{id}/todos", web::post().guard(needs(TodosWrite)).to(add_list_todo))
+        .route("/lists/{id}/members", web::get().guard(needs(ListsRead)).to(get_members))
+        .route("/lists/{id}/members/{user_id}", web::put().guard(needs(ListsWrite)).to(change_member_role))
+        .route("/lists/{id}/members/{user_id}", web::delete().guard(needs(ListsWrite)).to(remove_member))
+        .route("/lists/{id}/invitations", web::get().guard(needs(ListsRead)).to(get_list_invitations))
+        .route("/lists/{id}/invitations", web::post().guard(needs(ListsWrite)).to(invite))
+        .route("/lists/{id}/invitations/{invitation_id}", web::delete().guard(needs(ListsWrite)).to(cancel_invitation))
+        .route("/lists/{id}/audit", web::get().guard(needs(ListsRead)).to(get_audit_log))
+        .route("/invitations", web::get().guard(needs(ListsRead)).to(get_invitations))
+        .route("/invitations/{id}/accept", web::post().guard(needs(ListsWrite)).to(accept_invitation))
+        .route("/invitations/{id}/decline", web::post().guard(needs(ListsWrite)).to(decline_invitation))
+        .route("/share-links", web::get().guard(session_only()).to(get_share_links))
+        .route("/share-links", web::post().guard(session_only()).to(create_share_link))
+        .route("/share-links/{id}", web::delete().guard(session_only()).to(revoke_share_link))
+        .route("/shared/{token}", web::get().to(get_shared)) // Public: the token is the credential
+        .default_service(web::to(auth::unmatched)) // 404, or 403 when an API token lacks the scope a route needs
     })
     .bind("127.0.0.1:8080") ? .run().await // ? is for error handling in rust (reminder)
 }
\ No newline at end of file
@@ -0,0 +1,21 @@
+// Markdown notes rendered to HTML that is safe to put straight into a page
+use pulldown_cmark::{html, Options, Parser};
+
+// Render CommonMark with the usual GitHub extensions, then strip anything that could run script
+// or break out of the surrounding markup
+pub fn render(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(markdown, options));
+    // Task list items come out as disabled checkboxes, which are the only inputs let through
+    ammonia::Builder::default()
+        .add_tags(&["input"])
+        .add_tag_attribute_values("input", "checked", &[""])
+        .set_tag_attribute_value("input", "type", "checkbox")
+        .set_tag_attribute_value("input", "disabled", "")
+        .clean(&unsafe_html)
+        .to_string()
+}
@@ -0,0 +1,1191 @@
+// Data types exchanged with clients and kept by the stores
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, Serialize}; // Serialization and deserialization for JSON payloads
+use std::collections::{BTreeSet, HashMap, HashSet};
+use uuid::Uuid; // Universally Unique Identifier (UUID) for unique todo item IDs
+
+use crate::markdown;
+use crate::recurrence::Recurrence;
+use crate::store::SortField;
+
+// Define a struct for a single To-Do item
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TodoItem {
+    pub id: Uuid,                          // Unique identifier for the to-do item
+    pub title: String,                     // Title or description of the to-do task
+    pub completed: bool,                   // Status of the task (true if completed, false otherwise)
+    pub created_at: DateTime<Utc>,         // Timestamp for when the task was created
+    pub updated_at: Option<DateTime<Utc>>, // Optional timestamp for when the task was last updated
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>, // Set while the task sits in the trash
+    #[serde(default)]
+    pub due_at: Option<Schedule>,          // When the task is due, if it has a deadline
+    #[serde(default)]
+    pub start_at: Option<Schedule>,        // When work on the task can start, if it cannot right away
+    #[serde(default)]
+    pub priority: Priority,                // How urgent the task is
+    #[serde(default)]
+    pub tags: BTreeSet<String>,            // Labels grouping the task with others, normalized by `normalize_tag`
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,           // Task this one is a subtask of, if any
+    #[serde(default)]
+    pub blocked_by: BTreeSet<Uuid>,        // Tasks that have to be completed before this one can be
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,    // Rule after which the task comes back once completed
+    #[serde(default)]
+    pub repeat_from: RepeatFrom,           // What the due date of the next instance is worked out from
+    #[serde(default)]
+    pub reminders: BTreeSet<u32>,          // When to send reminders, in minutes before the due date
+    #[serde(default)]
+    pub reminded: BTreeSet<u32>,           // Reminders already sent for the current due date
+    #[serde(default)]
+    pub list_id: Option<Uuid>,             // List the task belongs to, if any
+    #[serde(default)]
+    pub rank: String,                      // Place in the manual order, see `crate::rank`
+    #[serde(default)]
+    pub notes: String,                     // Longer description in Markdown
+    #[serde(default)]
+    pub checklist: Vec<ChecklistItem>,     // Steps ticked off one by one, in order
+    #[serde(default)]
+    pub owner_id: Option<Uuid>,            // Account the task belongs to; none only for tasks from before accounts
+}
+
+// Struct for handling create to-do request payload
+#[derive(Deserialize)]
+pub struct CreateTodoItem {
+    pub title: String,                  // Title of the new to-do task
+    pub completed: bool,                // Initial status of the task
+    #[serde(default)]
+    pub due_at: Option<Schedule>,       // Optional deadline
+    #[serde(default)]
+    pub start_at: Option<Schedule>,     // Optional start date
+    #[serde(default)]
+    pub priority: Priority,             // Initial priority (none by default)
+    #[serde(default)]
+    pub tags: BTreeSet<String>,         // Initial tags
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,        // Task to file the new one under as a subtask
+    #[serde(default)]
+    pub blocked_by: BTreeSet<Uuid>,     // Tasks the new one waits for
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>, // Recurrence rule, e.g. FREQ=WEEKLY;BYDAY=MO
+    #[serde(default)]
+    pub repeat_from: RepeatFrom,        // Due date (default) or completion date
+    #[serde(default)]
+    pub reminders: BTreeSet<u32>,       // Reminder offsets in minutes before the due date
+    #[serde(default)]
+    pub list_id: Option<Uuid>,          // List to add the task to
+    #[serde(default)]
+    pub notes: String,                  // Markdown description
+    #[serde(default)]
+    pub checklist: Vec<ChecklistEntry>, // Initial checklist
+}
+
+// Struct for handling update to-do request payload
+#[derive(Deserialize)]
+pub struct UpdateTodoItem {
+    pub title: Option<String>,                  // Optional new title for the task
+    pub completed: Option<bool>,                // Optional new completion status
+    #[serde(default, deserialize_with = "nullable")]
+    pub due_at: Option<Option<Schedule>>,       // New deadline; null removes it
+    #[serde(default, deserialize_with = "nullable")]
+    pub start_at: Option<Option<Schedule>>,     // New start date; null removes it
+    pub priority: Option<Priority>,             // Optional new priority
+    pub tags: Option<BTreeSet<String>>,         // Optional new set of tags, replacing the current one
+    #[serde(default, deserialize_with = "nullable")]
+    pub parent_id: Option<Option<Uuid>>,        // New parent task; null makes it a top-level task
+    pub blocked_by: Option<BTreeSet<Uuid>>,     // Optional new set of blocking tasks, replacing the current one
+    #[serde(default, deserialize_with = "nullable")]
+    pub recurrence: Option<Option<Recurrence>>, // New recurrence rule; null stops the task from recurring
+    pub repeat_from: Option<RepeatFrom>,        // What the next instance is due relative to
+    pub reminders: Option<BTreeSet<u32>>,       // Optional new set of reminder offsets, replacing the current one
+    #[serde(default, deserialize_with = "nullable")]
+    pub list_id: Option<Option<Uuid>>,          // List to move the task to; null takes it out of its list
+    pub notes: Option<String>,                  // Optional new Markdown description
+    pub checklist: Option<Vec<ChecklistEntry>>, // Optional new checklist, replacing the current one
+}
+
+// One step of a checklist
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ChecklistItem {
+    pub id: Uuid,     // Identifier of the step within its item
+    pub text: String, // What to do
+    pub done: bool,   // Whether the step has been ticked off
+}
+
+// A checklist step in a create or update payload. Steps that give the id of an existing one keep it.
+#[derive(Deserialize)]
+pub struct ChecklistEntry {
+    pub id: Option<Uuid>, // Id of the step this entry replaces, if any
+    pub text: String,     // What to do
+    #[serde(default)]
+    pub done: bool,       // Whether the step has been ticked off
+}
+
+// Payload for changing a single checklist step
+#[derive(Deserialize)]
+pub struct UpdateChecklistItem {
+    pub text: Option<String>, // Optional new text
+    pub done: Option<bool>,   // Tick the step off or reopen it
+}
+
+// Why a checklist in a payload cannot be saved, if it cannot
+pub fn checklist_error(entries: &[ChecklistEntry]) -> Option<&'static str> {
+    let mut ids = HashSet::new();
+    for entry in entries {
+        if entry.text.trim().is_empty() {
+            return Some("Checklist items must have a text");
+        }
+        if entry.id.is_some_and(|id| !ids.insert(id)) {
+            return Some("Checklist item ids must be unique");
+        }
+    }
+    None
+}
+
+// Turn the entries of a payload into checklist steps, giving new steps an id
+fn checklist_items(entries: &[ChecklistEntry]) -> Vec<ChecklistItem> {
+    entries
+        .iter()
+        .map(|entry| ChecklistItem {
+            id: entry.id.unwrap_or_else(Uuid::new_v4),
+            text: entry.text.trim().to_string(),
+            done: entry.done,
+        })
+        .collect()
+}
+
+// How urgent a task is, from least to most
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl Priority {
+    pub fn name(self) -> &'static str {
+        match self {
+            Priority::None => "none",
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [Priority::None, Priority::Low, Priority::Medium, Priority::High]
+            .iter()
+            .copied()
+            .find(|priority| priority.name().eq_ignore_ascii_case(name))
+    }
+}
+
+// What the due date of the next instance of a recurring task is worked out from
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RepeatFrom {
+    #[default]
+    Due,        // The due date of the completed instance, so the schedule stays fixed
+    Completion, // The day the instance was completed, e.g. for chores due a week after they were last done
+}
+
+impl RepeatFrom {
+    pub fn name(self) -> &'static str {
+        match self {
+            RepeatFrom::Due => "due",
+            RepeatFrom::Completion => "completion",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [RepeatFrom::Due, RepeatFrom::Completion]
+            .iter()
+            .copied()
+            .find(|repeat_from| repeat_from.name().eq_ignore_ascii_case(name))
+    }
+}
+
+// Canonical form of a tag: trimmed and lowercased. Empty tags and tags containing commas (which
+// separate tags in query strings) are rejected.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() || tag.contains(',') {
+        None
+    } else {
+        Some(tag)
+    }
+}
+
+fn normalize_tags(tags: &BTreeSet<String>) -> BTreeSet<String> {
+    tags.iter().filter_map(|tag| normalize_tag(tag)).collect()
+}
+
+// Tell a field left out of an update payload (None) apart from one explicitly set to null (Some(None))
+fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+// A due or start date: either a whole calendar day or a precise moment.
+// In JSON it is a date ("2024-05-01") or an RFC 3339 timestamp ("2024-05-01T17:00:00+02:00").
+// Timed values keep the offset they were given in; all-day values float, meaning they cover that
+// day in whatever time zone they are looked at from.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(untagged)]
+pub enum Schedule {
+    AllDay(NaiveDate),
+    Timed(DateTime<FixedOffset>),
+}
+
+impl Schedule {
+    // Time span covered, seen from `tz`: a single instant (no end) or a day [start, end)
+    pub fn span(&self, tz: Tz) -> (DateTime<Utc>, Option<DateTime<Utc>>) {
+        match self {
+            Schedule::Timed(time) => (time.with_timezone(&Utc), None),
+            Schedule::AllDay(date) => (
+                start_of_day(*date, tz),
+                Some(start_of_day(*date + Duration::days(1), tz)),
+            ),
+        }
+    }
+
+    // Calendar day it falls on in `tz`
+    pub fn date_in(&self, tz: Tz) -> NaiveDate {
+        match self {
+            Schedule::Timed(time) => time.with_timezone(&tz).date_naive(),
+            Schedule::AllDay(date) => *date,
+        }
+    }
+
+    // Whether the moment has fully passed at `now`: all-day values only once their day is over
+    pub fn has_passed(&self, now: DateTime<Utc>, tz: Tz) -> bool {
+        let (start, end) = self.span(tz);
+        end.unwrap_or(start) <= now
+    }
+
+    // Text form used by the stores, the same as the JSON one
+    pub fn encode(&self) -> String {
+        match self {
+            Schedule::AllDay(date) => date.format("%Y-%m-%d").to_string(),
+            Schedule::Timed(time) => time.to_rfc3339(),
+        }
+    }
+
+    pub fn decode(text: &str) -> Option<Self> {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .map(Schedule::AllDay)
+            .or_else(|_| DateTime::parse_from_rfc3339(text).map(Schedule::Timed))
+            .ok()
+    }
+}
+
+// First instant of a calendar day in `tz`; if a DST gap swallows midnight, the first hour that exists
+pub fn start_of_day(date: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+    (0..3)
+        .find_map(|hours| {
+            tz.from_local_datetime(&(midnight + Duration::hours(hours)))
+                .earliest()
+        })
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| midnight.and_utc())
+}
+
+impl TodoItem {
+    // Build a new to-do item from a create payload
+    pub fn new(item: &CreateTodoItem, owner_id: Uuid) -> Self {
+        TodoItem {
+            id: Uuid::new_v4(),                          // Generate a new UUID for the to-do item
+            title: item.title.clone(),                   // Set the title of the to-do item
+            completed: item.completed,                   // Set the completion status of the to-do item
+            created_at: Utc::now(),                      // Set the creation timestamp of the to-do item
+            updated_at: None,                            // Set the update timestamp to None initially
+            deleted_at: None,                            // New items are never in the trash
+            due_at: item.due_at,                         // Set the deadline, if any
+            start_at: item.start_at,                     // Set the start date, if any
+            priority: item.priority,                     // Set the priority
+            tags: normalize_tags(&item.tags),            // Set the tags
+            parent_id: item.parent_id,                   // Set the parent task, if any
+            blocked_by: item.blocked_by.clone(),         // Set the blocking tasks
+            recurrence: item.recurrence.clone(),         // Set the recurrence rule, if any
+            repeat_from: item.repeat_from,               // Set what the next instance is due relative to
+            reminders: item.reminders.clone(),           // Set the reminder offsets
+            reminded: BTreeSet::new(),                   // No reminder has been sent yet
+            list_id: item.list_id,                       // Set the list, if any
+            rank: String::new(),                         // Ranked by the handler, which knows the other items
+            notes: item.notes.clone(),                   // Set the description
+            checklist: checklist_items(&item.checklist), // Set the checklist
+            owner_id: Some(owner_id),                    // Set the account it belongs to
+        }
+    }
+
+    // Apply the fields present in an update payload and bump the update timestamp
+    pub fn apply(&mut self, changes: &UpdateTodoItem) {
+        if let Some(title) = &changes.title {
+            self.title = title.clone();
+        }
+        if let Some(completed) = changes.completed {
+            self.completed = completed;
+        }
+        if let Some(due_at) = changes.due_at {
+            if due_at != self.due_at {
+                self.reminded.clear(); // Reminders for the new due date are still to come
+            }
+            self.due_at = due_at;
+        }
+        if let Some(start_at) = changes.start_at {
+            self.start_at = start_at;
+        }
+        if let Some(priority) = changes.priority {
+            self.priority = priority;
+        }
+        if let Some(tags) = &changes.tags {
+            self.tags = normalize_tags(tags);
+        }
+        if let Some(parent_id) = changes.parent_id {
+            self.parent_id = parent_id;
+        }
+        if let Some(blocked_by) = &changes.blocked_by {
+            self.blocked_by = blocked_by.clone();
+        }
+        if let Some(recurrence) = &changes.recurrence {
+            self.recurrence = recurrence.clone();
+        }
+        if let Some(repeat_from) = changes.repeat_from {
+            self.repeat_from = repeat_from;
+        }
+        if let Some(reminders) = &changes.reminders {
+            self.reminders = reminders.clone();
+            self.reminded.retain(|minutes| reminders.contains(minutes));
+        }
+        if let Some(list_id) = changes.list_id {
+            self.list_id = list_id;
+        }
+        if let Some(notes) = &changes.notes {
+            self.notes = notes.clone();
+        }
+        if let Some(checklist) = &changes.checklist {
+            self.checklist = checklist_items(checklist);
+        }
+        self.updated_at = Some(Utc::now());
+    }
+
+    // Whether the item may only start after it is due, which clients are not allowed to save
+    pub fn starts_after_due(&self) -> bool {
+        match (self.start_at, self.due_at) {
+            (Some(start_at), Some(due_at)) => {
+                let (due_start, due_end) = due_at.span(Tz::UTC);
+                start_at.span(Tz::UTC).0 > due_end.unwrap_or(due_start)
+            }
+            _ => false,
+        }
+    }
+
+    // Copy the editable contents of an earlier version back onto this item. The list, the place in
+    // the subtask hierarchy and the blocking tasks are left alone, since the old links may point at
+    // things that are gone by now or would form a cycle. So is completion, which only an update may
+    // change: completing checks the blocking tasks and brings up the next occurrence.
+    pub fn restore_from(&mut self, earlier: &TodoItem) {
+        self.title = earlier.title.clone();
+        if self.due_at != earlier.due_at {
+            self.reminded.clear();
+        }
+        self.due_at = earlier.due_at;
+        self.start_at = earlier.start_at;
+        self.priority = earlier.priority;
+        self.tags = earlier.tags.clone();
+        self.recurrence = earlier.recurrence.clone();
+        self.repeat_from = earlier.repeat_from;
+        self.reminders = earlier.reminders.clone();
+        self.reminded.retain(|minutes| earlier.reminders.contains(minutes));
+        self.notes = earlier.notes.clone();
+        self.checklist = earlier.checklist.clone();
+        self.updated_at = Some(Utc::now());
+    }
+}
+
+// What kind of change produced a revision
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RevisionAction {
+    Created,  // The item was added
+    Updated,  // One or more fields were edited
+    Reverted, // The item was rolled back to an earlier revision
+    Deleted,  // The item was moved to the trash
+    Restored, // The item was taken back out of the trash
+    Purged,   // The item was permanently removed from the trash
+    Moved,    // The item was given another place in the manual order
+}
+
+// A single field that changed between two revisions
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FieldChange {
+    pub field: String,          // Name of the field as it appears in the JSON representation
+    pub old: serde_json::Value, // Value before the change (null for newly created items)
+    pub new: serde_json::Value, // Value after the change
+}
+
+// One entry in the history of a to-do item
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Revision {
+    pub id: Uuid,                  // Unique identifier for the revision
+    pub todo_id: Uuid,             // The to-do item this revision belongs to
+    pub number: u32,               // Position in the item's history, starting at 1
+    pub action: RevisionAction,    // What kind of change this was
+    pub actor: String,             // Who made the change
+    pub at: DateTime<Utc>,         // When the change was made
+    pub changes: Vec<FieldChange>, // Fields that differ from the previous revision
+    pub snapshot: TodoItem,        // Full state of the item after the change, used for reverts
+}
+
+// Direction of the ordering requested by a client
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+// Query string accepted by GET /todos
+#[derive(Deserialize)]
+pub struct ListTodosParams {
+    pub completed: Option<bool>,                // Only items with this completion status
+    pub q: Option<String>,                      // Only items whose title contains this text, ignoring case
+    pub created_after: Option<DateTime<Utc>>,   // Only items created at or after this time
+    pub created_before: Option<DateTime<Utc>>,  // Only items created before this time
+    pub updated_after: Option<DateTime<Utc>>,   // Only items last updated at or after this time
+    pub updated_before: Option<DateTime<Utc>>,  // Only items last updated before this time
+    #[serde(default)]
+    pub sort: SortField,                        // Field to order by (position, created_at, updated_at or title)
+    #[serde(default)]
+    pub order: SortOrder,                       // asc or desc
+    pub tag: Option<String>,                    // Only items carrying all of these comma-separated tags
+    pub priority: Option<Priority>,             // Only items with this priority
+    pub filter: Option<String>,                 // Filter expression, see `crate::filter`
+    pub tz: Option<String>,                     // IANA time zone the filter's dates are read in (UTC by default)
+    pub list: Option<String>,                   // Only items in the list with this id, or in no list for `none`
+    pub render: Option<Render>,                 // Also return the notes rendered this way
+    pub limit: Option<usize>,                   // Page size
+    pub cursor: Option<String>,                 // `next_cursor` of the previous page
+}
+
+// One page of to-do items
+#[derive(Serialize)]
+pub struct TodoPage<T = TodoItem> {
+    pub items: Vec<T>,               // Items on this page
+    pub next_cursor: Option<String>, // Pass as `cursor` to fetch the next page; null on the last page
+}
+
+impl TodoPage {
+    // Ids of the items on this page
+    pub fn ids(&self) -> Vec<Uuid> {
+        self.items.iter().map(|todo| todo.id).collect()
+    }
+
+    // The same page with every item wrapped for the response, see `TodoView`. Items missing from
+    // `comment_counts` have no comments.
+    pub fn render(
+        self,
+        render: Option<Render>,
+        comment_counts: &HashMap<Uuid, usize>,
+    ) -> TodoPage<TodoView> {
+        TodoPage {
+            items: self
+                .items
+                .into_iter()
+                .map(|todo| {
+                    let comment_count = comment_counts.get(&todo.id).copied().unwrap_or(0);
+                    TodoView::new(todo, render, comment_count)
+                })
+                .collect(),
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+// Form notes can be returned in besides Markdown
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Render {
+    Html, // Sanitized HTML in `notes_html`
+}
+
+// A to-do item as listings return it, with the size of its comment thread and its notes rendered
+// if the client asked for that
+#[derive(Serialize)]
+pub struct TodoView {
+    #[serde(flatten)]
+    pub todo: TodoItem,             // The item itself
+    pub comment_count: usize,       // Number of comments on the item
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes_html: Option<String>, // The notes as sanitized HTML, for `render=html`
+}
+
+impl TodoView {
+    pub fn new(todo: TodoItem, render: Option<Render>, comment_count: usize) -> Self {
+        let notes_html = render.map(|Render::Html| markdown::render(&todo.notes));
+        TodoView {
+            todo,
+            comment_count,
+            notes_html,
+        }
+    }
+}
+
+// Query string of GET /todos/search
+#[derive(Deserialize)]
+pub struct SearchParams {
+    pub q: String,              // Words to look for; the last one may be incomplete
+    pub limit: Option<usize>,   // Maximum number of results
+    pub render: Option<Render>, // Also return the notes rendered this way
+}
+
+// A to-do item matching a search, along with how relevant it is
+#[derive(Serialize)]
+pub struct SearchResult {
+    pub score: f64,     // Relevance; higher is better
+    #[serde(flatten)]
+    pub todo: TodoView, // The matching item
+}
+
+// A saved search; the items it matches are worked out every time it is read
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SmartList {
+    pub id: Uuid,                          // Unique identifier for the smart list
+    pub name: String,                      // Name shown to the user
+    pub filter: String,                    // Filter expression selecting its items, see `crate::filter`
+    pub created_at: DateTime<Utc>,         // Timestamp for when the smart list was created
+    pub updated_at: Option<DateTime<Utc>>, // Timestamp for when it was last changed
+    #[serde(default)]
+    pub owner_id: Option<Uuid>,            // Account the smart list belongs to
+}
+
+// Payload for creating a smart list
+#[derive(Deserialize)]
+pub struct CreateSmartList {
+    pub name: String,   // Name of the new smart list
+    pub filter: String, // Filter expression selecting its items
+}
+
+// Payload for changing a smart list
+#[derive(Deserialize)]
+pub struct UpdateSmartList {
+    pub name: Option<String>,   // Optional new name
+    pub filter: Option<String>, // Optional new filter expression
+}
+
+impl SmartList {
+    pub fn new(list: &CreateSmartList, owner_id: Uuid) -> Self {
+        SmartList {
+            id: Uuid::new_v4(),
+            name: list.name.clone(),
+            filter: list.filter.clone(),
+            created_at: Utc::now(),
+            updated_at: None,
+            owner_id: Some(owner_id),
+        }
+    }
+
+    // Apply the fields present in an update payload and bump the update timestamp
+    pub fn apply(&mut self, changes: &UpdateSmartList) {
+        if let Some(name) = &changes.name {
+            self.name = name.clone();
+        }
+        if let Some(filter) = &changes.filter {
+            self.filter = filter.clone();
+        }
+        self.updated_at = Some(Utc::now());
+    }
+}
+
+// A file attached to a to-do item; its contents live in the blob store, see `crate::attachments`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Attachment {
+    pub id: Uuid,                  // Unique identifier for the attachment
+    pub todo_id: Uuid,             // Item the file is attached to
+    pub file_name: String,         // Name the file was uploaded with, without any directories
+    pub content_type: String,      // MIME type sniffed from the contents
+    pub size: u64,                 // Length of the contents in bytes
+    pub digest: String,            // Hex SHA-256 of the contents, which names the blob
+    pub created_at: DateTime<Utc>, // Timestamp for when the file was uploaded
+}
+
+// A remark left on a to-do item, oldest first in its thread
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Comment {
+    pub id: Uuid,                         // Unique identifier for the comment
+    pub todo_id: Uuid,                    // Item the comment is on
+    pub author: String,                   // Actor who wrote it; only they may edit or delete it
+    pub body: String,                     // Text of the comment
+    pub created_at: DateTime<Utc>,        // Timestamp for when the comment was posted
+    pub edited_at: Option<DateTime<Utc>>, // Timestamp for when the body was last changed, if ever
+}
+
+// Body of POST /todos/{id}/comments and PUT /todos/{id}/comments/{comment_id}
+#[derive(Deserialize)]
+pub struct CommentBody {
+    pub body: String, // Text of the comment, trimmed and not empty
+}
+
+// A named list (project) grouping to-do items
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TodoList {
+    pub id: Uuid,                          // Unique identifier for the list
+    pub name: String,                      // Name shown to the user
+    pub color: Option<String>,             // Color the list is shown in, as #rrggbb
+    pub archived: bool,                    // Archived lists are kept but take no new items
+    pub position: u32,                     // Place among the other lists of its owner, lowest first
+    pub created_at: DateTime<Utc>,         // Timestamp for when the list was created
+    pub updated_at: Option<DateTime<Utc>>, // Timestamp for when it was last changed
+    #[serde(default)]
+    pub owner_id: Option<Uuid>,            // Account the list belongs to
+}
+
+// Payload for creating a list
+#[derive(Deserialize)]
+pub struct CreateTodoList {
+    pub name: String,          // Name of the new list
+    #[serde(default)]
+    pub color: Option<String>, // Optional color as #rrggbb
+    pub position: Option<u32>, // Place among the other lists; after all of them by default
+}
+
+// Payload for changing a list
+#[derive(Deserialize)]
+pub struct UpdateTodoList {
+    pub name: Option<String>,          // Optional new name
+    #[serde(default, deserialize_with = "nullable")]
+    pub color: Option<Option<String>>, // New color; null removes it
+    pub archived: Option<bool>,        // Archive or unarchive the list
+    pub position: Option<u32>,         // New place among the other lists
+}
+
+// Query string of GET /lists
+#[derive(Deserialize)]
+pub struct ListsParams {
+    pub archived: Option<bool>, // Only archived (true) or only active (false) lists
+}
+
+impl TodoList {
+    pub fn new(list: &CreateTodoList, position: u32, owner_id: Uuid) -> Self {
+        TodoList {
+            id: Uuid::new_v4(),
+            name: list.name.clone(),
+            color: list.color.as_deref().and_then(normalize_color),
+            archived: false,
+            position: list.position.unwrap_or(position),
+            created_at: Utc::now(),
+            updated_at: None,
+            owner_id: Some(owner_id),
+        }
+    }
+
+    // Apply the fields present in an update payload and bump the update timestamp
+    pub fn apply(&mut self, changes: &UpdateTodoList) {
+        if let Some(name) = &changes.name {
+            self.name = name.clone();
+        }
+        if let Some(color) = &changes.color {
+            self.color = color.as_deref().and_then(normalize_color);
+        }
+        if let Some(archived) = changes.archived {
+            self.archived = archived;
+        }
+        if let Some(position) = changes.position {
+            self.position = position;
+        }
+        self.updated_at = Some(Utc::now());
+    }
+}
+
+// Canonical form of a list color: `#` and six hex digits, lowercased. Anything else is rejected.
+pub fn normalize_color(color: &str) -> Option<String> {
+    let digits = color.trim().strip_prefix('#')?;
+    if digits.len() == 6 && digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(format!("#{}", digits.to_ascii_lowercase()))
+    } else {
+        None
+    }
+}
+
+// Query string of the due date views
+#[derive(Deserialize)]
+pub struct AgendaParams {
+    pub tz: Option<String>, // IANA time zone deciding what "today" is (UTC by default)
+}
+
+// A tag along with the number of live items carrying it
+#[derive(Serialize)]
+pub struct TagCount {
+    pub name: String,
+    pub count: usize,
+}
+
+// Payload for renaming a tag
+#[derive(Deserialize)]
+pub struct RenameTag {
+    pub name: String, // New name; must not be in use yet
+}
+
+// Payload for merging a tag into another
+#[derive(Deserialize)]
+pub struct MergeTag {
+    pub into: String, // Tag that replaces the merged one on every item
+}
+
+// Payload of POST /todos/{id}/move; exactly one of the two is given
+#[derive(Deserialize)]
+pub struct MoveTodo {
+    pub before: Option<Uuid>, // Put the item right before this one
+    pub after: Option<Uuid>,  // Put the item right after this one
+}
+
+// Query string of writes that can extend to every subtask or override blocking tasks
+#[derive(Deserialize)]
+pub struct CascadeParams {
+    #[serde(default)]
+    pub cascade: bool, // Apply a completion change, deletion or restore to all subtasks as well
+    #[serde(default)]
+    pub force: bool,   // Complete items even though tasks blocking them are still open
+}
+
+// A to-do item with its subtasks nested below it
+#[derive(Serialize)]
+pub struct TodoTree {
+    #[serde(flatten)]
+    pub todo: TodoItem,          // The item itself
+    pub progress: Progress,      // How far along its subtasks are
+    pub children: Vec<TodoTree>, // Direct subtasks, oldest first
+}
+
+// Completion roll-up over all subtasks of an item, at any depth
+#[derive(Serialize)]
+pub struct Progress {
+    pub completed: usize, // Subtasks that are completed
+    pub total: usize,     // All subtasks
+    pub percent: u32,     // completed / total in percent, rounded down; for items without subtasks 0 or 100
+}
+
+// An account signing in with a username and password
+#[derive(Serialize, Deserialize, Clone)]
+pub struct User {
+    pub id: Uuid,                            // Unique identifier for the account
+    pub username: String,                    // Name signed in with, normalized by `normalize_username`
+    pub password_hash: String,               // Argon2 hash of the password in PHC string format
+    pub created_at: DateTime<Utc>,           // Timestamp for when the account was registered
+    #[serde(default)]
+    pub failed_logins: u32,                  // Wrong passwords since the last right one
+    #[serde(default)]
+    pub locked_until: Option<DateTime<Utc>>, // No password is checked before this, after too many
+}
+
+impl User {
+    // Whether something with this owner belongs to the account
+    pub fn owns(&self, owner_id: Option<Uuid>) -> bool {
+        owner_id == Some(self.id)
+    }
+}
+
+// An account as responses show it, without its password hash
+#[derive(Serialize)]
+pub struct UserProfile {
+    pub id: Uuid,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&User> for UserProfile {
+    fn from(user: &User) -> Self {
+        UserProfile {
+            id: user.id,
+            username: user.username.clone(),
+            created_at: user.created_at,
+        }
+    }
+}
+
+// Body of POST /auth/register and POST /auth/login
+#[derive(Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+    pub code: Option<String>, // At login, a code from the authenticator app or a recovery code
+}
+
+// A signed-in session, found again by the hash of the bearer token handed out at login
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Session {
+    pub id: String,                // Hex SHA-256 of the token; the token itself is never stored
+    pub user_id: Uuid,             // Account the session signs in as
+    pub created_at: DateTime<Utc>, // Timestamp for when the session was started
+    pub expires_at: DateTime<Utc>, // The token is refused from this point on
+}
+
+// Response of POST /auth/login
+#[derive(Serialize)]
+pub struct LoginResponse {
+    pub token: String,             // Send as `Authorization: Bearer <token>`
+    pub expires_at: DateTime<Utc>, // When the token stops working
+    pub user: UserProfile,         // Account signed in as
+}
+
+// The second sign-in step of an account: a TOTP secret shared with an authenticator app, and the
+// recovery codes standing in for it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TwoFactor {
+    pub user_id: Uuid,                       // Account it belongs to
+    pub secret: String,                      // Shared secret in base32, as the app was given it
+    pub enabled: bool,                       // Set once a first code confirmed the enrollment
+    pub recovery_codes: Vec<String>,         // Hex SHA-256 of each unused recovery code
+    pub last_step: Option<i64>,              // Time step of the last code accepted, never reused
+    pub failures: u32,                       // Wrong codes since the last right one
+    pub locked_until: Option<DateTime<Utc>>, // No code is checked before this, once too many failed
+    pub created_at: DateTime<Utc>,           // Timestamp for when the enrollment was started
+}
+
+// Response of GET /auth/totp
+#[derive(Serialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,              // Whether logging in asks for a code
+    pub pending: bool,              // Whether an enrollment waits for its first code
+    pub recovery_codes_left: usize, // Recovery codes not used yet
+}
+
+// Response of POST /auth/totp: what to give the authenticator app
+#[derive(Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String, // Shared secret in base32, for typing in
+    pub uri: String,    // otpauth:// URI, usually shown as a QR code
+}
+
+// Body of the routes that need a current code: confirming, disabling, new recovery codes
+#[derive(Deserialize)]
+pub struct TotpCode {
+    pub code: String, // Code from the authenticator app, or a recovery code where those are allowed
+}
+
+// Recovery codes as handed out once; only their hashes are kept
+#[derive(Serialize)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+// Trim and lowercase a username, or none when it is not 3 to 32 letters, digits, `.`, `_` or `-`
+pub fn normalize_username(username: &str) -> Option<String> {
+    let username = username.trim().to_lowercase();
+    let valid = (3..=32).contains(&username.chars().count())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    Some(username).filter(|_| valid)
+}
+
+// What an API token may do; signed-in sessions may do everything
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scope {
+    #[serde(rename = "todos:read")]
+    TodosRead,  // Read items with their comments, attachments and history, tags and the trash
+    #[serde(rename = "todos:write")]
+    TodosWrite, // Add, change, move and delete items and everything attached to them
+    #[serde(rename = "lists:read")]
+    ListsRead,  // Read lists and smart lists, with the items in them
+    #[serde(rename = "lists:write")]
+    ListsWrite, // Add, change and delete lists and smart lists
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::TodosRead,
+        Scope::TodosWrite,
+        Scope::ListsRead,
+        Scope::ListsWrite,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scope::TodosRead => "todos:read",
+            Scope::TodosWrite => "todos:write",
+            Scope::ListsRead => "lists:read",
+            Scope::ListsWrite => "lists:write",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Scope::ALL.iter().copied().find(|scope| scope.name() == name)
+    }
+}
+
+// A long-lived token for scripts and bots, limited to its scopes and found again by the hash of its secret
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiToken {
+    pub id: Uuid,                            // Unique identifier for the token, used to revoke it
+    pub user_id: Uuid,                       // Account the token acts as
+    pub name: String,                        // What the token is for, e.g. "CI"
+    pub hash: String,                        // Hex SHA-256 of the secret; the secret itself is never stored
+    pub scopes: Vec<Scope>,                  // What the token may do
+    pub created_at: DateTime<Utc>,           // Timestamp for when the token was created
+    pub expires_at: Option<DateTime<Utc>>,   // The token is refused from this point on; never when None
+    pub last_used_at: Option<DateTime<Utc>>, // Roughly when the token was last used, to the minute
+}
+
+// An API token as responses show it, without its hash
+#[derive(Serialize)]
+pub struct ApiTokenInfo {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl From<&ApiToken> for ApiTokenInfo {
+    fn from(token: &ApiToken) -> Self {
+        ApiTokenInfo {
+            id: token.id,
+            name: token.name.clone(),
+            scopes: token.scopes.clone(),
+            created_at: token.created_at,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+        }
+    }
+}
+
+// Body of POST /auth/tokens
+#[derive(Deserialize)]
+pub struct NewApiToken {
+    pub name: String,                 // What the token is for
+    pub scopes: Vec<Scope>,           // What the token may do; at least one
+    pub expires_in_days: Option<u32>, // Lifetime of the token; it never expires when omitted
+}
+
+// Response of POST /auth/tokens: the only time the secret is shown
+#[derive(Serialize)]
+pub struct CreatedApiToken {
+    pub token: String, // Send as `Authorization: Bearer <token>`
+    #[serde(flatten)]
+    pub info: ApiTokenInfo,
+}
+
+// What someone may do with a shared list and the items in it, from least to most
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Viewer, // Sees the list and its items
+    Editor, // Also adds, changes and deletes its items
+    Owner,  // Also changes and deletes the list and decides who it is shared with
+}
+
+impl Role {
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [Role::Viewer, Role::Editor, Role::Owner]
+            .iter()
+            .copied()
+            .find(|role| role.name() == name)
+    }
+}
+
+// A list as responses show it, with the role the signed-in account has on it
+#[derive(Serialize)]
+pub struct ListView {
+    #[serde(flatten)]
+    pub list: TodoList,
+    pub role: Role,
+}
+
+// Someone other than the owner who was given access to a list
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ListMember {
+    pub list_id: Uuid,           // List shared
+    pub user_id: Uuid,           // Account it is shared with
+    pub role: Role,              // Editor or viewer
+    pub added_at: DateTime<Utc>, // Timestamp for when the invitation was accepted
+}
+
+// An offer to share a list, waiting for the invited account to accept or decline it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Invitation {
+    pub id: Uuid,                  // Unique identifier for the invitation
+    pub list_id: Uuid,             // List offered
+    pub user_id: Uuid,             // Account invited
+    pub role: Role,                // Role given on accepting: editor or viewer
+    pub invited_by: Uuid,          // Account that sent the invitation
+    pub created_at: DateTime<Utc>, // Timestamp for when the invitation was sent
+}
+
+// An invitation as responses show it, with names instead of account ids
+#[derive(Serialize)]
+pub struct InvitationView {
+    pub id: Uuid,
+    pub list_id: Uuid,
+    pub list_name: String,
+    pub username: String,   // Account invited
+    pub role: Role,
+    pub invited_by: String, // Account that sent the invitation
+    pub created_at: DateTime<Utc>,
+}
+
+// Body of POST /lists/{id}/invitations
+#[derive(Deserialize)]
+pub struct Invite {
+    pub username: String, // Account to share the list with
+    pub role: Role,       // Editor or viewer
+}
+
+// Body of PUT /lists/{id}/members/{user_id}
+#[derive(Deserialize)]
+pub struct ChangeRole {
+    pub role: Role, // Editor or viewer
+}
+
+// Someone with access to a list, as GET /lists/{id}/members shows them
+#[derive(Serialize)]
+pub struct AccessEntry {
+    pub user_id: Uuid,
+    pub username: String,
+    pub role: Role,
+    pub since: DateTime<Utc>, // When the list was created, for the owner, or the invitation accepted
+}
+
+// What happened to the access to a list
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessAction {
+    Invited,            // An invitation was sent
+    InvitationCanceled, // The owner took an invitation back
+    Declined,           // The invited account turned the invitation down
+    Joined,             // The invited account accepted the invitation
+    RoleChanged,        // The owner gave a member another role
+    Removed,            // The owner took a member's access away
+    Left,               // A member gave up their access
+}
+
+impl AccessAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            AccessAction::Invited => "invited",
+            AccessAction::InvitationCanceled => "invitation_canceled",
+            AccessAction::Declined => "declined",
+            AccessAction::Joined => "joined",
+            AccessAction::RoleChanged => "role_changed",
+            AccessAction::Removed => "removed",
+            AccessAction::Left => "left",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [
+            AccessAction::Invited,
+            AccessAction::InvitationCanceled,
+            AccessAction::Declined,
+            AccessAction::Joined,
+            AccessAction::RoleChanged,
+            AccessAction::Removed,
+            AccessAction::Left,
+        ]
+        .iter()
+        .copied()
+        .find(|action| action.name() == name)
+    }
+}
+
+// One entry in the audit log of who was given or lost access to a list
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AccessChange {
+    pub id: Uuid,             // Unique identifier for the entry
+    pub list_id: Uuid,        // List whose access changed
+    pub actor: String,        // Who made the change
+    pub action: AccessAction, // What changed
+    pub username: String,     // Whose access changed
+    pub role: Option<Role>,   // Role given, for invitations, joins and role changes
+    pub at: DateTime<Utc>,    // Timestamp for when the change was made
+}
+
+// A link giving anyone who has it read-only access to a list or a single item, until it expires or
+// is revoked
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ShareLink {
+    pub id: Uuid,                  // Unique identifier for the link, signed into its token
+    pub owner_id: Uuid,            // Account that made the link
+    pub list_id: Option<Uuid>,     // List shown, for a link to a list
+    pub todo_id: Option<Uuid>,     // Item shown with its subtasks, for a link to a single item
+    pub created_at: DateTime<Utc>, // Timestamp for when the link was made
+    pub expires_at: DateTime<Utc>, // Timestamp for when the link stops working
+}
+
+// Payload of POST /share-links, naming either a list or an item
+#[derive(Deserialize)]
+pub struct NewShareLink {
+    pub list_id: Option<Uuid>,         // List to share
+    pub todo_id: Option<Uuid>,         // Item to share
+    pub expires_in_hours: Option<u32>, // How long the link works; a week when left out
+}
+
+// A share link as its owner sees it, with the path opening it
+#[derive(Serialize)]
+pub struct ShareLinkView {
+    #[serde(flatten)]
+    pub link: ShareLink,
+    pub path: String, // `/shared/{token}`, to be put after the address of the server
+}
+
+// A to-do item as share links show it: without the account, list, reminder and ordering details
+// only its owner needs
+#[derive(Serialize)]
+pub struct SharedTodo {
+    pub id: Uuid,
+    pub title: String,
+    pub completed: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub due_at: Option<Schedule>,
+    pub start_at: Option<Schedule>,
+    pub priority: Priority,
+    pub tags: BTreeSet<String>,
+    pub parent_id: Option<Uuid>,
+    pub recurrence: Option<Recurrence>,
+    pub notes: String,
+    pub checklist: Vec<ChecklistItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes_html: Option<String>, // The notes as sanitized HTML, for `render=html`
+}
+
+impl SharedTodo {
+    pub fn new(todo: TodoItem, render: Option<Render>) -> Self {
+        let notes_html = render.map(|Render::Html| markdown::render(&todo.notes));
+        SharedTodo {
+            id: todo.id,
+            title: todo.title,
+            completed: todo.completed,
+            created_at: todo.created_at,
+            updated_at: todo.updated_at,
+            due_at: todo.due_at,
+            start_at: todo.start_at,
+            priority: todo.priority,
+            tags: todo.tags,
+            parent_id: todo.parent_id,
+            recurrence: todo.recurrence,
+            notes: todo.notes,
+            checklist: todo.checklist,
+            notes_html,
+        }
+    }
+}
@@ -0,0 +1,292 @@
+// Manual order of to-do items: lexicographic ranks that always leave room for another item in between
+//
+// A rank is a string of base-36 digits read as the fraction 0.d1d2d3..., so comparing ranks as strings
+// compares those fractions. Ranks never end in `0`, which keeps room below every one of them. Moving
+// an item only gives it a new rank between its new neighbours; no other item is touched.
+use uuid::Uuid;
+
+use crate::models::TodoItem;
+use crate::store::{Cursor, Event, SortField, SortKey, StoreResult, TodoQuery, TodoStore};
+
+const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+const BASE: usize = 36;
+
+// A rank sorting strictly after `low` and before `high`; either may be open. Bounds that are out of
+// order are treated as if there was no upper bound.
+pub fn between(low: Option<&str>, high: Option<&str>) -> String {
+    let high = high.filter(|high| low.is_none_or(|low| low < *high));
+    let rank = match (low, high) {
+        (Some(low), None) => step(&digits(low), true),
+        (None, Some(high)) => step(&digits(high), false),
+        (low, high) => midpoint(
+            &low.map_or_else(Vec::new, digits),
+            high.map(digits).as_deref(),
+        ),
+    };
+    rank.into_iter()
+        .map(|digit| DIGITS[digit] as char)
+        .collect()
+}
+
+// `count` ascending ranks after `low`, spread evenly so that later moves between them stay short
+pub fn spread(low: Option<&str>, count: usize) -> Vec<String> {
+    let head = between(low, None);
+    let mut width = 1;
+    while BASE.pow(width) <= count {
+        width += 1;
+    }
+    (1..=count)
+        .map(|n| {
+            let mut value = n * BASE.pow(width) / (count + 1);
+            let mut tail = vec![0; width as usize];
+            for digit in tail.iter_mut().rev() {
+                *digit = value % BASE;
+                value /= BASE;
+            }
+            while tail.last() == Some(&0) {
+                tail.pop();
+            }
+            head.chars()
+                .chain(tail.into_iter().map(|digit| DIGITS[digit] as char))
+                .collect()
+        })
+        .collect()
+}
+
+fn digits(rank: &str) -> Vec<usize> {
+    rank.bytes()
+        .map(|byte| DIGITS.iter().position(|digit| *digit == byte).unwrap_or(0))
+        .collect()
+}
+
+// Digits right after `rank` (`up`) or right before it, for an end of the order with no neighbour
+// past it. Halving the gap to the end of the alphabet would add a digit every few items added at
+// that end, and stepping the last digit one every few dozen. Instead a rank starting with `level`
+// copies of the end digit (`z` going up, `0` going down) is followed by a counter of `level + 1`
+// digits, which is stepped; once it runs out, the next level starts. A rank of n digits thus leaves
+// room for about 36^(n/2) items at the same end.
+fn step(rank: &[usize], up: bool) -> Vec<usize> {
+    let edge = if up { BASE - 1 } else { 0 };
+    let level = rank.iter().take_while(|digit| **digit == edge).count();
+    let mut counter: Vec<usize> = (level..=2 * level)
+        .map(|n| rank.get(n).copied().unwrap_or(0))
+        .collect();
+    // Step the counter like a number, carrying or borrowing across its digits
+    let mut overflow = true;
+    for digit in counter.iter_mut().rev() {
+        if *digit == edge {
+            *digit = BASE - 1 - edge;
+            continue;
+        }
+        if up {
+            *digit += 1;
+        } else {
+            *digit -= 1;
+        }
+        overflow = false;
+        break;
+    }
+    // A counter starting with the end digit would be read as one more level, so that starts one
+    if overflow || counter[0] == edge {
+        counter = if up {
+            let mut first = vec![0; level + 2];
+            first[level + 1] = 1;
+            first
+        } else {
+            vec![BASE - 1; level + 2]
+        };
+        counter.splice(0..0, vec![edge; level + 1]);
+    } else {
+        counter.splice(0..0, vec![edge; level]);
+    }
+    // Trailing zeros do not change the value, and ranks never end in one
+    while counter.last() == Some(&0) {
+        counter.pop();
+    }
+    counter
+}
+
+// Digits strictly between `low` and `high` (1.0 when there is none), where `low` < `high`
+fn midpoint(low: &[usize], high: Option<&[usize]>) -> Vec<usize> {
+    let low_at = |n: usize| low.get(n).copied().unwrap_or(0);
+    if let Some(high) = high {
+        // Digits both bounds share stay as they are
+        let shared = high
+            .iter()
+            .enumerate()
+            .take_while(|(n, digit)| low_at(*n) == **digit)
+            .count();
+        if shared > 0 {
+            let mut rank = high[..shared].to_vec();
+            let rest = low.get(shared..).unwrap_or(&[]);
+            rank.extend(midpoint(rest, Some(&high[shared..])));
+            return rank;
+        }
+    }
+    let low_digit = low_at(0);
+    let high_digit = high.map_or(BASE, |high| high[0]);
+    if high_digit - low_digit > 1 {
+        return vec![(low_digit + high_digit) / 2];
+    }
+    match high {
+        // The upper bound has more digits, so its first digit alone already sorts below it
+        Some(high) if high.len() > 1 => vec![high_digit],
+        _ => {
+            let mut rank = vec![low_digit];
+            rank.extend(midpoint(low.get(1..).unwrap_or(&[]), None));
+            rank
+        }
+    }
+}
+
+// Rank of the nearest item, live or in the trash, after `rank` (before it with `descending`). Without
+// a rank, that is the first (last) item of all.
+fn neighbour(
+    store: &dyn TodoStore,
+    rank: Option<&str>,
+    descending: bool,
+) -> StoreResult<Option<String>> {
+    let mut nearest: Option<String> = None;
+    for in_trash in [false, true] {
+        let query = TodoQuery {
+            in_trash,
+            sort: SortField::Position,
+            descending,
+            after: rank.map(|rank| Cursor {
+                field: SortField::Position,
+                key: SortKey::Text(rank.to_string()),
+                // Past every item sharing the rank, in the direction of travel
+                id: if descending {
+                    Uuid::nil()
+                } else {
+                    Uuid::from_u128(u128::MAX)
+                },
+            }),
+            limit: Some(1),
+            ..TodoQuery::default()
+        };
+        for todo in store.list(&query)? {
+            let closer = nearest.as_ref().is_none_or(|nearest| {
+                if descending {
+                    todo.rank > *nearest
+                } else {
+                    todo.rank < *nearest
+                }
+            });
+            if closer {
+                nearest = Some(todo.rank);
+            }
+        }
+    }
+    Ok(nearest)
+}
+
+// Rank for a new item, after every other one
+pub fn last(store: &dyn TodoStore) -> StoreResult<String> {
+    Ok(between(neighbour(store, None, true)?.as_deref(), None))
+}
+
+// Rank placing an item right after `todo`
+pub fn after(store: &dyn TodoStore, todo: &TodoItem) -> StoreResult<String> {
+    let next = neighbour(store, Some(&todo.rank), false)?;
+    Ok(between(Some(&todo.rank), next.as_deref()))
+}
+
+// Rank placing an item right before `todo`
+pub fn before(store: &dyn TodoStore, todo: &TodoItem) -> StoreResult<String> {
+    let previous = neighbour(store, Some(&todo.rank), true)?;
+    Ok(between(previous.as_deref(), Some(&todo.rank)))
+}
+
+// Events giving a rank to the items stored before ranks existed, after the ranked ones and in the
+// order they were created
+pub fn backfill(store: &dyn TodoStore) -> StoreResult<Vec<Event>> {
+    let mut todos = store.list(&TodoQuery::default())?;
+    todos.extend(store.list(&TodoQuery {
+        in_trash: true,
+        ..TodoQuery::default()
+    })?);
+    let (mut unranked, ranked): (Vec<TodoItem>, Vec<TodoItem>) =
+        todos.into_iter().partition(|todo| todo.rank.is_empty());
+    if unranked.is_empty() {
+        return Ok(Vec::new());
+    }
+    unranked.sort_by_key(|todo| (todo.created_at, todo.id));
+    let highest = ranked.into_iter().map(|todo| todo.rank).max();
+    let ranks = spread(highest.as_deref(), unranked.len());
+    Ok(unranked
+        .into_iter()
+        .zip(ranks)
+        .map(|(mut todo, rank)| {
+            todo.rank = rank;
+            Event::TodoUpdated(todo)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `count` ranks, each added at the same end of the order as the one before
+    fn added_at_one_end(count: usize, at_end: bool) -> Vec<String> {
+        let mut ranks = vec![between(None, None)];
+        for _ in 1..count {
+            let rank = ranks.last().unwrap();
+            ranks.push(if at_end {
+                between(Some(rank), None)
+            } else {
+                between(None, Some(rank))
+            });
+        }
+        ranks
+    }
+
+    #[test]
+    fn ranks_stay_short_when_added_at_one_end() {
+        for at_end in [true, false] {
+            let ranks = added_at_one_end(1000, at_end);
+            for pair in ranks.windows(2) {
+                if at_end {
+                    assert!(pair[0] < pair[1], "{} then {}", pair[0], pair[1]);
+                } else {
+                    assert!(pair[0] > pair[1], "{} then {}", pair[0], pair[1]);
+                }
+            }
+            assert!(ranks
+                .iter()
+                .all(|rank| rank.len() <= 3 && !rank.ends_with('0')));
+        }
+    }
+
+    #[test]
+    fn ranks_keep_growing_slowly() {
+        // The second level alone holds 35 * 36^2 ranks of at most five digits
+        let ranks = added_at_one_end(40_000, true);
+        assert!(ranks.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(ranks.iter().all(|rank| rank.len() <= 5));
+    }
+
+    #[test]
+    fn between_lies_strictly_between_its_bounds() {
+        let mut ranks = added_at_one_end(300, true);
+        ranks.extend(added_at_one_end(300, false));
+        ranks.extend(spread(Some("k"), 100));
+        ranks.sort();
+        ranks.dedup();
+        for (n, low) in ranks.iter().enumerate() {
+            for high in ranks[n + 1..].iter().step_by(37).chain(ranks.get(n + 1)) {
+                let rank = between(Some(low), Some(high));
+                assert!(low < &rank && &rank < high, "{} < {} < {}", low, rank, high);
+                assert!(!rank.ends_with('0'));
+            }
+        }
+        // Halving the same gap over and over still leaves room
+        let (low, mut high) = ("a".to_string(), "b".to_string());
+        for _ in 0..200 {
+            let rank = between(Some(&low), Some(&high));
+            assert!(low < rank && rank < high, "{} < {} < {}", low, rank, high);
+            high = rank;
+        }
+    }
+}
@@ -0,0 +1,434 @@
+// Recurring tasks: a subset of RFC 5545 recurrence rules (FREQ, INTERVAL, BYDAY, BYMONTHDAY, COUNT
+// and UNTIL) and the next instance spawned when an instance of a recurring task is completed
+//
+// Rules are written the iCalendar way, e.g. `FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH` or
+// `FREQ=MONTHLY;BYMONTHDAY=-1;COUNT=6`. Periods are counted from the date the next occurrence is
+// looked for from (the due date or the completion date, see `RepeatFrom`), and COUNT is the number
+// of instances left in the series, the current one included.
+use chrono::{DateTime, Datelike, Days, Duration, Months, NaiveDate, TimeZone, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt;
+use uuid::Uuid;
+
+use crate::models::{ChecklistItem, RepeatFrom, Schedule, TodoItem};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+// End of a series as given by UNTIL: a date, or a UTC timestamp
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Until {
+    Date(NaiveDate),
+    Instant(DateTime<Utc>),
+}
+
+// A parsed recurrence rule; it travels as its RRULE text in JSON and in the stores
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: u32,          // Every how many periods the task comes back (1 by default)
+    pub by_day: Vec<Weekday>,   // Only on these weekdays
+    pub by_month_day: Vec<i32>, // Only on these days of the month; -1 is the last one
+    pub count: Option<u32>,     // Instances left, the current one included
+    pub until: Option<Until>,   // No occurrences after this
+}
+
+// Occurrences more than this many periods apart (e.g. every February 29th) are not looked for
+const SEARCH_YEARS: i64 = 8;
+
+// Largest INTERVAL accepted; a rule coming back less often than this is no use for a task
+const MAX_INTERVAL: u32 = 1000;
+
+impl Recurrence {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let text = match text.get(..6) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("RRULE:") => &text[6..],
+            _ => text,
+        };
+        let mut frequency = None;
+        let mut rule = Recurrence {
+            frequency: Frequency::Daily,
+            interval: 1,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            count: None,
+            until: None,
+        };
+        let mut seen = BTreeSet::new();
+        for part in text.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("expected NAME=VALUE, found `{}`", part))?;
+            let name = name.to_ascii_uppercase();
+            if !seen.insert(name.clone()) {
+                return Err(format!("{} is given more than once", name));
+            }
+            match name.as_str() {
+                "FREQ" => frequency = Some(parse_frequency(value)?),
+                "INTERVAL" => {
+                    rule.interval = parse_positive("INTERVAL", value)?;
+                    if rule.interval > MAX_INTERVAL {
+                        return Err(format!("INTERVAL can be at most {}", MAX_INTERVAL));
+                    }
+                }
+                "BYDAY" => {
+                    rule.by_day = value
+                        .split(',')
+                        .map(parse_weekday)
+                        .collect::<Result<_, _>>()?
+                }
+                "BYMONTHDAY" => {
+                    rule.by_month_day = value
+                        .split(',')
+                        .map(parse_month_day)
+                        .collect::<Result<_, _>>()?
+                }
+                "COUNT" => rule.count = Some(parse_positive("COUNT", value)?),
+                "UNTIL" => rule.until = Some(parse_until(value)?),
+                _ => return Err(format!("unsupported rule part `{}`", name)),
+            }
+        }
+        rule.frequency = frequency.ok_or("FREQ is required")?;
+        if rule.count.is_some() && rule.until.is_some() {
+            return Err("COUNT and UNTIL cannot be combined".to_string());
+        }
+        Ok(rule)
+    }
+
+    // The first occurrence after the day `from` falls on, keeping the time of day of timed values
+    pub fn next(&self, from: Schedule) -> Option<Schedule> {
+        let anchor = local_date(from);
+        let limit = Duration::try_days(i64::from(self.interval) * 366 * SEARCH_YEARS)
+            .and_then(|span| anchor.checked_add_signed(span))
+            .unwrap_or(NaiveDate::MAX);
+        // Only the periods a whole number of intervals from the anchor's are looked through
+        let date = (0..)
+            .map_while(|intervals| {
+                let periods = self.interval.checked_mul(intervals)?;
+                self.period_start(anchor, periods)
+                    .filter(|start| *start <= limit)
+            })
+            .find_map(|start| {
+                let end = self.period_start(start, 1);
+                start
+                    .iter_days()
+                    .take_while(|date| end.is_none_or(|end| *date < end) && *date <= limit)
+                    .find(|date| *date > anchor && self.on_day(anchor, *date))
+            })?;
+        let next = match from {
+            Schedule::AllDay(_) => Schedule::AllDay(date),
+            Schedule::Timed(time) => Schedule::Timed(
+                time.offset()
+                    .from_local_datetime(&date.and_time(time.time()))
+                    .single()?,
+            ),
+        };
+        let within = match (self.until, next) {
+            (None, _) => true,
+            (Some(Until::Date(until)), _) => local_date(next) <= until,
+            (Some(Until::Instant(until)), Schedule::Timed(time)) => time <= until,
+            (Some(Until::Instant(until)), Schedule::AllDay(date)) => date <= until.date_naive(),
+        };
+        Some(next).filter(|_| within)
+    }
+
+    // First day of the period `periods` periods after the one `date` falls in, or None past the last
+    // date there is
+    fn period_start(&self, date: NaiveDate, periods: u32) -> Option<NaiveDate> {
+        match self.frequency {
+            Frequency::Daily => date.checked_add_days(Days::new(u64::from(periods))),
+            Frequency::Weekly => {
+                week_start(date).checked_add_days(Days::new(u64::from(periods) * 7))
+            }
+            Frequency::Monthly => date.with_day(1)?.checked_add_months(Months::new(periods)),
+            Frequency::Yearly => {
+                let year = i32::try_from(periods).ok()?.checked_add(date.year())?;
+                NaiveDate::from_ymd_opt(year, 1, 1)
+            }
+        }
+    }
+
+    // Whether `date` is one of the days the rule picks within its period
+    fn on_day(&self, anchor: NaiveDate, date: NaiveDate) -> bool {
+        if self.by_day.is_empty() && self.by_month_day.is_empty() {
+            // Without BYDAY or BYMONTHDAY the day of the anchor repeats
+            return match self.frequency {
+                Frequency::Daily => true,
+                Frequency::Weekly => date.weekday() == anchor.weekday(),
+                Frequency::Monthly => date.day() == anchor.day(),
+                Frequency::Yearly => date.month() == anchor.month() && date.day() == anchor.day(),
+            };
+        }
+        (self.by_day.is_empty() || self.by_day.contains(&date.weekday()))
+            && (self.by_month_day.is_empty()
+                || self
+                    .by_month_day
+                    .iter()
+                    .any(|day| month_day(date, *day) == Some(date)))
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+        write!(f, "FREQ={}", frequency)?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<&str> = self.by_day.iter().map(|day| weekday_code(*day)).collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if !self.by_month_day.is_empty() {
+            let days: Vec<String> = self.by_month_day.iter().map(i32::to_string).collect();
+            write!(f, ";BYMONTHDAY={}", days.join(","))?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={}", count)?;
+        }
+        match self.until {
+            Some(Until::Date(date)) => write!(f, ";UNTIL={}", date.format("%Y%m%d")),
+            Some(Until::Instant(time)) => write!(f, ";UNTIL={}", time.format("%Y%m%dT%H%M%SZ")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TryFrom<String> for Recurrence {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        Recurrence::parse(&text).map_err(|err| format!("invalid recurrence rule: {}", err))
+    }
+}
+
+impl From<Recurrence> for String {
+    fn from(rule: Recurrence) -> String {
+        rule.to_string()
+    }
+}
+
+// The instance that follows `todo` once it is completed at `now`, or None if it does not recur or its
+// series is over. Due and start dates move forward together; blocking links are not carried over and
+// the checklist starts over with every step open.
+pub fn next_instance(todo: &TodoItem, now: DateTime<Utc>) -> Option<TodoItem> {
+    let rule = todo.recurrence.as_ref()?;
+    let rest = match rule.count {
+        Some(1) => return None,
+        Some(count) => Recurrence {
+            count: Some(count - 1),
+            ..rule.clone()
+        },
+        None => rule.clone(),
+    };
+    let from = match (todo.repeat_from, todo.due_at) {
+        (RepeatFrom::Due, Some(due_at)) => due_at,
+        (_, Some(Schedule::Timed(due_at))) => Schedule::Timed(
+            due_at
+                .offset()
+                .from_local_datetime(
+                    &now.with_timezone(due_at.offset())
+                        .date_naive()
+                        .and_time(due_at.time()),
+                )
+                .single()?,
+        ),
+        _ => Schedule::AllDay(now.date_naive()),
+    };
+    let due_at = rule.next(from)?;
+    let shift = local_date(due_at) - local_date(todo.due_at.unwrap_or(from));
+    let start_at = todo.start_at.map(|start_at| match start_at {
+        Schedule::AllDay(date) => Schedule::AllDay(date + shift),
+        Schedule::Timed(time) => Schedule::Timed(time + shift),
+    });
+    Some(TodoItem {
+        id: Uuid::new_v4(),
+        completed: false,
+        created_at: now,
+        updated_at: None,
+        deleted_at: None,
+        due_at: Some(due_at),
+        start_at,
+        blocked_by: BTreeSet::new(),
+        recurrence: Some(rest),
+        reminded: BTreeSet::new(),
+        checklist: todo
+            .checklist
+            .iter()
+            .map(|item| ChecklistItem {
+                done: false,
+                ..item.clone()
+            })
+            .collect(),
+        ..todo.clone()
+    })
+}
+
+// Calendar day of a schedule as written, i.e. in the offset a timed value carries
+fn local_date(schedule: Schedule) -> NaiveDate {
+    match schedule {
+        Schedule::AllDay(date) => date,
+        Schedule::Timed(time) => time.date_naive(),
+    }
+}
+
+// Monday of the week `date` falls in (weeks start on Monday, the RFC 5545 default)
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(i64::from(date.weekday().num_days_from_monday()))
+}
+
+// The date for a BYMONTHDAY value in the month of `date`, if that month has such a day
+fn month_day(date: NaiveDate, day: i32) -> Option<NaiveDate> {
+    if day > 0 {
+        return date.with_day(day as u32);
+    }
+    let first = date.with_day(1)?;
+    let next_month = first.checked_add_months(Months::new(1))?;
+    let last = next_month - Duration::days(1);
+    let target = i64::from(last.day()) + i64::from(day) + 1;
+    if target < 1 {
+        return None;
+    }
+    last.with_day(target as u32)
+}
+
+fn parse_frequency(value: &str) -> Result<Frequency, String> {
+    match value.to_ascii_uppercase().as_str() {
+        "DAILY" => Ok(Frequency::Daily),
+        "WEEKLY" => Ok(Frequency::Weekly),
+        "MONTHLY" => Ok(Frequency::Monthly),
+        "YEARLY" => Ok(Frequency::Yearly),
+        _ => Err(format!(
+            "FREQ must be DAILY, WEEKLY, MONTHLY or YEARLY, found `{}`",
+            value
+        )),
+    }
+}
+
+fn parse_positive(name: &str, value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(number) if number > 0 => Ok(number),
+        _ => Err(format!(
+            "{} must be a positive number, found `{}`",
+            name, value
+        )),
+    }
+}
+
+fn parse_weekday(value: &str) -> Result<Weekday, String> {
+    [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ]
+    .iter()
+    .copied()
+    .find(|day| value.eq_ignore_ascii_case(weekday_code(*day)))
+    .ok_or_else(|| {
+        format!(
+            "BYDAY takes MO, TU, WE, TH, FR, SA or SU, found `{}`",
+            value
+        )
+    })
+}
+
+fn weekday_code(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+fn parse_month_day(value: &str) -> Result<i32, String> {
+    match value.parse::<i32>() {
+        Ok(day) if day != 0 && (-31..=31).contains(&day) => Ok(day),
+        _ => Err(format!(
+            "BYMONTHDAY takes 1 to 31 or -31 to -1, found `{}`",
+            value
+        )),
+    }
+}
+
+// UNTIL is a date (20241231) or a UTC date and time (20241231T170000Z)
+fn parse_until(value: &str) -> Result<Until, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+        return Ok(Until::Date(date));
+    }
+    chrono::NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ")
+        .map(|time| Until::Instant(time.and_utc()))
+        .map_err(|_| {
+            format!(
+                "UNTIL must look like 20241231 or 20241231T170000Z, found `{}`",
+                value
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next(rule: &str, from: NaiveDate) -> Option<NaiveDate> {
+        let rule = Recurrence::parse(rule).unwrap();
+        match rule.next(Schedule::AllDay(from))? {
+            Schedule::AllDay(date) => Some(date),
+            Schedule::Timed(_) => None,
+        }
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn occurrences_come_in_the_periods_of_the_interval() {
+        let thursday = date(2026, 3, 5);
+        let rule = "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH";
+        assert_eq!(next(rule, date(2026, 3, 2)), Some(thursday));
+        assert_eq!(next(rule, thursday), Some(date(2026, 3, 16)));
+        let every_third_day = "FREQ=DAILY;INTERVAL=3";
+        assert_eq!(next(every_third_day, thursday), Some(date(2026, 3, 8)));
+        let last_day = "FREQ=MONTHLY;BYMONTHDAY=-1";
+        assert_eq!(next(last_day, date(2026, 1, 31)), Some(date(2026, 2, 28)));
+        let leap_day = date(2024, 2, 29);
+        assert_eq!(next("FREQ=YEARLY", leap_day), Some(date(2028, 2, 29)));
+    }
+
+    #[test]
+    fn a_huge_interval_is_refused() {
+        assert!(Recurrence::parse("FREQ=YEARLY;INTERVAL=1000").is_ok());
+        assert!(Recurrence::parse("FREQ=YEARLY;INTERVAL=1001").is_err());
+        assert!(Recurrence::parse("FREQ=YEARLY;INTERVAL=4294967295").is_err());
+    }
+
+    #[test]
+    fn the_largest_interval_is_found_without_running_off_the_calendar() {
+        let rule = "FREQ=YEARLY;INTERVAL=1000";
+        assert_eq!(next(rule, date(2026, 3, 2)), Some(date(3026, 3, 2)));
+        assert_eq!(next(rule, NaiveDate::MAX - Duration::days(1)), None);
+        assert_eq!(next("FREQ=DAILY;INTERVAL=1000", NaiveDate::MAX), None);
+    }
+}
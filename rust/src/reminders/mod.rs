@@ -0,0 +1,341 @@
+// Reminders for items with a due date, sent by a background task on the actix runtime
+//
+// Each item lists offsets in minutes before its due date (`reminders`) and remembers which of them
+// have been sent for the current due date (`reminded`). Because that is saved with the item, the
+// scheduler picks up where it left off after a restart: reminders that came due while the server
+// was down are sent late, as long as the item itself is not due yet. All-day due dates count from
+// the start of the day in TODO_REMINDER_TZ (UTC by default). The log gets the reminders of every
+// account; the mail and webhook sinks are for the one account named in TODO_REMINDER_ACCOUNT.
+use actix_web::rt::time::sleep;
+use actix_web::web;
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::models::{Schedule, TodoItem};
+use crate::state::AppState;
+use crate::store::{Event, StoreResult, TodoQuery};
+
+mod sinks;
+
+pub use sinks::{sinks_from_env, Sink};
+
+// Longest the scheduler sleeps, so that reminders added in the meantime are not picked up late
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+// What a sink is told when a reminder comes due
+#[derive(Serialize, Clone, Debug)]
+pub struct Notification {
+    pub todo_id: Uuid,
+    pub title: String,
+    pub due_at: Schedule,
+    pub minutes_before: u32,      // The reminder offset that came due
+    pub remind_at: DateTime<Utc>, // When the reminder was meant to go out
+    #[serde(skip)]
+    pub account: Option<String>, // Username of the owner of the item
+}
+
+impl Notification {
+    // One line summing up the reminder, for log lines and mail subjects
+    pub fn summary(&self) -> String {
+        format!("\"{}\" is due {}", self.title, self.due_at.encode())
+    }
+}
+
+// Time zone all-day due dates are read in, from TODO_REMINDER_TZ
+pub fn time_zone_from_env() -> Result<Tz, String> {
+    match env::var("TODO_REMINDER_TZ") {
+        Ok(name) => name
+            .parse()
+            .map_err(|_| format!("unknown time zone `{}` in TODO_REMINDER_TZ", name)),
+        Err(_) => Ok(Tz::UTC),
+    }
+}
+
+// Reminders of an item not sent yet, with the due date they count back from
+struct Pending {
+    due_at: Schedule,
+    reminders: Vec<(u32, DateTime<Utc>)>, // Offset in minutes and when it goes out
+}
+
+// The reminders of `todo` not sent yet. Completed and trashed items and items without a due date
+// get none.
+fn schedule(todo: &TodoItem, tz: Tz) -> Option<Pending> {
+    let due_at = todo
+        .due_at
+        .filter(|_| !todo.completed && todo.deleted_at.is_none())?;
+    let start = due_at.span(tz).0;
+    let reminders = todo
+        .reminders
+        .difference(&todo.reminded)
+        .map(|minutes| (*minutes, start - Duration::minutes(i64::from(*minutes))))
+        .collect();
+    Some(Pending { due_at, reminders })
+}
+
+// Send every reminder that is due by `now` and mark it as sent; returns how many were sent
+async fn send_due(
+    data: &web::Data<AppState>,
+    sinks: &Arc<Vec<Box<dyn Sink>>>,
+    tz: Tz,
+    now: DateTime<Utc>,
+) -> StoreResult<usize> {
+    let mut notifications = Vec::new();
+    let mut skipped = Vec::new();
+    let mut accounts = HashMap::new();
+    for todo in data.store.list(&TodoQuery::default())? {
+        let Pending { due_at, reminders } = match schedule(&todo, tz) {
+            Some(pending) => pending,
+            None => continue,
+        };
+        for (minutes, remind_at) in reminders {
+            if remind_at > now {
+                continue;
+            }
+            if due_at.has_passed(now, tz) {
+                // Missed while the server was down and too late to be of use now
+                skipped.push((todo.id, due_at, minutes));
+                continue;
+            }
+            notifications.push(Notification {
+                todo_id: todo.id,
+                title: todo.title.clone(),
+                due_at,
+                minutes_before: minutes,
+                remind_at,
+                account: account(data, &mut accounts, todo.owner_id)?,
+            });
+        }
+    }
+
+    let mut sent = Vec::new();
+    for notification in notifications {
+        let delivered = {
+            let sinks = Arc::clone(sinks);
+            let notification = notification.clone();
+            web::block(move || deliver(&sinks, &notification)).await
+        };
+        // A reminder counts as sent once any sink meant for it took it; otherwise it is tried again
+        // next time
+        if let Ok(true) = delivered {
+            sent.push((
+                notification.todo_id,
+                notification.due_at,
+                notification.minutes_before,
+            ));
+        }
+    }
+    let count = sent.len();
+    mark_sent(data, sent.into_iter().chain(skipped))?;
+    Ok(count)
+}
+
+// Username of the account `owner_id`, looked up once per run in `accounts`
+fn account(
+    data: &AppState,
+    accounts: &mut HashMap<Uuid, Option<String>>,
+    owner_id: Option<Uuid>,
+) -> StoreResult<Option<String>> {
+    let owner_id = match owner_id {
+        Some(owner_id) => owner_id,
+        None => return Ok(None),
+    };
+    if let Some(username) = accounts.get(&owner_id) {
+        return Ok(username.clone());
+    }
+    let username = data.store.user(owner_id)?.map(|user| user.username);
+    accounts.insert(owner_id, username.clone());
+    Ok(username)
+}
+
+// Hand a notification to every sink meant for it, logging failures; whether one of them took it.
+// One no sink is meant for, e.g. of another account than the mail sink's, has nowhere to go and
+// counts as delivered.
+fn deliver(sinks: &[Box<dyn Sink>], notification: &Notification) -> bool {
+    let mut meant = sinks
+        .iter()
+        .filter(|sink| sink.accepts(notification))
+        .peekable();
+    if meant.peek().is_none() {
+        return true;
+    }
+    let mut delivered = false;
+    for sink in meant {
+        match sink.send(notification) {
+            Ok(()) => delivered = true,
+            Err(err) => log::error!(
+                "failed to send the reminder for todo {} to the {} sink: {}",
+                notification.todo_id,
+                sink.name(),
+                err
+            ),
+        }
+    }
+    delivered
+}
+
+// Record reminders as sent, unless the item's due date changed while they were being delivered
+fn mark_sent(
+    data: &AppState,
+    sent: impl Iterator<Item = (Uuid, Schedule, u32)>,
+) -> StoreResult<()> {
+    let _guard = data.writes.lock().unwrap();
+    let mut updated: Vec<TodoItem> = Vec::new();
+    for (id, due_at, minutes) in sent {
+        if let Some(todo) = updated.iter_mut().find(|todo| todo.id == id) {
+            todo.reminded.insert(minutes);
+            continue;
+        }
+        if let Some(mut todo) = data.store.get(id)? {
+            if todo.due_at == Some(due_at) && todo.reminders.contains(&minutes) {
+                todo.reminded.insert(minutes);
+                updated.push(todo);
+            }
+        }
+    }
+    if !updated.is_empty() {
+        // Bookkeeping only: no revision and no new update time
+        let events: Vec<Event> = updated.into_iter().map(Event::TodoUpdated).collect();
+        data.commit(&events)?;
+    }
+    Ok(())
+}
+
+// How long to wait before the next reminder is due, at most `POLL_INTERVAL`. Reminders that are
+// overdue already failed to go out and are retried at that pace too.
+fn next_wake(data: &AppState, tz: Tz) -> StoreResult<std::time::Duration> {
+    let now = Utc::now();
+    let next = data
+        .store
+        .list(&TodoQuery::default())?
+        .iter()
+        .filter_map(|todo| schedule(todo, tz))
+        .flat_map(|pending| pending.reminders)
+        .map(|(_, remind_at)| remind_at)
+        .min();
+    Ok(match next {
+        Some(next) => (next - now)
+            .to_std()
+            .unwrap_or(POLL_INTERVAL)
+            .min(POLL_INTERVAL),
+        None => POLL_INTERVAL,
+    })
+}
+
+// Run the reminder scheduler on the actix runtime until the server stops
+pub fn spawn_scheduler(data: web::Data<AppState>, sinks: Vec<Box<dyn Sink>>, tz: Tz) {
+    let sinks = Arc::new(sinks);
+    actix_web::rt::spawn(async move {
+        loop {
+            match send_due(&data, &sinks, tz, Utc::now()).await {
+                Ok(0) => {}
+                Ok(sent) => log::info!("sent {} reminders", sent),
+                Err(err) => log::error!("failed to send reminders: {}", err),
+            }
+            let wait = next_wake(&data, tz).unwrap_or(POLL_INTERVAL);
+            // Never spin: wake at most once a second
+            sleep(wait.max(std::time::Duration::from_secs(1))).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app_state, todo, TempDir};
+    use chrono::TimeZone;
+    use std::sync::Mutex;
+
+    // Keeps what it is sent instead of delivering it, standing in for SMTP and webhooks
+    struct RecordingSink(Arc<Mutex<Vec<Notification>>>);
+
+    impl Sink for RecordingSink {
+        fn name(&self) -> &'static str {
+            "recording"
+        }
+
+        fn send(&self, notification: &Notification) -> Result<(), String> {
+            self.0.lock().unwrap().push(notification.clone());
+            Ok(())
+        }
+    }
+
+    // State holding one item with a reminder, and a recording sink whose deliveries can be read back
+    struct Setup {
+        data: web::Data<AppState>,
+        sinks: Arc<Vec<Box<dyn Sink>>>,
+        sent: Arc<Mutex<Vec<Notification>>>,
+        id: Uuid,
+    }
+
+    // An item due at `due_at` with a reminder `minutes` before
+    fn setup(dir: &TempDir, due_at: DateTime<Utc>, minutes: u32) -> Setup {
+        let data = web::Data::new(app_state(dir));
+        let mut item = todo(None);
+        item.due_at = Some(Schedule::Timed(due_at.fixed_offset()));
+        item.reminders.insert(minutes);
+        data.commit(&[Event::TodoCreated(item.clone())]).unwrap();
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let sinks: Vec<Box<dyn Sink>> = vec![Box::new(RecordingSink(Arc::clone(&sent)))];
+        Setup {
+            data,
+            sinks: Arc::new(sinks),
+            sent,
+            id: item.id,
+        }
+    }
+
+    fn reminded(data: &AppState, id: Uuid) -> bool {
+        !data.store.get(id).unwrap().unwrap().reminded.is_empty()
+    }
+
+    #[actix_web::test]
+    async fn a_due_reminder_is_sent_once() {
+        let dir = TempDir::new();
+        let due_at = Utc.with_ymd_and_hms(2026, 3, 2, 9, 0, 0).unwrap();
+        let Setup {
+            data,
+            sinks,
+            sent,
+            id,
+        } = setup(&dir, due_at, 60);
+
+        let early = due_at - Duration::minutes(61);
+        assert_eq!(send_due(&data, &sinks, Tz::UTC, early).await.unwrap(), 0);
+        assert!(sent.lock().unwrap().is_empty());
+
+        let now = due_at - Duration::minutes(59);
+        assert_eq!(send_due(&data, &sinks, Tz::UTC, now).await.unwrap(), 1);
+        assert_eq!(send_due(&data, &sinks, Tz::UTC, now).await.unwrap(), 0);
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].todo_id, id);
+        assert_eq!(sent[0].minutes_before, 60);
+        assert_eq!(sent[0].remind_at, due_at - Duration::minutes(60));
+        assert!(reminded(&data, id));
+    }
+
+    #[actix_web::test]
+    async fn a_reminder_missed_until_the_item_is_due_is_skipped() {
+        let dir = TempDir::new();
+        let due_at = Utc.with_ymd_and_hms(2026, 3, 2, 9, 0, 0).unwrap();
+        let Setup {
+            data,
+            sinks,
+            sent,
+            id,
+        } = setup(&dir, due_at, 30);
+
+        // The server was down from before the reminder until after the item came due
+        let back_up = due_at + Duration::hours(2);
+        assert_eq!(send_due(&data, &sinks, Tz::UTC, back_up).await.unwrap(), 0);
+        assert!(sent.lock().unwrap().is_empty());
+        // ... and it is marked so that it is not sent any later either
+        assert!(reminded(&data, id));
+        assert_eq!(next_wake(&data, Tz::UTC).unwrap(), POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,343 @@
+// Where reminders go: the log, an outbound webhook or an SMTP server, chosen by TODO_REMINDER_SINKS.
+// A webhook or a mailbox belongs to one person, so those two only get the reminders of the account
+// named in TODO_REMINDER_ACCOUNT; the log gets everyone's.
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use std::env;
+use std::time::Duration;
+
+use super::Notification;
+use crate::models::normalize_username;
+
+// A destination for reminders. `send` blocks, so the scheduler calls it off the async workers.
+pub trait Sink: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn send(&self, notification: &Notification) -> Result<(), String>;
+
+    // Whether `notification` is for this sink at all
+    fn accepts(&self, _notification: &Notification) -> bool {
+        true
+    }
+}
+
+// Whether `notification` is a reminder of the account `account`
+fn for_account(notification: &Notification, account: &str) -> bool {
+    notification.account.as_deref() == Some(account)
+}
+
+// Writes reminders to the server log
+pub struct LogSink;
+
+impl Sink for LogSink {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    fn send(&self, notification: &Notification) -> Result<(), String> {
+        log::info!(
+            "reminder for todo {}: {}",
+            notification.todo_id,
+            notification.summary()
+        );
+        Ok(())
+    }
+}
+
+// POSTs each reminder of one account as JSON to a URL
+pub struct WebhookSink {
+    url: String,
+    account: String, // Username whose reminders are sent
+    agent: ureq::Agent,
+}
+
+impl WebhookSink {
+    pub fn new(url: String, account: String) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(10))
+            .build();
+        WebhookSink {
+            url,
+            account,
+            agent,
+        }
+    }
+}
+
+impl Sink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn accepts(&self, notification: &Notification) -> bool {
+        for_account(notification, &self.account)
+    }
+
+    fn send(&self, notification: &Notification) -> Result<(), String> {
+        self.agent
+            .post(&self.url)
+            .send_json(notification)
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+}
+
+// Mails each reminder of one account through an SMTP server
+pub struct SmtpSink {
+    transport: SmtpTransport,
+    from: Mailbox,
+    to: Mailbox,
+    account: String, // Username whose reminders are mailed to `to`
+}
+
+impl Sink for SmtpSink {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    fn accepts(&self, notification: &Notification) -> bool {
+        for_account(notification, &self.account)
+    }
+
+    fn send(&self, notification: &Notification) -> Result<(), String> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(self.to.clone())
+            .subject(format!("Reminder: {}", notification.title))
+            .body(format!(
+                "{}.\n\nThis reminder was set for {} minutes before the due date.\n",
+                notification.summary(),
+                notification.minutes_before
+            ))
+            .map_err(|err| err.to_string())?;
+        self.transport
+            .send(&message)
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+}
+
+// Build the sinks named in the comma-separated TODO_REMINDER_SINKS (default `log`):
+//   webhook  TODO_REMINDER_WEBHOOK_URL and TODO_REMINDER_ACCOUNT
+//   smtp     TODO_SMTP_HOST, TODO_SMTP_FROM, TODO_SMTP_TO, TODO_SMTP_TLS (none, starttls or tls;
+//            default none), TODO_SMTP_PORT (default 25, 587 or 465 to match TODO_SMTP_TLS), and
+//            optionally TODO_SMTP_USERNAME and TODO_SMTP_PASSWORD, and TODO_REMINDER_ACCOUNT
+pub fn sinks_from_env() -> Result<Vec<Box<dyn Sink>>, String> {
+    let names = env::var("TODO_REMINDER_SINKS").unwrap_or_else(|_| "log".to_string());
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    for name in names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        match name {
+            "log" => sinks.push(Box::new(LogSink)),
+            "webhook" => sinks.push(Box::new(WebhookSink::new(
+                required("TODO_REMINDER_WEBHOOK_URL")?,
+                account_from_env()?,
+            ))),
+            "smtp" => sinks.push(Box::new(smtp_from_env()?)),
+            _ => {
+                return Err(format!(
+                    "unknown reminder sink `{}` in TODO_REMINDER_SINKS (use log, webhook or smtp)",
+                    name
+                ))
+            }
+        }
+    }
+    Ok(sinks)
+}
+
+fn smtp_from_env() -> Result<SmtpSink, String> {
+    let host = required("TODO_SMTP_HOST")?;
+    let mailbox = |name: &str| -> Result<Mailbox, String> {
+        let value = required(name)?;
+        value
+            .parse()
+            .map_err(|_| format!("invalid address `{}` in {}", value, name))
+    };
+    let (from, to) = (mailbox("TODO_SMTP_FROM")?, mailbox("TODO_SMTP_TO")?);
+    let tls = env::var("TODO_SMTP_TLS").unwrap_or_else(|_| "none".to_string());
+    let builder = match tls.as_str() {
+        "none" => SmtpTransport::builder_dangerous(&host),
+        "starttls" => SmtpTransport::starttls_relay(&host).map_err(|err| err.to_string())?,
+        "tls" => SmtpTransport::relay(&host).map_err(|err| err.to_string())?,
+        _ => {
+            return Err(format!(
+                "TODO_SMTP_TLS must be none, starttls or tls, found `{}`",
+                tls
+            ))
+        }
+    };
+    let mut builder = builder.timeout(Some(Duration::from_secs(10)));
+    if let Ok(port) = env::var("TODO_SMTP_PORT") {
+        builder = builder.port(
+            port.parse()
+                .map_err(|_| format!("invalid port `{}` in TODO_SMTP_PORT", port))?,
+        );
+    }
+    if let (Ok(username), Ok(password)) = (
+        env::var("TODO_SMTP_USERNAME"),
+        env::var("TODO_SMTP_PASSWORD"),
+    ) {
+        builder = builder.credentials(Credentials::new(username, password));
+    }
+    Ok(SmtpSink {
+        transport: builder.build(),
+        from,
+        to,
+        account: account_from_env()?,
+    })
+}
+
+// Username of the only account whose reminders go to the webhook and the mailbox
+fn account_from_env() -> Result<String, String> {
+    let value = required("TODO_REMINDER_ACCOUNT")?;
+    normalize_username(&value)
+        .ok_or_else(|| format!("invalid username `{}` in TODO_REMINDER_ACCOUNT", value))
+}
+
+fn required(name: &str) -> Result<String, String> {
+    env::var(name).map_err(|_| format!("{} must be set", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Schedule;
+    use chrono::{NaiveDate, Utc};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use uuid::Uuid;
+
+    fn notification(account: &str) -> Notification {
+        Notification {
+            todo_id: Uuid::new_v4(),
+            title: "Pay rent".to_string(),
+            due_at: Schedule::AllDay(NaiveDate::from_ymd_opt(2026, 3, 2).unwrap()),
+            minutes_before: 60,
+            remind_at: Utc::now(),
+            account: Some(account.to_string()),
+        }
+    }
+
+    // A local HTTP server taking one request and sending back its body
+    fn http_stand_in() -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_ascii_lowercase();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let response = "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n";
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            sender.send(String::from_utf8(body).unwrap()).unwrap();
+        });
+        (url, receiver)
+    }
+
+    // A local SMTP server taking one connection and sending back the envelope and message
+    fn smtp_stand_in() -> (u16, mpsc::Receiver<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut seen = Vec::new();
+            let mut data = false;
+            stream.write_all(b"220 localhost ready\r\n").unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_string();
+                let reply: &[u8] = if data {
+                    if line == "." {
+                        data = false;
+                        b"250 queued\r\n"
+                    } else {
+                        seen.push(line);
+                        continue;
+                    }
+                } else {
+                    let command = line.to_ascii_uppercase();
+                    seen.push(line);
+                    if command.starts_with("DATA") {
+                        data = true;
+                        b"354 go ahead\r\n"
+                    } else if command.starts_with("QUIT") {
+                        stream.write_all(b"221 bye\r\n").unwrap();
+                        break;
+                    } else {
+                        b"250 localhost\r\n"
+                    }
+                };
+                stream.write_all(reply).unwrap();
+            }
+            sender.send(seen).unwrap();
+        });
+        (port, receiver)
+    }
+
+    #[test]
+    fn the_webhook_gets_the_reminder_as_json() {
+        let (url, received) = http_stand_in();
+        let sink = WebhookSink::new(url, "ada".to_string());
+        let notification = notification("ada");
+        assert!(sink.accepts(&notification));
+        sink.send(&notification).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&received.recv().unwrap()).unwrap();
+        assert_eq!(body["title"], "Pay rent");
+        assert_eq!(body["todo_id"], notification.todo_id.to_string());
+        assert_eq!(body["minutes_before"], 60);
+        assert!(body.get("account").is_none());
+    }
+
+    #[test]
+    fn the_mailbox_gets_the_reminder() {
+        let (port, received) = smtp_stand_in();
+        let sink = SmtpSink {
+            transport: SmtpTransport::builder_dangerous("127.0.0.1")
+                .port(port)
+                .build(),
+            from: "todo@example.com".parse().unwrap(),
+            to: "ada@example.com".parse().unwrap(),
+            account: "ada".to_string(),
+        };
+        sink.send(&notification("ada")).unwrap();
+        let seen = received.recv().unwrap();
+        let saw = |text: &str| seen.iter().any(|line| line.contains(text));
+        assert!(saw("RCPT TO:<ada@example.com>"));
+        assert!(saw("Subject: Reminder: Pay rent"));
+        assert!(saw("\"Pay rent\" is due 2026-03-02"));
+    }
+
+    #[test]
+    fn only_the_reminders_of_their_account_go_to_mail_and_webhooks() {
+        let sink = WebhookSink::new("http://127.0.0.1:9/hook".to_string(), "ada".to_string());
+        assert!(sink.accepts(&notification("ada")));
+        assert!(!sink.accepts(&notification("bob")));
+        let mut unowned = notification("ada");
+        unowned.account = None;
+        assert!(!sink.accepts(&unowned));
+        assert!(LogSink.accepts(&notification("bob")));
+    }
+}
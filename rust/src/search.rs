@@ -0,0 +1,294 @@
+// In-memory full-text index over live to-do items with stemming, typo tolerance and BM25 ranking
+use rust_stemmers::{Algorithm, Stemmer};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::models::TodoItem;
+use crate::store::{Event, Visibility};
+
+// Number of indexed fields, the text of each and how much a match in it counts
+const FIELD_COUNT: usize = 2;
+const FIELD_WEIGHTS: [f64; FIELD_COUNT] = [1.0, 0.5]; // title, notes
+
+fn field_texts(todo: &TodoItem) -> [&str; FIELD_COUNT] {
+    [&todo.title, &todo.notes]
+}
+
+// BM25 parameters: term frequency saturation and document length normalization
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+// Share of the score kept by a term that only matches as a prefix or with typos
+const PREFIX_WEIGHT: f64 = 0.8;
+const TYPO_WEIGHTS: [f64; 2] = [0.6, 0.4]; // One and two edits away
+
+// Words too common to tell items apart, left out of the index and of queries
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is", "it", "of", "on",
+    "or", "that", "the", "this", "to", "with",
+];
+
+// One word of a text: as written (lowercased) and its stem
+struct Token {
+    word: String,
+    stem: String,
+}
+
+// What the index remembers about each item, needed to score, to filter and to remove it
+struct Document {
+    lengths: [usize; FIELD_COUNT], // Number of indexed terms per field
+    terms: Vec<String>,            // Distinct terms, to find its postings on removal
+    owner_id: Option<Uuid>,        // Account owning the item, whose part of the index holds it
+    list_id: Option<Uuid>,         // List holding the item, for the lists shared with others
+}
+
+// A matching item and its relevance
+pub struct SearchHit {
+    pub id: Uuid,
+    pub score: f64,
+}
+
+// The items of one account, each part scored on its own so the others never have to be looked at
+#[derive(Default)]
+struct Partition {
+    postings: HashMap<String, HashMap<Uuid, [u32; FIELD_COUNT]>>, // Term -> item -> occurrences per field
+    documents: HashMap<Uuid, Document>,
+    total_lengths: [usize; FIELD_COUNT], // Sum of `Document::lengths`, for the average field length
+}
+
+pub struct SearchIndex {
+    stemmer: Stemmer,
+    partitions: HashMap<Option<Uuid>, Partition>, // Owner -> their items
+    owners: HashMap<Uuid, Option<Uuid>>,          // Item -> owner, to find its partition on removal
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        SearchIndex {
+            stemmer: Stemmer::create(Algorithm::English),
+            partitions: HashMap::new(),
+            owners: HashMap::new(),
+        }
+    }
+
+    pub fn build(todos: &[TodoItem]) -> Self {
+        let mut index = SearchIndex::new();
+        todos.iter().for_each(|todo| index.insert(todo));
+        index
+    }
+
+    // Bring the index up to date with a committed event; trashed items drop out of it
+    pub fn apply(&mut self, event: &Event) {
+        match event {
+            Event::TodoCreated(todo) | Event::TodoUpdated(todo) => {
+                if todo.deleted_at.is_none() {
+                    self.insert(todo)
+                } else {
+                    self.remove(todo.id)
+                }
+            }
+            Event::TodoDeleted { id } => self.remove(*id),
+            _ => {}
+        }
+    }
+
+    // Index an item, replacing whatever was indexed for it before
+    pub fn insert(&mut self, todo: &TodoItem) {
+        self.remove(todo.id);
+        let mut lengths = [0; FIELD_COUNT];
+        let mut counts: HashMap<String, [u32; FIELD_COUNT]> = HashMap::new();
+        for (field, text) in field_texts(todo).iter().enumerate() {
+            for token in self.tokenize(text) {
+                lengths[field] += 1;
+                counts.entry(token.stem).or_insert([0; FIELD_COUNT])[field] += 1;
+            }
+        }
+        let partition = self.partitions.entry(todo.owner_id).or_default();
+        for (field, length) in lengths.iter().enumerate() {
+            partition.total_lengths[field] += length;
+        }
+        let terms = counts.keys().cloned().collect();
+        for (term, frequencies) in counts {
+            partition
+                .postings
+                .entry(term)
+                .or_default()
+                .insert(todo.id, frequencies);
+        }
+        let document = Document {
+            lengths,
+            terms,
+            owner_id: todo.owner_id,
+            list_id: todo.list_id,
+        };
+        partition.documents.insert(todo.id, document);
+        self.owners.insert(todo.id, todo.owner_id);
+    }
+
+    pub fn remove(&mut self, id: Uuid) {
+        let owner_id = match self.owners.remove(&id) {
+            Some(owner_id) => owner_id,
+            None => return,
+        };
+        let partition = match self.partitions.get_mut(&owner_id) {
+            Some(partition) => partition,
+            None => return,
+        };
+        let document = match partition.documents.remove(&id) {
+            Some(document) => document,
+            None => return,
+        };
+        for (field, length) in document.lengths.iter().enumerate() {
+            partition.total_lengths[field] -= length;
+        }
+        for term in document.terms {
+            if let Some(postings) = partition.postings.get_mut(&term) {
+                postings.remove(&id);
+                if postings.is_empty() {
+                    partition.postings.remove(&term);
+                }
+            }
+        }
+        if partition.documents.is_empty() {
+            self.partitions.remove(&owner_id);
+        }
+    }
+
+    // Items `visible` covers containing every word of `query`, most relevant first. Only the
+    // items of `owners` are looked at: the account itself and the owners of the lists shared with
+    // it. Words may be misspelled, and the last one may be a prefix of the word being typed.
+    pub fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        visible: &Visibility,
+        owners: &[Option<Uuid>],
+    ) -> Vec<SearchHit> {
+        let tokens = self.tokenize(query);
+        if tokens.is_empty() {
+            return Vec::new();
+        }
+        let mut hits: Vec<SearchHit> = owners
+            .iter()
+            .filter_map(|owner_id| self.partitions.get(owner_id))
+            .flat_map(|partition| {
+                partition.search(&tokens, |document| {
+                    visible.reaches(document.owner_id, document.list_id)
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.id.cmp(&b.id)));
+        hits.truncate(limit);
+        hits
+    }
+
+    // Split text into lowercase words, dropping stop words
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+            .map(|word| Token {
+                stem: self.stemmer.stem(&word).into_owned(),
+                word,
+            })
+            .collect()
+    }
+}
+
+impl Partition {
+    // Items of this partition accepted by `keep` that contain every one of `tokens`
+    fn search(&self, tokens: &[Token], keep: impl Fn(&Document) -> bool) -> Vec<SearchHit> {
+        let mut scores: HashMap<Uuid, (usize, f64)> = HashMap::new(); // Item -> words matched, score
+        for (position, token) in tokens.iter().enumerate() {
+            let is_last = position + 1 == tokens.len();
+            let mut best: HashMap<Uuid, f64> = HashMap::new();
+            for (term, postings) in &self.postings {
+                let weight = match match_weight(token, term, is_last) {
+                    Some(weight) => weight,
+                    None => continue,
+                };
+                let idf = self.idf(postings.len());
+                for (id, frequencies) in postings {
+                    let document = &self.documents[id];
+                    if !keep(document) {
+                        continue;
+                    }
+                    let score = weight * idf * self.term_score(document, frequencies);
+                    let entry = best.entry(*id).or_insert(0.0);
+                    *entry = entry.max(score);
+                }
+            }
+            for (id, score) in best {
+                let entry = scores.entry(id).or_insert((0, 0.0));
+                entry.0 += 1;
+                entry.1 += score;
+            }
+        }
+        scores
+            .into_iter()
+            .filter(|(_, (matched, _))| *matched == tokens.len())
+            .map(|(id, (_, score))| SearchHit { id, score })
+            .collect()
+    }
+
+    // Inverse document frequency of a term found in `matching` items
+    fn idf(&self, matching: usize) -> f64 {
+        let total = self.documents.len() as f64;
+        let matching = matching as f64;
+        (1.0 + (total - matching + 0.5) / (matching + 0.5)).ln()
+    }
+
+    // BM25 term frequency component, summed over the fields of an item
+    fn term_score(&self, document: &Document, frequencies: &[u32; FIELD_COUNT]) -> f64 {
+        let count = self.documents.len() as f64;
+        (0..FIELD_COUNT)
+            .filter(|&field| frequencies[field] > 0)
+            .map(|field| {
+                let frequency = f64::from(frequencies[field]);
+                let average = (self.total_lengths[field] as f64 / count).max(1.0);
+                let length = document.lengths[field] as f64 / average;
+                FIELD_WEIGHTS[field] * frequency * (K1 + 1.0)
+                    / (frequency + K1 * (1.0 - B + B * length))
+            })
+            .sum()
+    }
+}
+
+// How well an indexed term answers a query word, if at all
+fn match_weight(token: &Token, term: &str, is_last: bool) -> Option<f64> {
+    if term == token.stem {
+        return Some(1.0);
+    }
+    if is_last
+        && token.word.chars().count() >= 2
+        && (term.starts_with(&token.word) || term.starts_with(&token.stem))
+    {
+        return Some(PREFIX_WEIGHT);
+    }
+    // Stemming can mangle a misspelled word, so compare the word as written too
+    [&token.stem, &token.word]
+        .iter()
+        .filter_map(|candidate| {
+            let allowed = allowed_typos(candidate);
+            let length = candidate.chars().count();
+            if allowed == 0 || length.abs_diff(term.chars().count()) > allowed {
+                return None;
+            }
+            Some(strsim::levenshtein(candidate, term)).filter(|&distance| distance <= allowed)
+        })
+        .min()
+        .map(|distance| match distance {
+            0 => 1.0,
+            distance => TYPO_WEIGHTS[distance - 1],
+        })
+}
+
+// Short words must be spelled right; longer ones may be one or two edits off
+fn allowed_typos(word: &str) -> usize {
+    match word.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
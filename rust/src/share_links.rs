@@ -0,0 +1,130 @@
+// Signed tokens of the links that show a list or an item to people without an account
+//
+// A token holds the id of its link and the second it expires, followed by an HMAC-SHA256 of both,
+// all in URL-safe base64. The signature lets forged or altered tokens be turned away before the
+// store is asked; the link itself is still looked up, so deleting it revokes the token at once.
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::hmac;
+use ring::rand::SystemRandom;
+use std::env;
+use uuid::Uuid;
+
+use crate::models::ShareLink;
+
+// Bytes of the id and expiry signed in a token
+const PAYLOAD_LEN: usize = 16 + 8;
+
+// Shortest TODO_SHARE_SECRET accepted, in bytes
+const MIN_SECRET_LEN: usize = 32;
+
+pub struct ShareSigner {
+    key: hmac::Key,
+}
+
+impl ShareSigner {
+    // Signer keyed with TODO_SHARE_SECRET. Without it a random key is made, and links stop working
+    // when the server restarts.
+    pub fn from_env() -> Result<Self, String> {
+        let key = match env::var("TODO_SHARE_SECRET") {
+            Ok(secret) if secret.len() >= MIN_SECRET_LEN => {
+                hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes())
+            }
+            Ok(_) => {
+                return Err(format!(
+                    "TODO_SHARE_SECRET must be at least {} bytes long",
+                    MIN_SECRET_LEN
+                ))
+            }
+            Err(_) => {
+                log::warn!("TODO_SHARE_SECRET is not set; share links will not survive a restart");
+                hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+                    .map_err(|_| "cannot generate a share link key".to_string())?
+            }
+        };
+        Ok(ShareSigner { key })
+    }
+
+    // The token opening `link`
+    pub fn token(&self, link: &ShareLink) -> String {
+        let mut bytes = payload(link.id, link.expires_at).to_vec();
+        bytes.extend_from_slice(hmac::sign(&self.key, &bytes).as_ref());
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    // The id of the link and the time it expires, if `token` was signed with this key
+    pub fn verify(&self, token: &str) -> Option<(Uuid, DateTime<Utc>)> {
+        let bytes = URL_SAFE_NO_PAD.decode(token).ok()?;
+        if bytes.len() <= PAYLOAD_LEN {
+            return None;
+        }
+        let (signed, signature) = bytes.split_at(PAYLOAD_LEN);
+        hmac::verify(&self.key, signed, signature).ok()?;
+        let id = Uuid::from_slice(&signed[..16]).ok()?;
+        let mut seconds = [0; 8];
+        seconds.copy_from_slice(&signed[16..]);
+        let expires_at = DateTime::from_timestamp(i64::from_be_bytes(seconds), 0)?;
+        Some((id, expires_at))
+    }
+}
+
+fn payload(id: Uuid, expires_at: DateTime<Utc>) -> [u8; PAYLOAD_LEN] {
+    let mut bytes = [0; PAYLOAD_LEN];
+    bytes[..16].copy_from_slice(id.as_bytes());
+    bytes[16..].copy_from_slice(&expires_at.timestamp().to_be_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    const SECRET: &str = "the secret the links are signed with";
+
+    fn signer(secret: &str) -> ShareSigner {
+        ShareSigner {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+        }
+    }
+
+    fn link() -> ShareLink {
+        let now = DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap();
+        ShareLink {
+            id: Uuid::new_v4(),
+            owner_id: Uuid::new_v4(),
+            list_id: Some(Uuid::new_v4()),
+            todo_id: None,
+            created_at: now,
+            expires_at: now + Duration::hours(1),
+        }
+    }
+
+    #[test]
+    fn a_token_verifies_to_its_link() {
+        let signer = signer(SECRET);
+        let link = link();
+        let token = signer.token(&link);
+        assert_eq!(signer.verify(&token), Some((link.id, link.expires_at)));
+    }
+
+    #[test]
+    fn an_altered_or_cut_token_is_refused() {
+        let signer = signer(SECRET);
+        let token = signer.token(&link());
+        let mut bytes = URL_SAFE_NO_PAD.decode(&token).unwrap();
+        // Move the expiry by a second
+        bytes[PAYLOAD_LEN - 1] ^= 1;
+        assert_eq!(signer.verify(&URL_SAFE_NO_PAD.encode(&bytes)), None);
+        assert_eq!(signer.verify(&token[..token.len() - 1]), None);
+        assert_eq!(signer.verify(&token[..PAYLOAD_LEN]), None);
+        assert_eq!(signer.verify(""), None);
+    }
+
+    #[test]
+    fn a_token_signed_with_another_key_is_refused() {
+        let token = signer("a secret of some other server").token(&link());
+        assert_eq!(signer(SECRET).verify(&token), None);
+    }
+}
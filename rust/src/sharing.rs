@@ -0,0 +1,48 @@
+// Who may see and change which lists and items
+//
+// A list belongs to its owner, and so does every item in it, whoever added it. Sharing a list gives
+// its members a role on the list and all of its items: viewers see them, editors also change them.
+// Items in no list are only ever seen by their owner.
+use crate::models::{Role, TodoItem, TodoList, User};
+use crate::store::{StoreResult, TodoStore, Visibility};
+
+// The items `user` can see with at least the role `min`: their own, and those in the lists shared
+// with them with such a role
+pub fn visibility(store: &dyn TodoStore, user: &User, min: Role) -> StoreResult<Visibility> {
+    Ok(Visibility {
+        user_id: user.id,
+        lists: store
+            .memberships(user.id)?
+            .into_iter()
+            .filter(|member| member.role >= min)
+            .map(|member| member.list_id)
+            .collect(),
+    })
+}
+
+// Role of `user` on `list`, if they have access to it at all
+pub fn list_role(store: &dyn TodoStore, user: &User, list: &TodoList) -> StoreResult<Option<Role>> {
+    if user.owns(list.owner_id) {
+        return Ok(Some(Role::Owner));
+    }
+    Ok(store
+        .memberships(user.id)?
+        .into_iter()
+        .find(|member| member.list_id == list.id)
+        .map(|member| member.role))
+}
+
+// Role of `user` on `todo`: owner of their own items, otherwise their role on the list holding it
+pub fn todo_role(store: &dyn TodoStore, user: &User, todo: &TodoItem) -> StoreResult<Option<Role>> {
+    if user.owns(todo.owner_id) {
+        return Ok(Some(Role::Owner));
+    }
+    match todo.list_id {
+        Some(list_id) => Ok(store
+            .memberships(user.id)?
+            .into_iter()
+            .find(|member| member.list_id == list_id)
+            .map(|member| member.role)),
+        None => Ok(None),
+    }
+}
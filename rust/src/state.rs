@@ -0,0 +1,80 @@
+// Application state shared across requests and background tasks
+use chrono::Duration;
+use std::collections::HashSet;
+use std::sync::{Mutex, RwLock};
+
+use crate::attachments::BlobStore;
+use crate::jwt::JwtVerifier;
+use crate::rank;
+use crate::search::SearchIndex;
+use crate::share_links::ShareSigner;
+use crate::store::{Event, StoreResult, TodoQuery, TodoStore};
+
+pub struct AppState {
+    pub store: Box<dyn TodoStore>, // Storage engine holding the to-do items, safe to share across worker threads
+    pub writes: Mutex<()>, // Serializes read-modify-write sequences so concurrent updates are not lost
+    pub search: RwLock<SearchIndex>, // Full-text index over the live items, kept in step with every commit
+    pub blobs: BlobStore, // Attachment contents on disk, cleaned up as attachments are deleted
+    pub session_ttl: Duration, // How long a session started at login lasts
+    pub jwt: Option<JwtVerifier>, // Checks JWTs signed in with, when they are turned on
+    pub share_signer: ShareSigner, // Signs and checks the tokens of share links
+}
+
+impl AppState {
+    // Wrap a freshly opened store, ranking and indexing the items it already holds and removing
+    // blobs no attachment refers to
+    pub fn new(
+        store: Box<dyn TodoStore>,
+        blobs: BlobStore,
+        session_ttl: Duration,
+        jwt: Option<JwtVerifier>,
+        share_signer: ShareSigner,
+    ) -> StoreResult<Self> {
+        let ranked = rank::backfill(store.as_ref())?;
+        if !ranked.is_empty() {
+            log::info!(
+                "ranked {} todos stored before manual ordering",
+                ranked.len()
+            );
+            store.commit(&ranked)?;
+        }
+        let swept = blobs.sweep(store.as_ref())?;
+        if swept > 0 {
+            log::info!("removed {} unused attachment blobs", swept);
+        }
+        let search = SearchIndex::build(&store.list(&TodoQuery::default())?);
+        Ok(AppState {
+            store,
+            writes: Mutex::new(()),
+            search: RwLock::new(search),
+            blobs,
+            session_ttl,
+            jwt,
+            share_signer,
+        })
+    }
+
+    // Write a batch of events to the store, then fold them into the in-memory indexes and remove
+    // the blobs of deleted attachments that nothing else refers to.
+    // Callers hold `writes`, so indexes see batches in the same order as the store.
+    pub fn commit(&self, events: &[Event]) -> StoreResult<()> {
+        let mut digests = HashSet::new();
+        for event in events {
+            if let Event::AttachmentDeleted { id } = event {
+                if let Some(attachment) = self.store.attachment(*id)? {
+                    digests.insert(attachment.digest);
+                }
+            }
+        }
+        self.store.commit(events)?;
+        {
+            let mut search = self.search.write().unwrap();
+            events.iter().for_each(|event| search.apply(event));
+        }
+        // The events are saved either way; a blob left behind is only wasted space
+        if let Err(err) = self.blobs.remove_unused(self.store.as_ref(), &digests) {
+            log::error!("failed to clean up attachment blobs: {}", err);
+        }
+        Ok(())
+    }
+}
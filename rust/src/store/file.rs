@@ -0,0 +1,52 @@
+// Store keeping all data in a single JSON file
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use super::memory::{State, StateStore};
+use super::{Event, StoreResult};
+
+// Serves reads from memory and rewrites the whole file after every change
+pub struct FileStore {
+    path: PathBuf,        // JSON file holding the serialized state
+    state: RwLock<State>, // Mirror of the file contents
+}
+
+impl FileStore {
+    // Load the state from `path`, starting empty if the file does not exist yet
+    pub fn open(path: impl AsRef<Path>) -> StoreResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let state = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+            Err(err) if err.kind() == ErrorKind::NotFound => State::default(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(FileStore {
+            path,
+            state: RwLock::new(state),
+        })
+    }
+
+    // Write to a sibling temporary file first so a crash never leaves a half-written file behind
+    fn persist(&self, state: &State) -> StoreResult<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer_pretty(&mut writer, state)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl StateStore for FileStore {
+    fn state(&self) -> &RwLock<State> {
+        &self.state
+    }
+
+    // The whole file is rewritten with the new state
+    fn save(&self, updated: &State, _: &[Event]) -> StoreResult<()> {
+        self.persist(updated)
+    }
+}
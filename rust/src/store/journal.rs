@@ -0,0 +1,271 @@
+// Event-sourced store: every change is appended to an on-disk journal and replayed on startup
+//
+// The data directory holds two files:
+//   journal.log   - append-only records, each `[len: u32 LE][crc32: u32 LE][JSON payload]` holding one
+//                   committed batch of events
+//   snapshot.json - the full state as of some sequence number, written atomically
+//
+// On startup the snapshot is loaded and every journal record with a higher sequence number is
+// replayed on top of it. A torn or corrupt record at the tail (e.g. from a crash mid-write) ends the
+// replay and is cut off, so the journal always stays appendable. A write that fails while the
+// server runs is cut off right away, so that later records never end up behind a torn one.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+use super::memory::{State, StateStore};
+use super::{Event, StoreError, StoreResult};
+
+const JOURNAL_FILE: &str = "journal.log";
+const SNAPSHOT_FILE: &str = "snapshot.json";
+
+// Size of the length + checksum header in front of every record
+const HEADER_LEN: usize = 8;
+
+// Refuse to allocate for records claiming to be larger than this; such a header can only be garbage
+const MAX_RECORD_LEN: usize = 64 * 1024 * 1024;
+
+// One entry of the journal
+#[derive(Serialize, Deserialize)]
+struct Record {
+    seq: u64,           // Position of the batch in the overall history, starting at 1
+    at: DateTime<Utc>,  // When the batch was written
+    events: Vec<Event>, // The changes, applied together
+}
+
+// Compacted state written every `snapshot_every` records
+#[derive(Serialize, Deserialize, Default)]
+struct Snapshot {
+    seq: u64, // Sequence number of the last record folded into this snapshot
+    #[serde(flatten)]
+    state: State,
+}
+
+// Open journal file plus the bookkeeping needed to append to it
+struct Writer {
+    file: File,            // Journal opened for appending
+    seq: u64,              // Sequence number of the last written event
+    since_snapshot: usize, // Records appended since the last snapshot was taken
+    failed: bool,          // A torn record could not be cut off again; nothing more is appended
+}
+
+pub struct JournalStore {
+    dir: PathBuf,          // Data directory holding the journal and snapshot
+    snapshot_every: usize, // Compact the journal into a snapshot after this many records
+    state: RwLock<State>,  // Current state rebuilt from the snapshot and the journal
+    writer: Mutex<Writer>, // Serializes appends so journal order matches apply order
+}
+
+impl JournalStore {
+    // Open the data directory at `dir`, creating it if needed, and rebuild the state from disk
+    pub fn open(dir: impl AsRef<Path>, snapshot_every: usize) -> StoreResult<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let snapshot = match File::open(dir.join(SNAPSHOT_FILE)) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+            Err(err) if err.kind() == ErrorKind::NotFound => Snapshot::default(),
+            Err(err) => return Err(err.into()),
+        };
+        let snapshot_seq = snapshot.seq;
+        let mut state = snapshot.state;
+        let mut seq = snapshot_seq;
+
+        let journal_path = dir.join(JOURNAL_FILE);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&journal_path)?;
+        let (records, valid_len) = read_records(&mut file)?;
+        let total_len = file.metadata()?.len();
+        if valid_len < total_len {
+            log::warn!(
+                "journal {} has {} unreadable trailing bytes, truncating",
+                journal_path.display(),
+                total_len - valid_len
+            );
+            file.set_len(valid_len)?;
+            file.sync_all()?;
+        }
+
+        let mut replayed = 0;
+        for record in records
+            .into_iter()
+            .filter(|record| record.seq > snapshot_seq)
+        {
+            record.events.iter().for_each(|event| state.apply(event));
+            seq = record.seq;
+            replayed += 1;
+        }
+        log::info!(
+            "loaded {} todos from {} (snapshot at #{}, {} records replayed)",
+            state.todos.len(),
+            dir.display(),
+            snapshot_seq,
+            replayed
+        );
+
+        Ok(JournalStore {
+            dir,
+            snapshot_every: snapshot_every.max(1),
+            state: RwLock::new(state),
+            writer: Mutex::new(Writer {
+                file,
+                seq,
+                since_snapshot: replayed,
+                failed: false,
+            }),
+        })
+    }
+
+    // Durably append a batch of events as one record; `updated` is the state it leads to
+    fn append(&self, updated: &State, events: &[Event]) -> StoreResult<()> {
+        let mut writer = self.writer.lock().unwrap();
+        if writer.failed {
+            return Err(StoreError::Io(io::Error::other(format!(
+                "journal {} ends in a torn record; restart to recover it",
+                self.dir.display()
+            ))));
+        }
+        let record = Record {
+            seq: writer.seq + 1,
+            at: Utc::now(),
+            events: events.to_vec(),
+        };
+        let payload = serde_json::to_vec(&record)?;
+        let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        frame.extend_from_slice(&payload);
+        // A record that does not make it to disk whole is cut off again. Left in place, it would end
+        // the replay at the next start and take every later record down with it.
+        let len = writer.file.metadata()?.len();
+        if let Err(err) = writer
+            .file
+            .write_all(&frame)
+            .and_then(|()| writer.file.sync_data())
+        {
+            if let Err(rollback) = writer.file.set_len(len) {
+                log::error!(
+                    "failed to cut a torn record off {}: {}",
+                    self.dir.display(),
+                    rollback
+                );
+                writer.failed = true;
+            }
+            return Err(err.into());
+        }
+
+        writer.seq = record.seq;
+        writer.since_snapshot += 1;
+
+        if writer.since_snapshot >= self.snapshot_every {
+            // A failed snapshot is not fatal: the journal still holds every record
+            match self.snapshot(&mut writer, updated) {
+                Ok(()) => writer.since_snapshot = 0,
+                Err(err) => log::error!("failed to snapshot {}: {}", self.dir.display(), err),
+            }
+        }
+        Ok(())
+    }
+
+    // Write the current state as a snapshot, then empty the journal it supersedes
+    fn snapshot(&self, writer: &mut Writer, state: &State) -> StoreResult<()> {
+        let tmp_path = self.dir.join(format!("{}.tmp", SNAPSHOT_FILE));
+        let mut out = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(
+            &mut out,
+            &Snapshot {
+                seq: writer.seq,
+                state: state.clone(),
+            },
+        )?;
+        out.flush()?;
+        out.get_ref().sync_all()?;
+        fs::rename(&tmp_path, self.dir.join(SNAPSHOT_FILE))?;
+        File::open(&self.dir)?.sync_all()?;
+
+        // Records up to `seq` are now covered by the snapshot; if we crash before this truncation they
+        // are simply skipped on the next replay
+        writer.file.set_len(0)?;
+        writer.file.sync_all()?;
+        Ok(())
+    }
+}
+
+// Read every intact record from the start of `file`, returning them with the length of the valid prefix
+fn read_records(file: &mut File) -> StoreResult<(Vec<Record>, u64)> {
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while bytes.len() - offset >= HEADER_LEN {
+        let len = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap());
+        let start = offset + HEADER_LEN;
+        if len > MAX_RECORD_LEN || bytes.len() - start < len {
+            break;
+        }
+        let payload = &bytes[start..start + len];
+        if crc32fast::hash(payload) != crc {
+            break;
+        }
+        match serde_json::from_slice(payload) {
+            Ok(record) => records.push(record),
+            Err(_) => break,
+        }
+        offset = start + len;
+    }
+    Ok((records, offset as u64))
+}
+
+impl StateStore for JournalStore {
+    fn state(&self) -> &RwLock<State> {
+        &self.state
+    }
+
+    fn save(&self, updated: &State, events: &[Event]) -> StoreResult<()> {
+        self.append(updated, events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TodoStore;
+    use crate::testing::{todo, TempDir};
+
+    #[test]
+    fn a_torn_record_is_cut_off_without_losing_later_ones() {
+        let dir = TempDir::new();
+        let path = dir.path("journal");
+        let journal = Path::new(&path).join(JOURNAL_FILE);
+        let (first, torn, third) = (todo(None), todo(None), todo(None));
+        {
+            let store = JournalStore::open(&path, 1000).unwrap();
+            store.commit(&[Event::TodoCreated(first.clone())]).unwrap();
+            let intact = fs::metadata(&journal).unwrap().len();
+            store.commit(&[Event::TodoCreated(torn.clone())]).unwrap();
+            // Cut the second record off halfway, as a crash while writing it would
+            let len = fs::metadata(&journal).unwrap().len();
+            let file = OpenOptions::new().write(true).open(&journal).unwrap();
+            file.set_len(intact + (len - intact) / 2).unwrap();
+        }
+        {
+            let store = JournalStore::open(&path, 1000).unwrap();
+            assert!(store.get(first.id).unwrap().is_some());
+            assert!(store.get(torn.id).unwrap().is_none());
+            store.commit(&[Event::TodoCreated(third.clone())]).unwrap();
+        }
+        let store = JournalStore::open(&path, 1000).unwrap();
+        assert!(store.get(first.id).unwrap().is_some());
+        assert!(store.get(torn.id).unwrap().is_none());
+        assert!(store.get(third.id).unwrap().is_some());
+    }
+}
@@ -0,0 +1,772 @@
+// Volatile in-process store, wiped on every restart
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+use super::{Event, StoreError, StoreResult, TodoQuery, TodoStore};
+use crate::models::{
+    AccessChange, ApiToken, Attachment, Comment, Invitation, ListMember, Revision, Session,
+    ShareLink, SmartList, TodoItem, TodoList, TwoFactor, User,
+};
+
+// Everything a store keeps, held in memory by the memory, file and journal engines
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct State {
+    #[serde(default)]
+    pub todos: Vec<TodoItem>, // Items in insertion order
+    #[serde(default)]
+    pub revisions: Vec<Revision>, // Revisions of every item, in the order they were recorded
+    #[serde(default)]
+    pub smart_lists: Vec<SmartList>, // Saved searches in creation order
+    #[serde(default)]
+    pub lists: Vec<TodoList>, // Lists in creation order
+    #[serde(default)]
+    pub attachments: Vec<Attachment>, // Attachments in upload order
+    #[serde(default)]
+    pub comments: Vec<Comment>, // Comments in the order they were posted
+    #[serde(default)]
+    pub users: Vec<User>, // Accounts in the order they registered
+    #[serde(default)]
+    pub sessions: Vec<Session>, // Sessions in the order they were started
+    #[serde(default)]
+    pub api_tokens: Vec<ApiToken>, // API tokens in the order they were created
+    #[serde(default)]
+    pub members: Vec<ListMember>, // List members in the order they joined
+    #[serde(default)]
+    pub invitations: Vec<Invitation>, // Open invitations in the order they were sent
+    #[serde(default)]
+    pub access_log: Vec<AccessChange>, // Audit log entries of every list, oldest first
+    #[serde(default)]
+    pub share_links: Vec<ShareLink>, // Share links in the order they were made
+    #[serde(default)]
+    pub two_factors: Vec<TwoFactor>, // Second sign-in steps in the order they were enrolled
+}
+
+impl State {
+    pub fn list(&self, query: &TodoQuery) -> Vec<TodoItem> {
+        query.apply(self.todos.iter())
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<TodoItem> {
+        self.todos.iter().find(|todo| todo.id == id).cloned()
+    }
+
+    pub fn revisions(&self, todo_id: Uuid) -> Vec<Revision> {
+        self.revisions
+            .iter()
+            .filter(|revision| revision.todo_id == todo_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn smart_list(&self, id: Uuid) -> Option<SmartList> {
+        self.smart_lists.iter().find(|list| list.id == id).cloned()
+    }
+
+    pub fn todo_lists(&self) -> Vec<TodoList> {
+        let mut lists = self.lists.clone();
+        lists.sort_by_key(|list| (list.position, list.created_at, list.id));
+        lists
+    }
+
+    pub fn todo_list(&self, id: Uuid) -> Option<TodoList> {
+        self.lists.iter().find(|list| list.id == id).cloned()
+    }
+
+    pub fn attachments(&self, todo_id: Option<Uuid>) -> Vec<Attachment> {
+        self.attachments
+            .iter()
+            .filter(|attachment| todo_id.is_none_or(|todo_id| attachment.todo_id == todo_id))
+            .cloned()
+            .collect()
+    }
+
+    pub fn comments(&self, todo_id: Uuid) -> Vec<Comment> {
+        self.comments
+            .iter()
+            .filter(|comment| comment.todo_id == todo_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn comment(&self, id: Uuid) -> Option<Comment> {
+        self.comments
+            .iter()
+            .find(|comment| comment.id == id)
+            .cloned()
+    }
+
+    pub fn comment_counts(&self, todo_ids: &[Uuid]) -> HashMap<Uuid, usize> {
+        let mut counts = HashMap::new();
+        for comment in &self.comments {
+            if todo_ids.contains(&comment.todo_id) {
+                *counts.entry(comment.todo_id).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    pub fn user(&self, id: Uuid) -> Option<User> {
+        self.users.iter().find(|user| user.id == id).cloned()
+    }
+
+    pub fn user_by_name(&self, username: &str) -> Option<User> {
+        self.users
+            .iter()
+            .find(|user| user.username == username)
+            .cloned()
+    }
+
+    pub fn session(&self, id: &str) -> Option<Session> {
+        self.sessions
+            .iter()
+            .find(|session| session.id == id)
+            .cloned()
+    }
+
+    pub fn sessions(&self, user_id: Uuid) -> Vec<Session> {
+        self.sessions
+            .iter()
+            .filter(|session| session.user_id == user_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn api_token(&self, id: Uuid) -> Option<ApiToken> {
+        self.api_tokens.iter().find(|token| token.id == id).cloned()
+    }
+
+    pub fn api_token_by_hash(&self, hash: &str) -> Option<ApiToken> {
+        self.api_tokens
+            .iter()
+            .find(|token| token.hash == hash)
+            .cloned()
+    }
+
+    pub fn api_tokens(&self, user_id: Uuid) -> Vec<ApiToken> {
+        self.api_tokens
+            .iter()
+            .filter(|token| token.user_id == user_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn member(&self, list_id: Uuid, user_id: Uuid) -> Option<ListMember> {
+        self.members
+            .iter()
+            .find(|member| member.list_id == list_id && member.user_id == user_id)
+            .cloned()
+    }
+
+    pub fn members(&self, list_id: Uuid) -> Vec<ListMember> {
+        self.members
+            .iter()
+            .filter(|member| member.list_id == list_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn memberships(&self, user_id: Uuid) -> Vec<ListMember> {
+        self.members
+            .iter()
+            .filter(|member| member.user_id == user_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn invitation(&self, id: Uuid) -> Option<Invitation> {
+        self.invitations
+            .iter()
+            .find(|invitation| invitation.id == id)
+            .cloned()
+    }
+
+    pub fn list_invitations(&self, list_id: Uuid) -> Vec<Invitation> {
+        self.invitations
+            .iter()
+            .filter(|invitation| invitation.list_id == list_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn user_invitations(&self, user_id: Uuid) -> Vec<Invitation> {
+        self.invitations
+            .iter()
+            .filter(|invitation| invitation.user_id == user_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn share_link(&self, id: Uuid) -> Option<ShareLink> {
+        self.share_links.iter().find(|link| link.id == id).cloned()
+    }
+
+    pub fn share_links(&self, owner_id: Uuid) -> Vec<ShareLink> {
+        self.share_links
+            .iter()
+            .filter(|link| link.owner_id == owner_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn list_share_links(&self, list_id: Uuid) -> Vec<ShareLink> {
+        self.share_links
+            .iter()
+            .filter(|link| link.list_id == Some(list_id))
+            .cloned()
+            .collect()
+    }
+
+    pub fn two_factor(&self, user_id: Uuid) -> Option<TwoFactor> {
+        self.two_factors
+            .iter()
+            .find(|factor| factor.user_id == user_id)
+            .cloned()
+    }
+
+    pub fn access_log(&self, list_id: Uuid) -> Vec<AccessChange> {
+        self.access_log
+            .iter()
+            .filter(|change| change.list_id == list_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn attachment(&self, id: Uuid) -> Option<Attachment> {
+        self.attachments
+            .iter()
+            .find(|attachment| attachment.id == id)
+            .cloned()
+    }
+
+    // Apply a batch in place, each event checked against what the ones before it left behind as
+    // the SQLite engine does. The first event that does not fit fails the whole batch, and `self`
+    // is put back as it was; otherwise the returned rollback can still take the batch back.
+    pub fn apply_batch(&mut self, events: &[Event]) -> StoreResult<Rollback> {
+        let mut rollback = Rollback(Vec::with_capacity(events.len()));
+        for event in events {
+            if let Err(err) = self.check(event) {
+                rollback.undo(self);
+                return Err(err);
+            }
+            rollback.0.push(self.apply_undoable(event));
+        }
+        Ok(rollback)
+    }
+
+    // Make sure a single event can be applied to the state as it is
+    fn check(&self, event: &Event) -> StoreResult<()> {
+        match event {
+            Event::TodoCreated(todo) if self.contains(todo.id) => {
+                return Err(StoreError::Conflict(format!(
+                    "todo {} already exists",
+                    todo.id
+                )))
+            }
+            Event::TodoUpdated(TodoItem { id, .. }) | Event::TodoDeleted { id }
+                if !self.contains(*id) =>
+            {
+                return Err(StoreError::Conflict(format!("todo {} does not exist", id)))
+            }
+            Event::SmartListCreated(list) if self.has_smart_list(list.id) => {
+                return Err(StoreError::Conflict(format!(
+                    "smart list {} already exists",
+                    list.id
+                )))
+            }
+            Event::SmartListUpdated(SmartList { id, .. }) | Event::SmartListDeleted { id }
+                if !self.has_smart_list(*id) =>
+            {
+                return Err(StoreError::Conflict(format!(
+                    "smart list {} does not exist",
+                    id
+                )))
+            }
+            Event::ListCreated(list) if self.has_list(list.id) => {
+                return Err(StoreError::Conflict(format!(
+                    "list {} already exists",
+                    list.id
+                )))
+            }
+            Event::ListUpdated(TodoList { id, .. }) | Event::ListDeleted { id }
+                if !self.has_list(*id) =>
+            {
+                return Err(StoreError::Conflict(format!("list {} does not exist", id)))
+            }
+            Event::AttachmentCreated(attachment) if self.attachment(attachment.id).is_some() => {
+                return Err(StoreError::Conflict(format!(
+                    "attachment {} already exists",
+                    attachment.id
+                )))
+            }
+            Event::AttachmentDeleted { id } if self.attachment(*id).is_none() => {
+                return Err(StoreError::Conflict(format!(
+                    "attachment {} does not exist",
+                    id
+                )))
+            }
+            Event::CommentCreated(comment) if self.comment(comment.id).is_some() => {
+                return Err(StoreError::Conflict(format!(
+                    "comment {} already exists",
+                    comment.id
+                )))
+            }
+            Event::CommentUpdated(Comment { id, .. }) | Event::CommentDeleted { id }
+                if self.comment(*id).is_none() =>
+            {
+                return Err(StoreError::Conflict(format!(
+                    "comment {} does not exist",
+                    id
+                )))
+            }
+            Event::UserCreated(user)
+                if self.user(user.id).is_some() || self.user_by_name(&user.username).is_some() =>
+            {
+                return Err(StoreError::Conflict(format!(
+                    "user {} already exists",
+                    user.username
+                )))
+            }
+            Event::UserUpdated(User { id, .. }) if self.user(*id).is_none() => {
+                return Err(StoreError::Conflict(format!("user {} does not exist", id)))
+            }
+            Event::SessionCreated(session) if self.session(&session.id).is_some() => {
+                return Err(StoreError::Conflict("session already exists".to_string()))
+            }
+            Event::SessionDeleted { id } if self.session(id).is_none() => {
+                return Err(StoreError::Conflict("session does not exist".to_string()))
+            }
+            Event::ApiTokenCreated(token)
+                if self.api_token(token.id).is_some()
+                    || self.api_token_by_hash(&token.hash).is_some() =>
+            {
+                return Err(StoreError::Conflict(format!(
+                    "api token {} already exists",
+                    token.id
+                )))
+            }
+            Event::ApiTokenUpdated(ApiToken { id, .. }) | Event::ApiTokenDeleted { id }
+                if self.api_token(*id).is_none() =>
+            {
+                return Err(StoreError::Conflict(format!(
+                    "api token {} does not exist",
+                    id
+                )))
+            }
+            Event::MemberAdded(member) if self.member(member.list_id, member.user_id).is_some() => {
+                return Err(StoreError::Conflict(format!(
+                    "list {} is already shared with {}",
+                    member.list_id, member.user_id
+                )))
+            }
+            Event::MemberUpdated(ListMember {
+                list_id, user_id, ..
+            })
+            | Event::MemberRemoved { list_id, user_id }
+                if self.member(*list_id, *user_id).is_none() =>
+            {
+                return Err(StoreError::Conflict(format!(
+                    "list {} is not shared with {}",
+                    list_id, user_id
+                )))
+            }
+            Event::InvitationCreated(invitation) if self.invitation(invitation.id).is_some() => {
+                return Err(StoreError::Conflict(format!(
+                    "invitation {} already exists",
+                    invitation.id
+                )))
+            }
+            Event::InvitationDeleted { id } if self.invitation(*id).is_none() => {
+                return Err(StoreError::Conflict(format!(
+                    "invitation {} does not exist",
+                    id
+                )))
+            }
+            Event::ShareLinkCreated(link) if self.share_link(link.id).is_some() => {
+                return Err(StoreError::Conflict(format!(
+                    "share link {} already exists",
+                    link.id
+                )))
+            }
+            Event::ShareLinkDeleted { id } if self.share_link(*id).is_none() => {
+                return Err(StoreError::Conflict(format!(
+                    "share link {} does not exist",
+                    id
+                )))
+            }
+            Event::TwoFactorCreated(factor) if self.two_factor(factor.user_id).is_some() => {
+                return Err(StoreError::Conflict(format!(
+                    "account {} already has a second sign-in step",
+                    factor.user_id
+                )))
+            }
+            Event::TwoFactorUpdated(TwoFactor { user_id, .. })
+            | Event::TwoFactorDeleted { user_id }
+                if self.two_factor(*user_id).is_none() =>
+            {
+                return Err(StoreError::Conflict(format!(
+                    "account {} has no second sign-in step",
+                    user_id
+                )))
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Fold a single event into the state
+    pub fn apply(&mut self, event: &Event) {
+        let _ = self.apply_undoable(event);
+    }
+
+    // Fold a single event into the state, returning what it takes to take it back again
+    fn apply_undoable(&mut self, event: &Event) -> Undo {
+        match event {
+            Event::TodoCreated(todo) => push(|state| &mut state.todos, self, todo),
+            Event::TodoUpdated(todo) => replace(
+                |state| &mut state.todos,
+                self,
+                todo,
+                |existing| existing.id == todo.id,
+            ),
+            Event::TodoDeleted { id } => {
+                remove(|state| &mut state.todos, self, |todo| todo.id == *id)
+            }
+            Event::RevisionRecorded(revision) => push(|state| &mut state.revisions, self, revision),
+            Event::SmartListCreated(list) => push(|state| &mut state.smart_lists, self, list),
+            Event::SmartListUpdated(list) => replace(
+                |state| &mut state.smart_lists,
+                self,
+                list,
+                |existing| existing.id == list.id,
+            ),
+            Event::SmartListDeleted { id } => {
+                remove(|state| &mut state.smart_lists, self, |list| list.id == *id)
+            }
+            Event::ListCreated(list) => push(|state| &mut state.lists, self, list),
+            Event::ListUpdated(list) => replace(
+                |state| &mut state.lists,
+                self,
+                list,
+                |existing| existing.id == list.id,
+            ),
+            Event::ListDeleted { id } => {
+                remove(|state| &mut state.lists, self, |list| list.id == *id)
+            }
+            Event::AttachmentCreated(attachment) => {
+                push(|state| &mut state.attachments, self, attachment)
+            }
+            Event::CommentCreated(comment) => push(|state| &mut state.comments, self, comment),
+            Event::CommentUpdated(comment) => replace(
+                |state| &mut state.comments,
+                self,
+                comment,
+                |existing| existing.id == comment.id,
+            ),
+            Event::CommentDeleted { id } => remove(
+                |state| &mut state.comments,
+                self,
+                |comment| comment.id == *id,
+            ),
+            Event::UserCreated(user) => push(|state| &mut state.users, self, user),
+            Event::UserUpdated(user) => replace(
+                |state| &mut state.users,
+                self,
+                user,
+                |existing| existing.id == user.id,
+            ),
+            Event::SessionCreated(session) => push(|state| &mut state.sessions, self, session),
+            Event::SessionDeleted { id } => remove(
+                |state| &mut state.sessions,
+                self,
+                |session| session.id == *id,
+            ),
+            Event::ApiTokenCreated(token) => push(|state| &mut state.api_tokens, self, token),
+            Event::ApiTokenUpdated(token) => replace(
+                |state| &mut state.api_tokens,
+                self,
+                token,
+                |existing| existing.id == token.id,
+            ),
+            Event::ApiTokenDeleted { id } => {
+                remove(|state| &mut state.api_tokens, self, |token| token.id == *id)
+            }
+            Event::MemberAdded(member) => push(|state| &mut state.members, self, member),
+            Event::MemberUpdated(member) => replace(
+                |state| &mut state.members,
+                self,
+                member,
+                |existing| existing.list_id == member.list_id && existing.user_id == member.user_id,
+            ),
+            Event::MemberRemoved { list_id, user_id } => remove(
+                |state| &mut state.members,
+                self,
+                |member| member.list_id == *list_id && member.user_id == *user_id,
+            ),
+            Event::InvitationCreated(invitation) => {
+                push(|state| &mut state.invitations, self, invitation)
+            }
+            Event::InvitationDeleted { id } => remove(
+                |state| &mut state.invitations,
+                self,
+                |invitation| invitation.id == *id,
+            ),
+            Event::AccessRecorded(change) => push(|state| &mut state.access_log, self, change),
+            Event::ShareLinkCreated(link) => push(|state| &mut state.share_links, self, link),
+            Event::ShareLinkDeleted { id } => {
+                remove(|state| &mut state.share_links, self, |link| link.id == *id)
+            }
+            Event::TwoFactorCreated(factor) => push(|state| &mut state.two_factors, self, factor),
+            Event::TwoFactorUpdated(factor) => replace(
+                |state| &mut state.two_factors,
+                self,
+                factor,
+                |existing| existing.user_id == factor.user_id,
+            ),
+            Event::TwoFactorDeleted { user_id } => remove(
+                |state| &mut state.two_factors,
+                self,
+                |factor| factor.user_id == *user_id,
+            ),
+            Event::AttachmentDeleted { id } => remove(
+                |state| &mut state.attachments,
+                self,
+                |attachment| attachment.id == *id,
+            ),
+        }
+    }
+
+    fn contains(&self, id: Uuid) -> bool {
+        self.todos.iter().any(|todo| todo.id == id)
+    }
+
+    fn has_smart_list(&self, id: Uuid) -> bool {
+        self.smart_lists.iter().any(|list| list.id == id)
+    }
+
+    fn has_list(&self, id: Uuid) -> bool {
+        self.lists.iter().any(|list| list.id == id)
+    }
+}
+
+// Takes a single applied event back again
+type Undo = Box<dyn FnOnce(&mut State)>;
+
+// One of the collections of a `State`
+type Field<T> = fn(&mut State) -> &mut Vec<T>;
+
+// Takes an applied batch back again, last event first
+pub struct Rollback(Vec<Undo>);
+
+impl Rollback {
+    pub fn undo(self, state: &mut State) {
+        self.0.into_iter().rev().for_each(|undo| undo(state));
+    }
+}
+
+// Add `item` to the end of a collection
+fn push<T: Clone + 'static>(field: Field<T>, state: &mut State, item: &T) -> Undo {
+    field(state).push(item.clone());
+    Box::new(move |state| {
+        field(state).pop();
+    })
+}
+
+// Put `item` in place of the entry it is a new version of, if that is still there
+fn replace<T: Clone + 'static>(
+    field: Field<T>,
+    state: &mut State,
+    item: &T,
+    matches: impl Fn(&T) -> bool,
+) -> Undo {
+    match field(state).iter().position(matches) {
+        Some(index) => {
+            let old = std::mem::replace(&mut field(state)[index], item.clone());
+            Box::new(move |state| field(state)[index] = old)
+        }
+        None => Box::new(|_| {}),
+    }
+}
+
+// Take every matching entry out of a collection, keeping where each was to put it back there
+fn remove<T: 'static>(field: Field<T>, state: &mut State, matches: impl Fn(&T) -> bool) -> Undo {
+    let items = field(state);
+    let mut removed = Vec::new();
+    let mut index = 0;
+    while index < items.len() {
+        if matches(&items[index]) {
+            removed.push((index + removed.len(), items.remove(index)));
+        } else {
+            index += 1;
+        }
+    }
+    Box::new(move |state| {
+        let items = field(state);
+        for (index, item) in removed {
+            items.insert(index, item);
+        }
+    })
+}
+
+// An engine serving every read from a `State` it keeps in memory. Reads and the checks of a commit
+// are shared through the `TodoStore` impl below; an engine only says where its state lives and how
+// a batch is made to last.
+pub trait StateStore: Send + Sync {
+    fn state(&self) -> &RwLock<State>;
+
+    // Make a batch last, given the state it leads to; the batch is taken back if this fails
+    fn save(&self, updated: &State, events: &[Event]) -> StoreResult<()>;
+}
+
+impl<T: StateStore> TodoStore for T {
+    fn list(&self, query: &TodoQuery) -> StoreResult<Vec<TodoItem>> {
+        Ok(self.state().read().unwrap().list(query))
+    }
+
+    fn get(&self, id: Uuid) -> StoreResult<Option<TodoItem>> {
+        Ok(self.state().read().unwrap().get(id))
+    }
+
+    fn revisions(&self, todo_id: Uuid) -> StoreResult<Vec<Revision>> {
+        Ok(self.state().read().unwrap().revisions(todo_id))
+    }
+
+    fn smart_lists(&self) -> StoreResult<Vec<SmartList>> {
+        Ok(self.state().read().unwrap().smart_lists.clone())
+    }
+
+    fn smart_list(&self, id: Uuid) -> StoreResult<Option<SmartList>> {
+        Ok(self.state().read().unwrap().smart_list(id))
+    }
+
+    fn todo_lists(&self) -> StoreResult<Vec<TodoList>> {
+        Ok(self.state().read().unwrap().todo_lists())
+    }
+
+    fn todo_list(&self, id: Uuid) -> StoreResult<Option<TodoList>> {
+        Ok(self.state().read().unwrap().todo_list(id))
+    }
+
+    fn attachments(&self, todo_id: Option<Uuid>) -> StoreResult<Vec<Attachment>> {
+        Ok(self.state().read().unwrap().attachments(todo_id))
+    }
+
+    fn attachment(&self, id: Uuid) -> StoreResult<Option<Attachment>> {
+        Ok(self.state().read().unwrap().attachment(id))
+    }
+
+    fn comments(&self, todo_id: Uuid) -> StoreResult<Vec<Comment>> {
+        Ok(self.state().read().unwrap().comments(todo_id))
+    }
+
+    fn comment(&self, id: Uuid) -> StoreResult<Option<Comment>> {
+        Ok(self.state().read().unwrap().comment(id))
+    }
+
+    fn comment_counts(&self, todo_ids: &[Uuid]) -> StoreResult<HashMap<Uuid, usize>> {
+        Ok(self.state().read().unwrap().comment_counts(todo_ids))
+    }
+
+    fn user(&self, id: Uuid) -> StoreResult<Option<User>> {
+        Ok(self.state().read().unwrap().user(id))
+    }
+
+    fn user_by_name(&self, username: &str) -> StoreResult<Option<User>> {
+        Ok(self.state().read().unwrap().user_by_name(username))
+    }
+
+    fn session(&self, id: &str) -> StoreResult<Option<Session>> {
+        Ok(self.state().read().unwrap().session(id))
+    }
+
+    fn sessions(&self, user_id: Uuid) -> StoreResult<Vec<Session>> {
+        Ok(self.state().read().unwrap().sessions(user_id))
+    }
+
+    fn api_token(&self, id: Uuid) -> StoreResult<Option<ApiToken>> {
+        Ok(self.state().read().unwrap().api_token(id))
+    }
+
+    fn api_token_by_hash(&self, hash: &str) -> StoreResult<Option<ApiToken>> {
+        Ok(self.state().read().unwrap().api_token_by_hash(hash))
+    }
+
+    fn api_tokens(&self, user_id: Uuid) -> StoreResult<Vec<ApiToken>> {
+        Ok(self.state().read().unwrap().api_tokens(user_id))
+    }
+
+    fn members(&self, list_id: Uuid) -> StoreResult<Vec<ListMember>> {
+        Ok(self.state().read().unwrap().members(list_id))
+    }
+
+    fn memberships(&self, user_id: Uuid) -> StoreResult<Vec<ListMember>> {
+        Ok(self.state().read().unwrap().memberships(user_id))
+    }
+
+    fn invitation(&self, id: Uuid) -> StoreResult<Option<Invitation>> {
+        Ok(self.state().read().unwrap().invitation(id))
+    }
+
+    fn list_invitations(&self, list_id: Uuid) -> StoreResult<Vec<Invitation>> {
+        Ok(self.state().read().unwrap().list_invitations(list_id))
+    }
+
+    fn user_invitations(&self, user_id: Uuid) -> StoreResult<Vec<Invitation>> {
+        Ok(self.state().read().unwrap().user_invitations(user_id))
+    }
+
+    fn access_log(&self, list_id: Uuid) -> StoreResult<Vec<AccessChange>> {
+        Ok(self.state().read().unwrap().access_log(list_id))
+    }
+
+    fn share_link(&self, id: Uuid) -> StoreResult<Option<ShareLink>> {
+        Ok(self.state().read().unwrap().share_link(id))
+    }
+
+    fn share_links(&self, owner_id: Uuid) -> StoreResult<Vec<ShareLink>> {
+        Ok(self.state().read().unwrap().share_links(owner_id))
+    }
+
+    fn list_share_links(&self, list_id: Uuid) -> StoreResult<Vec<ShareLink>> {
+        Ok(self.state().read().unwrap().list_share_links(list_id))
+    }
+
+    fn two_factor(&self, user_id: Uuid) -> StoreResult<Option<TwoFactor>> {
+        Ok(self.state().read().unwrap().two_factor(user_id))
+    }
+
+    // Work the batch into the state in place and take it back again if the engine fails to save it
+    fn commit(&self, events: &[Event]) -> StoreResult<()> {
+        let mut state = self.state().write().unwrap();
+        let rollback = state.apply_batch(events)?;
+        if let Err(err) = self.save(&state, events) {
+            rollback.undo(&mut state);
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+// Keeps everything in a lock-protected `State`
+#[derive(Default)]
+pub struct MemoryStore {
+    state: RwLock<State>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+}
+
+impl StateStore for MemoryStore {
+    fn state(&self) -> &RwLock<State> {
+        &self.state
+    }
+
+    // Nothing outlives the process, so there is nothing to write
+    fn save(&self, _: &State, _: &[Event]) -> StoreResult<()> {
+        Ok(())
+    }
+}
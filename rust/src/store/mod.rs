@@ -0,0 +1,403 @@
+// Storage engines for to-do items, all reachable through the `TodoStore` trait
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use uuid::Uuid;
+
+use crate::models::{
+    AccessChange, ApiToken, Attachment, Comment, Invitation, ListMember, Revision, Session,
+    ShareLink, SmartList, TodoItem, TodoList, TwoFactor, User,
+};
+
+mod file;
+mod journal;
+mod memory;
+mod query;
+mod sqlite;
+
+pub use file::FileStore;
+pub use journal::JournalStore;
+pub use memory::MemoryStore;
+pub use query::{Cursor, SortField, SortKey, TimeRange, TodoQuery, Visibility};
+pub use sqlite::SqliteStore;
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+// Operations every storage engine provides; handlers only ever talk to this trait
+pub trait TodoStore: Send + Sync {
+    // Fetch the to-do items matching `query`, in the order it asks for
+    fn list(&self, query: &TodoQuery) -> StoreResult<Vec<TodoItem>>;
+
+    // Fetch a single to-do item by id
+    fn get(&self, id: Uuid) -> StoreResult<Option<TodoItem>>;
+
+    // Fetch the recorded revisions of a to-do item, oldest first
+    fn revisions(&self, todo_id: Uuid) -> StoreResult<Vec<Revision>>;
+
+    // Fetch every smart list, oldest first
+    fn smart_lists(&self) -> StoreResult<Vec<SmartList>>;
+
+    // Fetch a single smart list by id
+    fn smart_list(&self, id: Uuid) -> StoreResult<Option<SmartList>>;
+
+    // Fetch every list, ordered by position and then by creation time
+    fn todo_lists(&self) -> StoreResult<Vec<TodoList>>;
+
+    // Fetch a single list by id
+    fn todo_list(&self, id: Uuid) -> StoreResult<Option<TodoList>>;
+
+    // Fetch the attachments of an item, or of every item when none is given, oldest first
+    fn attachments(&self, todo_id: Option<Uuid>) -> StoreResult<Vec<Attachment>>;
+
+    // Fetch a single attachment by id
+    fn attachment(&self, id: Uuid) -> StoreResult<Option<Attachment>>;
+
+    // Fetch the comments on an item, oldest first
+    fn comments(&self, todo_id: Uuid) -> StoreResult<Vec<Comment>>;
+
+    // Fetch a single comment by id
+    fn comment(&self, id: Uuid) -> StoreResult<Option<Comment>>;
+
+    // Count the comments on each of the given items; items without comments are left out
+    fn comment_counts(&self, todo_ids: &[Uuid]) -> StoreResult<HashMap<Uuid, usize>>;
+
+    // Fetch an account by id
+    fn user(&self, id: Uuid) -> StoreResult<Option<User>>;
+
+    // Fetch an account by its (normalized) username
+    fn user_by_name(&self, username: &str) -> StoreResult<Option<User>>;
+
+    // Fetch a session by the hash of its token
+    fn session(&self, id: &str) -> StoreResult<Option<Session>>;
+
+    // Fetch every session of an account, expired ones included
+    fn sessions(&self, user_id: Uuid) -> StoreResult<Vec<Session>>;
+
+    // Fetch an API token by id
+    fn api_token(&self, id: Uuid) -> StoreResult<Option<ApiToken>>;
+
+    // Fetch an API token by the hash of its secret
+    fn api_token_by_hash(&self, hash: &str) -> StoreResult<Option<ApiToken>>;
+
+    // Fetch every API token of an account, oldest first
+    fn api_tokens(&self, user_id: Uuid) -> StoreResult<Vec<ApiToken>>;
+
+    // Fetch everyone a list is shared with, in the order they joined
+    fn members(&self, list_id: Uuid) -> StoreResult<Vec<ListMember>>;
+
+    // Fetch every list shared with an account, in the order it joined them
+    fn memberships(&self, user_id: Uuid) -> StoreResult<Vec<ListMember>>;
+
+    // Fetch a single invitation by id
+    fn invitation(&self, id: Uuid) -> StoreResult<Option<Invitation>>;
+
+    // Fetch the open invitations to a list, oldest first
+    fn list_invitations(&self, list_id: Uuid) -> StoreResult<Vec<Invitation>>;
+
+    // Fetch the open invitations sent to an account, oldest first
+    fn user_invitations(&self, user_id: Uuid) -> StoreResult<Vec<Invitation>>;
+
+    // Fetch the audit log of a list, oldest entry first
+    fn access_log(&self, list_id: Uuid) -> StoreResult<Vec<AccessChange>>;
+
+    // Fetch a single share link by id
+    fn share_link(&self, id: Uuid) -> StoreResult<Option<ShareLink>>;
+
+    // Fetch every share link an account made, expired ones included, oldest first
+    fn share_links(&self, owner_id: Uuid) -> StoreResult<Vec<ShareLink>>;
+
+    // Fetch every share link to a list, whoever made it, oldest first
+    fn list_share_links(&self, list_id: Uuid) -> StoreResult<Vec<ShareLink>>;
+
+    // Fetch the second sign-in step of an account, enrolled or pending
+    fn two_factor(&self, user_id: Uuid) -> StoreResult<Option<TwoFactor>>;
+
+    // Apply a batch of changes atomically: either every event is stored or none is
+    fn commit(&self, events: &[Event]) -> StoreResult<()>;
+}
+
+// A single change to the stored data; batches of these are what `TodoStore::commit` writes
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    TodoCreated(TodoItem),          // A new item was added
+    TodoUpdated(TodoItem),          // An existing item was replaced by this version
+    TodoDeleted { id: Uuid },       // An item was removed
+    RevisionRecorded(Revision),     // A revision was appended to an item's history
+    SmartListCreated(SmartList),    // A new smart list was saved
+    SmartListUpdated(SmartList),    // An existing smart list was replaced by this version
+    SmartListDeleted { id: Uuid },  // A smart list was removed
+    ListCreated(TodoList),          // A new list was added
+    ListUpdated(TodoList),          // An existing list was replaced by this version
+    ListDeleted { id: Uuid },       // A list was removed
+    AttachmentCreated(Attachment),  // A file was attached to an item
+    AttachmentDeleted { id: Uuid }, // An attachment was removed
+    CommentCreated(Comment),        // A comment was posted on an item
+    CommentUpdated(Comment),        // An existing comment was replaced by this version
+    CommentDeleted { id: Uuid },    // A comment was removed
+    UserCreated(User),              // An account was registered
+    UserUpdated(User),              // An existing account was replaced by this version
+    SessionCreated(Session),        // A session was started at login
+    SessionDeleted { id: String },  // A session was ended or pruned after expiring
+    ApiTokenCreated(ApiToken),      // An API token was created
+    ApiTokenUpdated(ApiToken),      // An existing API token was replaced by this version
+    ApiTokenDeleted { id: Uuid },   // An API token was revoked
+    MemberAdded(ListMember),        // A list was shared with an account
+    MemberUpdated(ListMember),      // A member was given another role
+    // A member lost access to a list
+    MemberRemoved { list_id: Uuid, user_id: Uuid },
+    InvitationCreated(Invitation),  // An invitation to a list was sent
+    InvitationDeleted { id: Uuid }, // An invitation was accepted, declined or taken back
+    AccessRecorded(AccessChange),   // An entry was appended to the audit log of a list
+    ShareLinkCreated(ShareLink),    // A share link was made
+    ShareLinkDeleted { id: Uuid },  // A share link was revoked
+    TwoFactorCreated(TwoFactor),    // An account started enrolling a second sign-in step
+    TwoFactorUpdated(TwoFactor),    // A second sign-in step was replaced by this version
+    // An account turned its second sign-in step off
+    TwoFactorDeleted { user_id: Uuid },
+}
+
+// Errors raised by any of the storage engines
+#[derive(Debug)]
+pub enum StoreError {
+    Io(std::io::Error),      // Reading or writing a data file failed
+    Json(serde_json::Error), // A data file could not be (de)serialized
+    Pool(r2d2::Error),       // No database connection could be checked out of the pool
+    Sqlite(rusqlite::Error), // A database query failed
+    Conflict(String),        // The write clashes with existing data (e.g. a duplicate id)
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io(err) => write!(f, "storage i/o error: {}", err),
+            StoreError::Json(err) => write!(f, "storage file is corrupt: {}", err),
+            StoreError::Pool(err) => write!(f, "database connection unavailable: {}", err),
+            StoreError::Sqlite(err) => write!(f, "database error: {}", err),
+            StoreError::Conflict(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<std::io::Error> for StoreError {
+    fn from(err: std::io::Error) -> Self {
+        StoreError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(err: serde_json::Error) -> Self {
+        StoreError::Json(err)
+    }
+}
+
+impl From<r2d2::Error> for StoreError {
+    fn from(err: r2d2::Error) -> Self {
+        StoreError::Pool(err)
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(err: rusqlite::Error) -> Self {
+        StoreError::Sqlite(err)
+    }
+}
+
+// Storage failures surface to clients as a plain 500, except for conflicting writes
+impl actix_web::ResponseError for StoreError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            StoreError::Conflict(_) => actix_web::http::StatusCode::CONFLICT,
+            _ => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+// Build the engine named by TODO_STORAGE ("memory" when unset, "file", "journal" or "sqlite"),
+// stored at TODO_STORAGE_PATH; the journal compacts itself into a snapshot every
+// TODO_SNAPSHOT_EVERY events. Any other name is refused rather than quietly keeping data in memory.
+pub fn open_from_env() -> Result<Box<dyn TodoStore>, String> {
+    let path = env::var("TODO_STORAGE_PATH").ok();
+    let engine = match env::var("TODO_STORAGE") {
+        Ok(engine) => engine,
+        Err(env::VarError::NotPresent) => "memory".to_string(),
+        Err(env::VarError::NotUnicode(_)) => String::new(),
+    };
+    let store: Box<dyn TodoStore> = match engine.as_str() {
+        "memory" => Box::new(MemoryStore::new()),
+        "journal" => {
+            let snapshot_every = env::var("TODO_SNAPSHOT_EVERY")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(1000);
+            Box::new(
+                JournalStore::open(path.as_deref().unwrap_or("todo-data"), snapshot_every)
+                    .map_err(|err| err.to_string())?,
+            )
+        }
+        "file" => Box::new(
+            FileStore::open(path.as_deref().unwrap_or("todos.json"))
+                .map_err(|err| err.to_string())?,
+        ),
+        "sqlite" => Box::new(
+            SqliteStore::open(path.as_deref().unwrap_or("todos.db"))
+                .map_err(|err| err.to_string())?,
+        ),
+        _ => {
+            return Err(format!(
+                "unknown storage engine `{}` in TODO_STORAGE; use memory, file, journal or sqlite",
+                engine
+            ))
+        }
+    };
+    Ok(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::{State, StateStore};
+    use crate::testing::{list, todo, user, TempDir};
+    use std::sync::RwLock;
+
+    // One store of every engine, each kept in `dir`
+    fn engines(dir: &TempDir) -> Vec<(&'static str, Box<dyn TodoStore>)> {
+        let file = FileStore::open(dir.path("todos.json")).unwrap();
+        let journal = JournalStore::open(dir.path("journal"), 1000).unwrap();
+        let sqlite = SqliteStore::open(&dir.path("todos.db")).unwrap();
+        vec![
+            ("memory", Box::new(MemoryStore::new())),
+            ("file", Box::new(file)),
+            ("journal", Box::new(journal)),
+            ("sqlite", Box::new(sqlite)),
+        ]
+    }
+
+    #[test]
+    fn batches_are_checked_in_order_by_every_engine() {
+        let dir = TempDir::new();
+        for (engine, store) in engines(&dir) {
+            // Later events may rely on earlier ones of the same batch
+            let owner = user("owner");
+            let list = list(&owner);
+            let mut item = todo(Some(list.id));
+            let created = Event::TodoCreated(item.clone());
+            item.completed = true;
+            store
+                .commit(&[
+                    Event::UserCreated(owner),
+                    Event::ListCreated(list.clone()),
+                    created,
+                    Event::TodoUpdated(item.clone()),
+                ])
+                .unwrap_or_else(|err| panic!("{}: {}", engine, err));
+            let stored = store.get(item.id).unwrap();
+            assert!(stored.is_some_and(|todo| todo.completed), "{}", engine);
+            assert!(store.todo_list(list.id).unwrap().is_some(), "{}", engine);
+
+            // ... and an event clashing with an earlier one of the batch fails all of it
+            let other = todo(None);
+            let result = store.commit(&[
+                Event::TodoCreated(other.clone()),
+                Event::TodoDeleted { id: item.id },
+                Event::TodoUpdated(item.clone()),
+            ]);
+            assert!(matches!(result, Err(StoreError::Conflict(_))), "{}", engine);
+            assert!(store.get(other.id).unwrap().is_none(), "{}", engine);
+            assert!(store.get(item.id).unwrap().is_some(), "{}", engine);
+        }
+    }
+
+    #[test]
+    fn subtasks_are_found_by_parent_on_every_engine() {
+        let dir = TempDir::new();
+        for (engine, store) in engines(&dir) {
+            let parent = todo(None);
+            let child = TodoItem {
+                parent_id: Some(parent.id),
+                ..todo(None)
+            };
+            let grandchild = TodoItem {
+                parent_id: Some(child.id),
+                ..todo(None)
+            };
+            let events: Vec<_> = [&parent, &child, &grandchild]
+                .iter()
+                .map(|item| Event::TodoCreated((*item).clone()))
+                .collect();
+            store.commit(&events).unwrap();
+            let query = TodoQuery {
+                parent: Some(parent.id),
+                ..TodoQuery::default()
+            };
+            let found = store.list(&query).unwrap();
+            assert_eq!(found.len(), 1, "{}", engine);
+            assert_eq!(found[0].id, child.id, "{}", engine);
+        }
+    }
+
+    // Keeps its state in memory like `MemoryStore` but fails to save every batch
+    #[derive(Default)]
+    struct FailingStore {
+        state: RwLock<State>,
+    }
+
+    impl StateStore for FailingStore {
+        fn state(&self) -> &RwLock<State> {
+            &self.state
+        }
+
+        fn save(&self, _: &State, _: &[Event]) -> StoreResult<()> {
+            Err(StoreError::Io(std::io::Error::other("disk full")))
+        }
+    }
+
+    #[test]
+    fn a_batch_that_fails_to_save_is_taken_back() {
+        let store = FailingStore::default();
+        let items = [todo(None), todo(None), todo(None)];
+        {
+            let mut state = store.state.write().unwrap();
+            items
+                .iter()
+                .for_each(|item| state.apply(&Event::TodoCreated(item.clone())));
+        }
+        let mut renamed = items[2].clone();
+        renamed.title = "eggs".to_string();
+        let result = store.commit(&[
+            Event::TodoDeleted { id: items[0].id },
+            Event::TodoUpdated(renamed),
+            Event::TodoCreated(todo(None)),
+        ]);
+        assert!(matches!(result, Err(StoreError::Io(_))));
+        // Everything is back where it was, in the order it was in
+        let titles = |todos: &[TodoItem]| -> Vec<(Uuid, String)> {
+            todos
+                .iter()
+                .map(|todo| (todo.id, todo.title.clone()))
+                .collect()
+        };
+        assert_eq!(titles(&store.state.read().unwrap().todos), titles(&items));
+    }
+
+    #[test]
+    fn title_search_folds_case_the_same_on_every_engine() {
+        let dir = TempDir::new();
+        for (engine, store) in engines(&dir) {
+            let mut item = todo(None);
+            item.title = "Über die Straße".to_string();
+            store.commit(&[Event::TodoCreated(item.clone())]).unwrap();
+            for needle in ["über", "ÜBER DIE", "straße"] {
+                let query = TodoQuery {
+                    title_contains: Some(needle.to_string()),
+                    ..TodoQuery::default()
+                };
+                let found = store.list(&query).unwrap();
+                assert_eq!(found.len(), 1, "{}: {}", engine, needle);
+            }
+        }
+    }
+}
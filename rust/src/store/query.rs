@@ -0,0 +1,220 @@
+// Filtering, sorting and keyset pagination options for `TodoStore::list`
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Deserialize;
+use std::cmp::Ordering;
+use uuid::Uuid;
+
+use crate::filter::Filter;
+use crate::models::{Priority, TodoItem};
+
+// Field the results are ordered by; ties are always broken by id so the order is total
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    #[default]
+    Position, // Manual order (the default), see `crate::rank`
+    CreatedAt, // Creation time, i.e. insertion order
+    UpdatedAt, // Last modification time, falling back to the creation time for untouched items
+    Title,     // Title, ignoring ASCII case
+}
+
+// Value of the sort field for one item
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum SortKey {
+    Time(DateTime<Utc>),
+    Text(String),
+}
+
+impl SortField {
+    pub fn key(self, todo: &TodoItem) -> SortKey {
+        match self {
+            SortField::Position => SortKey::Text(todo.rank.clone()),
+            SortField::CreatedAt => SortKey::Time(todo.created_at),
+            SortField::UpdatedAt => SortKey::Time(todo.updated_at.unwrap_or(todo.created_at)),
+            SortField::Title => SortKey::Text(todo.title.to_ascii_lowercase()),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SortField::Position => "position",
+            SortField::CreatedAt => "created_at",
+            SortField::UpdatedAt => "updated_at",
+            SortField::Title => "title",
+        }
+    }
+}
+
+// Position right after the last item of a page, handed to clients as an opaque string
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cursor {
+    pub field: SortField, // Sort field the cursor was produced for
+    pub key: SortKey,     // Sort key of the last item returned
+    pub id: Uuid,         // Id of the last item returned
+}
+
+impl Cursor {
+    pub fn after(field: SortField, todo: &TodoItem) -> Self {
+        Cursor {
+            field,
+            key: field.key(todo),
+            id: todo.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let key = match &self.key {
+            SortKey::Time(time) => time.to_rfc3339_opts(SecondsFormat::Nanos, true),
+            SortKey::Text(text) => text.clone(),
+        };
+        URL_SAFE_NO_PAD.encode(format!("{}|{}|{}", self.field.name(), self.id, key))
+    }
+
+    // Decode a cursor, which must have been produced for the same sort field
+    pub fn decode(field: SortField, encoded: &str) -> Option<Self> {
+        let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(encoded).ok()?).ok()?;
+        let mut parts = decoded.splitn(3, '|');
+        if parts.next()? != field.name() {
+            return None;
+        }
+        let id = Uuid::parse_str(parts.next()?).ok()?;
+        let key = parts.next()?;
+        let key = match field {
+            SortField::CreatedAt | SortField::UpdatedAt => {
+                SortKey::Time(DateTime::parse_from_rfc3339(key).ok()?.with_timezone(&Utc))
+            }
+            SortField::Position | SortField::Title => SortKey::Text(key.to_string()),
+        };
+        Some(Cursor { field, key, id })
+    }
+}
+
+// Half-open time interval [from, to); either end may be left open
+#[derive(Default, Clone, Copy)]
+pub struct TimeRange {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl TimeRange {
+    pub fn contains(&self, time: DateTime<Utc>) -> bool {
+        self.from.is_none_or(|from| time >= from) && self.to.is_none_or(|to| time < to)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.from.is_none() && self.to.is_none()
+    }
+}
+
+// Items an account can see: its own, and every item in the lists shared with it
+#[derive(Default, Clone)]
+pub struct Visibility {
+    pub user_id: Uuid,    // Account whose own items are visible
+    pub lists: Vec<Uuid>, // Lists of other accounts whose items are visible too
+}
+
+impl Visibility {
+    pub fn covers(&self, todo: &TodoItem) -> bool {
+        self.reaches(todo.owner_id, todo.list_id)
+    }
+
+    // Whether an item with this owner and list is visible
+    pub fn reaches(&self, owner_id: Option<Uuid>, list_id: Option<Uuid>) -> bool {
+        owner_id == Some(self.user_id)
+            || list_id.is_some_and(|list_id| self.lists.contains(&list_id))
+    }
+}
+
+// Options narrowing down which to-do items `TodoStore::list` returns, and in which order
+#[derive(Default, Clone)]
+pub struct TodoQuery {
+    pub in_trash: bool,                 // List the trash instead of the live items
+    pub completed: Option<bool>,        // Only items with this completion status
+    pub title_contains: Option<String>, // Only items whose title contains this text, ignoring case
+    pub created: TimeRange,             // Only items created within this range
+    pub updated: TimeRange,             // Only items last updated within this range
+    pub tags: Vec<String>,              // Only items carrying every one of these (normalized) tags
+    pub priority: Option<Priority>,     // Only items with this priority
+    pub filter: Option<Filter>,         // Only items matching this filter expression
+    pub list: Option<Option<Uuid>>,     // Only items in this list (Some(None): in no list)
+    pub parent: Option<Uuid>,           // Only direct subtasks of this item
+    pub visible: Option<Visibility>,    // Only items an account can see
+    pub sort: SortField,                // Field to order by
+    pub descending: bool,               // Reverse the order
+    pub after: Option<Cursor>,          // Only items that come after this position
+    pub limit: Option<usize>,           // At most this many items
+}
+
+impl TodoQuery {
+    // Whether `todo` passes the filters of this query (ordering, cursor and limit are handled by `apply`)
+    pub fn matches(&self, todo: &TodoItem) -> bool {
+        todo.deleted_at.is_some() == self.in_trash
+            && self
+                .completed
+                .is_none_or(|completed| todo.completed == completed)
+            && self
+                .title_contains
+                .as_ref()
+                .is_none_or(|needle| todo.title.to_lowercase().contains(&needle.to_lowercase()))
+            && self.created.contains(todo.created_at)
+            && (self.updated.is_open()
+                || todo.updated_at.is_some_and(|at| self.updated.contains(at)))
+            && self.tags.iter().all(|tag| todo.tags.contains(tag))
+            && self
+                .priority
+                .is_none_or(|priority| todo.priority == priority)
+            && self
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(todo))
+            && self.list.is_none_or(|list_id| todo.list_id == list_id)
+            && self
+                .parent
+                .is_none_or(|parent_id| todo.parent_id == Some(parent_id))
+            && self
+                .visible
+                .as_ref()
+                .is_none_or(|visible| visible.covers(todo))
+    }
+
+    // Compare two items in the order requested by this query
+    pub fn compare(&self, a: &TodoItem, b: &TodoItem) -> Ordering {
+        let ordering = (self.sort.key(a), a.id).cmp(&(self.sort.key(b), b.id));
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    // Whether `todo` sorts strictly after the cursor of this query
+    fn is_after_cursor(&self, todo: &TodoItem) -> bool {
+        self.after.as_ref().is_none_or(|cursor| {
+            let ordering = (self.sort.key(todo), todo.id).cmp(&(cursor.key.clone(), cursor.id));
+            if self.descending {
+                ordering == Ordering::Less
+            } else {
+                ordering == Ordering::Greater
+            }
+        })
+    }
+
+    // Filter, sort and page an in-memory sequence of items according to this query
+    pub fn apply<'a, I>(&self, todos: I) -> Vec<TodoItem>
+    where
+        I: IntoIterator<Item = &'a TodoItem>,
+    {
+        let mut matching: Vec<&TodoItem> = todos
+            .into_iter()
+            .filter(|todo| self.matches(todo) && self.is_after_cursor(todo))
+            .collect();
+        matching.sort_by(|a, b| self.compare(a, b));
+        matching
+            .into_iter()
+            .take(self.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+}
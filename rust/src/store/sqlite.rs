@@ -0,0 +1,1620 @@
+// SQLite-backed storage for to-do items
+use chrono::{DateTime, SecondsFormat, Utc};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, params_from_iter, OptionalExtension, Row, ToSql, Transaction};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::{Event, SortField, SortKey, StoreError, StoreResult, TodoQuery, TodoStore};
+use crate::models::{
+    AccessAction, AccessChange, ApiToken, Attachment, Comment, Invitation, ListMember, Priority,
+    RepeatFrom, Revision, Role, Schedule, Session, ShareLink, SmartList, TodoItem, TodoList,
+    TwoFactor, User,
+};
+use crate::recurrence::Recurrence;
+
+// Schema migrations, applied in order; the index of the last applied one is kept in `PRAGMA user_version`
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE todos (
+        id TEXT PRIMARY KEY NOT NULL,
+        title TEXT NOT NULL,
+        completed INTEGER NOT NULL DEFAULT 0,
+        created_at TEXT NOT NULL,
+        updated_at TEXT
+    );",
+    // Revisions outlive the item they describe, so there is deliberately no foreign key to `todos`
+    "CREATE TABLE revisions (
+        id TEXT PRIMARY KEY NOT NULL,
+        todo_id TEXT NOT NULL,
+        number INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE UNIQUE INDEX revisions_todo_number ON revisions (todo_id, number);",
+    "ALTER TABLE todos ADD COLUMN deleted_at TEXT;
+    CREATE INDEX todos_deleted_at ON todos (deleted_at);",
+    // Keyset pagination walks these in order, one index per sort field
+    "CREATE INDEX todos_created_at ON todos (created_at, id);
+    CREATE INDEX todos_updated_at ON todos (COALESCE(updated_at, created_at), id);
+    CREATE INDEX todos_title ON todos (lower(title), id);",
+    "CREATE TABLE smart_lists (
+        id TEXT PRIMARY KEY NOT NULL,
+        name TEXT NOT NULL,
+        filter TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT
+    );",
+    // Due and start dates keep the text form of `Schedule`: a plain date or an RFC 3339 timestamp
+    "ALTER TABLE todos ADD COLUMN due_at TEXT;
+    ALTER TABLE todos ADD COLUMN start_at TEXT;",
+    // Tags are a JSON array of strings, searched with json_each
+    "ALTER TABLE todos ADD COLUMN priority TEXT NOT NULL DEFAULT 'none';
+    ALTER TABLE todos ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
+    CREATE INDEX todos_priority ON todos (priority);",
+    // No foreign key: subtasks may outlive a parent purged from the trash
+    "ALTER TABLE todos ADD COLUMN parent_id TEXT;
+    CREATE INDEX todos_parent_id ON todos (parent_id);",
+    // Blocking tasks are a JSON array of ids, like tags
+    "ALTER TABLE todos ADD COLUMN blocked_by TEXT NOT NULL DEFAULT '[]';",
+    // Recurrence rules keep their RRULE text
+    "ALTER TABLE todos ADD COLUMN recurrence TEXT;
+    ALTER TABLE todos ADD COLUMN repeat_from TEXT NOT NULL DEFAULT 'due';",
+    // Reminder offsets and the ones already sent are JSON arrays of minutes
+    "ALTER TABLE todos ADD COLUMN reminders TEXT NOT NULL DEFAULT '[]';
+    ALTER TABLE todos ADD COLUMN reminded TEXT NOT NULL DEFAULT '[]';",
+    // Like parent_id, list_id has no foreign key; deleting a list moves or trashes its items first
+    "CREATE TABLE lists (
+        id TEXT PRIMARY KEY NOT NULL,
+        name TEXT NOT NULL,
+        color TEXT,
+        archived INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT
+    );
+    ALTER TABLE todos ADD COLUMN list_id TEXT;
+    CREATE INDEX todos_list_id ON todos (list_id);",
+    // Ranks of the items stored before are filled in on startup, see `rank::backfill`
+    "ALTER TABLE todos ADD COLUMN rank TEXT NOT NULL DEFAULT '';
+    CREATE INDEX todos_rank ON todos (rank, id);",
+    // The checklist is a JSON array of steps
+    "ALTER TABLE todos ADD COLUMN notes TEXT NOT NULL DEFAULT '';
+    ALTER TABLE todos ADD COLUMN checklist TEXT NOT NULL DEFAULT '[]';",
+    // Blobs are shared by digest, so the digest is looked up before a blob is removed
+    "CREATE TABLE attachments (
+        id TEXT PRIMARY KEY NOT NULL,
+        todo_id TEXT NOT NULL,
+        file_name TEXT NOT NULL,
+        content_type TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        digest TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX attachments_todo_id ON attachments (todo_id);
+    CREATE INDEX attachments_digest ON attachments (digest);",
+    "CREATE TABLE comments (
+        id TEXT PRIMARY KEY NOT NULL,
+        todo_id TEXT NOT NULL,
+        author TEXT NOT NULL,
+        body TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        edited_at TEXT
+    );
+    CREATE INDEX comments_todo_id ON comments (todo_id, created_at);",
+    // Rows from before accounts have no owner until the first account registers and claims them
+    "ALTER TABLE todos ADD COLUMN owner_id TEXT;
+    CREATE INDEX todos_owner_id ON todos (owner_id);
+    ALTER TABLE lists ADD COLUMN owner_id TEXT;
+    ALTER TABLE smart_lists ADD COLUMN owner_id TEXT;
+    CREATE TABLE users (
+        id TEXT PRIMARY KEY NOT NULL,
+        username TEXT NOT NULL UNIQUE,
+        password_hash TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE TABLE sessions (
+        id TEXT PRIMARY KEY NOT NULL,
+        user_id TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        expires_at TEXT NOT NULL
+    );
+    CREATE INDEX sessions_user_id ON sessions (user_id);",
+    "CREATE TABLE api_tokens (
+        id TEXT PRIMARY KEY NOT NULL,
+        user_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        hash TEXT NOT NULL UNIQUE,
+        scopes TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        expires_at TEXT,
+        last_used_at TEXT
+    );
+    CREATE INDEX api_tokens_user_id ON api_tokens (user_id);",
+    "CREATE TABLE list_members (
+        list_id TEXT NOT NULL,
+        user_id TEXT NOT NULL,
+        role TEXT NOT NULL,
+        added_at TEXT NOT NULL,
+        PRIMARY KEY (list_id, user_id)
+    );
+    CREATE INDEX list_members_user_id ON list_members (user_id);
+    CREATE TABLE invitations (
+        id TEXT PRIMARY KEY NOT NULL,
+        list_id TEXT NOT NULL,
+        user_id TEXT NOT NULL,
+        role TEXT NOT NULL,
+        invited_by TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX invitations_list_id ON invitations (list_id);
+    CREATE INDEX invitations_user_id ON invitations (user_id);
+    CREATE TABLE access_log (
+        id TEXT PRIMARY KEY NOT NULL,
+        list_id TEXT NOT NULL,
+        actor TEXT NOT NULL,
+        action TEXT NOT NULL,
+        username TEXT NOT NULL,
+        role TEXT,
+        at TEXT NOT NULL
+    );
+    CREATE INDEX access_log_list_id ON access_log (list_id, at);",
+    "CREATE TABLE share_links (
+        id TEXT PRIMARY KEY NOT NULL,
+        owner_id TEXT NOT NULL,
+        list_id TEXT,
+        todo_id TEXT,
+        created_at TEXT NOT NULL,
+        expires_at TEXT NOT NULL
+    );
+    CREATE INDEX share_links_owner_id ON share_links (owner_id);",
+    "CREATE TABLE two_factors (
+        user_id TEXT PRIMARY KEY NOT NULL,
+        secret TEXT NOT NULL,
+        enabled INTEGER NOT NULL,
+        recovery_codes TEXT NOT NULL,
+        last_step INTEGER,
+        failures INTEGER NOT NULL,
+        locked_until TEXT,
+        created_at TEXT NOT NULL
+    );",
+    "ALTER TABLE users ADD COLUMN failed_logins INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE users ADD COLUMN locked_until TEXT;",
+    "CREATE INDEX share_links_list_id ON share_links (list_id);",
+];
+
+// Columns selected when loading a to-do item, matching the order read by `read_todo`
+const TODO_COLUMNS: &str =
+    "id, title, completed, created_at, updated_at, deleted_at, due_at, start_at, priority, tags, \
+     parent_id, blocked_by, recurrence, repeat_from, reminders, reminded, list_id, rank, notes, \
+     checklist, owner_id";
+
+// Columns selected when loading a smart list, matching the order read by `read_smart_list`
+const SMART_LIST_COLUMNS: &str = "id, name, filter, created_at, updated_at, owner_id";
+
+// Columns selected when loading a list, matching the order read by `read_list`
+const LIST_COLUMNS: &str = "id, name, color, archived, position, created_at, updated_at, owner_id";
+
+// Columns selected when loading an attachment, matching the order read by `read_attachment`
+const ATTACHMENT_COLUMNS: &str = "id, todo_id, file_name, content_type, size, digest, created_at";
+
+// Columns selected when loading a comment, matching the order read by `read_comment`
+const COMMENT_COLUMNS: &str = "id, todo_id, author, body, created_at, edited_at";
+
+// Columns selected when loading an account, matching the order read by `read_user`
+const USER_COLUMNS: &str = "id, username, password_hash, created_at, failed_logins, locked_until";
+
+// Columns selected when loading a session, matching the order read by `read_session`
+const SESSION_COLUMNS: &str = "id, user_id, created_at, expires_at";
+
+// Columns selected when loading an API token, matching the order read by `read_api_token`
+const API_TOKEN_COLUMNS: &str =
+    "id, user_id, name, hash, scopes, created_at, expires_at, last_used_at";
+
+// Columns selected when loading a list member, matching the order read by `read_member`
+const MEMBER_COLUMNS: &str = "list_id, user_id, role, added_at";
+
+// Columns selected when loading an invitation, matching the order read by `read_invitation`
+const INVITATION_COLUMNS: &str = "id, list_id, user_id, role, invited_by, created_at";
+
+// Columns selected when loading an audit log entry, matching the order read by `read_access_change`
+const ACCESS_LOG_COLUMNS: &str = "id, list_id, actor, action, username, role, at";
+
+// Columns selected when loading a share link, matching the order read by `read_share_link`
+const SHARE_LINK_COLUMNS: &str = "id, owner_id, list_id, todo_id, created_at, expires_at";
+
+// Columns selected when loading a second sign-in step, matching the order read by `read_two_factor`
+const TWO_FACTOR_COLUMNS: &str =
+    "user_id, secret, enabled, recovery_codes, last_step, failures, locked_until, created_at";
+
+// Durable store keeping to-do items in a SQLite database file
+pub struct SqliteStore {
+    pool: Pool<SqliteConnectionManager>, // Pool of connections shared by the worker threads
+}
+
+impl SqliteStore {
+    // Open (or create) the database at `path` and bring its schema up to date
+    pub fn open(path: &str) -> StoreResult<Self> {
+        let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+            // WAL lets readers proceed while a writer holds the lock; the timeout rides out short write contention
+            conn.execute_batch(
+                "PRAGMA journal_mode = WAL;
+                 PRAGMA foreign_keys = ON;
+                 PRAGMA busy_timeout = 5000;",
+            )?;
+            // SQLite's own lower() only folds ASCII; searches fold case like the other engines do
+            conn.create_scalar_function(
+                "fold_case",
+                1,
+                FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+                |ctx| Ok(ctx.get::<Option<String>>(0)?.map(|text| text.to_lowercase())),
+            )
+        });
+        let pool = Pool::new(manager)?;
+        let store = SqliteStore { pool };
+        store.migrate()?;
+        Ok(store)
+    }
+
+    // Apply every migration newer than the schema version recorded in the database
+    fn migrate(&self) -> StoreResult<()> {
+        let mut conn = self.pool.get()?;
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", index + 1)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+}
+
+impl TodoStore for SqliteStore {
+    fn list(&self, query: &TodoQuery) -> StoreResult<Vec<TodoItem>> {
+        let mut sql = format!(
+            "SELECT {} FROM todos WHERE deleted_at IS {}",
+            TODO_COLUMNS,
+            if query.in_trash { "NOT NULL" } else { "NULL" }
+        );
+        let mut args = Args::default();
+        if let Some(completed) = query.completed {
+            sql += &format!(" AND completed = {}", args.bind(completed));
+        }
+        if let Some(needle) = &query.title_contains {
+            sql += &format!(
+                " AND instr(fold_case(title), {}) > 0",
+                args.bind(needle.to_lowercase())
+            );
+        }
+        for tag in &query.tags {
+            sql += &format!(
+                " AND EXISTS (SELECT 1 FROM json_each(todos.tags) WHERE json_each.value = {})",
+                args.bind(tag.clone())
+            );
+        }
+        if let Some(priority) = query.priority {
+            sql += &format!(" AND priority = {}", args.bind(priority.name()));
+        }
+        match query.list {
+            Some(Some(list_id)) => {
+                sql += &format!(" AND list_id = {}", args.bind(list_id.to_string()))
+            }
+            Some(None) => sql += " AND list_id IS NULL",
+            None => {}
+        }
+        if let Some(parent_id) = query.parent {
+            sql += &format!(" AND parent_id = {}", args.bind(parent_id.to_string()));
+        }
+        if let Some(visible) = &query.visible {
+            sql += &format!(
+                " AND (owner_id = {} OR list_id IN (SELECT value FROM json_each({})))",
+                args.bind(visible.user_id.to_string()),
+                args.bind(serde_json::to_string(&visible.lists)?)
+            );
+        }
+        for (column, range) in [
+            ("created_at", &query.created),
+            ("updated_at", &query.updated),
+        ] {
+            if let Some(from) = &range.from {
+                sql += &format!(" AND {} >= {}", column, args.bind(encode_time(from)));
+            }
+            if let Some(to) = &range.to {
+                sql += &format!(" AND {} < {}", column, args.bind(encode_time(to)));
+            }
+        }
+
+        let key = sort_expression(query.sort);
+        let direction = if query.descending { "DESC" } else { "ASC" };
+        if let Some(cursor) = &query.after {
+            let value = match &cursor.key {
+                SortKey::Time(time) => args.bind(encode_time(time)),
+                SortKey::Text(text) => args.bind(text.clone()),
+            };
+            sql += &format!(
+                " AND ({}, id) {} ({}, {})",
+                key,
+                if query.descending { "<" } else { ">" },
+                value,
+                args.bind(cursor.id.to_string())
+            );
+        }
+        sql += &format!(" ORDER BY {} {}, id {}", key, direction, direction);
+        // Filter expressions are evaluated in Rust, so rows are then streamed until enough match
+        if let (Some(limit), None) = (query.limit, &query.filter) {
+            sql += &format!(" LIMIT {}", args.bind(limit as i64));
+        }
+
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&sql)?;
+        let mut todos = Vec::new();
+        for todo in stmt.query_map(params_from_iter(args.values.iter()), read_todo)? {
+            let todo = todo?;
+            if query
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(&todo))
+            {
+                todos.push(todo);
+                if query.limit.is_some_and(|limit| todos.len() >= limit) {
+                    break;
+                }
+            }
+        }
+        Ok(todos)
+    }
+
+    fn get(&self, id: Uuid) -> StoreResult<Option<TodoItem>> {
+        let conn = self.pool.get()?;
+        let todo = conn
+            .query_row(
+                &format!("SELECT {} FROM todos WHERE id = ?1", TODO_COLUMNS),
+                params![id.to_string()],
+                read_todo,
+            )
+            .optional()?;
+        Ok(todo)
+    }
+
+    fn revisions(&self, todo_id: Uuid) -> StoreResult<Vec<Revision>> {
+        let conn = self.pool.get()?;
+        let mut stmt =
+            conn.prepare("SELECT data FROM revisions WHERE todo_id = ?1 ORDER BY number")?;
+        let revisions = stmt
+            .query_map(params![todo_id.to_string()], |row| read_json(row, 0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(revisions)
+    }
+
+    fn smart_lists(&self) -> StoreResult<Vec<SmartList>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM smart_lists ORDER BY created_at, id",
+            SMART_LIST_COLUMNS
+        ))?;
+        let lists = stmt
+            .query_map([], read_smart_list)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(lists)
+    }
+
+    fn smart_list(&self, id: Uuid) -> StoreResult<Option<SmartList>> {
+        let conn = self.pool.get()?;
+        let list = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM smart_lists WHERE id = ?1",
+                    SMART_LIST_COLUMNS
+                ),
+                params![id.to_string()],
+                read_smart_list,
+            )
+            .optional()?;
+        Ok(list)
+    }
+
+    fn todo_lists(&self) -> StoreResult<Vec<TodoList>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM lists ORDER BY position, created_at, id",
+            LIST_COLUMNS
+        ))?;
+        let lists = stmt
+            .query_map([], read_list)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(lists)
+    }
+
+    fn todo_list(&self, id: Uuid) -> StoreResult<Option<TodoList>> {
+        let conn = self.pool.get()?;
+        let list = conn
+            .query_row(
+                &format!("SELECT {} FROM lists WHERE id = ?1", LIST_COLUMNS),
+                params![id.to_string()],
+                read_list,
+            )
+            .optional()?;
+        Ok(list)
+    }
+
+    fn attachments(&self, todo_id: Option<Uuid>) -> StoreResult<Vec<Attachment>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM attachments WHERE ?1 IS NULL OR todo_id = ?1 ORDER BY created_at, id",
+            ATTACHMENT_COLUMNS
+        ))?;
+        let attachments = stmt
+            .query_map(params![todo_id.map(|id| id.to_string())], read_attachment)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(attachments)
+    }
+
+    fn attachment(&self, id: Uuid) -> StoreResult<Option<Attachment>> {
+        let conn = self.pool.get()?;
+        let attachment = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM attachments WHERE id = ?1",
+                    ATTACHMENT_COLUMNS
+                ),
+                params![id.to_string()],
+                read_attachment,
+            )
+            .optional()?;
+        Ok(attachment)
+    }
+
+    fn comments(&self, todo_id: Uuid) -> StoreResult<Vec<Comment>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM comments WHERE todo_id = ?1 ORDER BY created_at, id",
+            COMMENT_COLUMNS
+        ))?;
+        let comments = stmt
+            .query_map(params![todo_id.to_string()], read_comment)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(comments)
+    }
+
+    fn comment(&self, id: Uuid) -> StoreResult<Option<Comment>> {
+        let conn = self.pool.get()?;
+        let comment = conn
+            .query_row(
+                &format!("SELECT {} FROM comments WHERE id = ?1", COMMENT_COLUMNS),
+                params![id.to_string()],
+                read_comment,
+            )
+            .optional()?;
+        Ok(comment)
+    }
+
+    fn comment_counts(&self, todo_ids: &[Uuid]) -> StoreResult<HashMap<Uuid, usize>> {
+        if todo_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let conn = self.pool.get()?;
+        // The ids go in as one JSON array rather than a placeholder each
+        let mut stmt = conn.prepare(
+            "SELECT todo_id, COUNT(*) FROM comments
+             WHERE todo_id IN (SELECT value FROM json_each(?1)) GROUP BY todo_id",
+        )?;
+        let ids = serde_json::to_string(todo_ids)?;
+        let counts = stmt
+            .query_map(params![ids], |row| {
+                let todo_id: String = row.get(0)?;
+                let count: i64 = row.get(1)?;
+                Ok((
+                    Uuid::parse_str(&todo_id).map_err(|err| conversion_error(0, err))?,
+                    count as usize,
+                ))
+            })?
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(counts)
+    }
+
+    fn user(&self, id: Uuid) -> StoreResult<Option<User>> {
+        let conn = self.pool.get()?;
+        let user = conn
+            .query_row(
+                &format!("SELECT {} FROM users WHERE id = ?1", USER_COLUMNS),
+                params![id.to_string()],
+                read_user,
+            )
+            .optional()?;
+        Ok(user)
+    }
+
+    fn user_by_name(&self, username: &str) -> StoreResult<Option<User>> {
+        let conn = self.pool.get()?;
+        let user = conn
+            .query_row(
+                &format!("SELECT {} FROM users WHERE username = ?1", USER_COLUMNS),
+                params![username],
+                read_user,
+            )
+            .optional()?;
+        Ok(user)
+    }
+
+    fn session(&self, id: &str) -> StoreResult<Option<Session>> {
+        let conn = self.pool.get()?;
+        let session = conn
+            .query_row(
+                &format!("SELECT {} FROM sessions WHERE id = ?1", SESSION_COLUMNS),
+                params![id],
+                read_session,
+            )
+            .optional()?;
+        Ok(session)
+    }
+
+    fn sessions(&self, user_id: Uuid) -> StoreResult<Vec<Session>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM sessions WHERE user_id = ?1 ORDER BY created_at, id",
+            SESSION_COLUMNS
+        ))?;
+        let sessions = stmt
+            .query_map(params![user_id.to_string()], read_session)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(sessions)
+    }
+
+    fn api_token(&self, id: Uuid) -> StoreResult<Option<ApiToken>> {
+        let conn = self.pool.get()?;
+        let token = conn
+            .query_row(
+                &format!("SELECT {} FROM api_tokens WHERE id = ?1", API_TOKEN_COLUMNS),
+                params![id.to_string()],
+                read_api_token,
+            )
+            .optional()?;
+        Ok(token)
+    }
+
+    fn api_token_by_hash(&self, hash: &str) -> StoreResult<Option<ApiToken>> {
+        let conn = self.pool.get()?;
+        let token = conn
+            .query_row(
+                &format!("SELECT {} FROM api_tokens WHERE hash = ?1", API_TOKEN_COLUMNS),
+                params![hash],
+                read_api_token,
+            )
+            .optional()?;
+        Ok(token)
+    }
+
+    fn api_tokens(&self, user_id: Uuid) -> StoreResult<Vec<ApiToken>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM api_tokens WHERE user_id = ?1 ORDER BY created_at, id",
+            API_TOKEN_COLUMNS
+        ))?;
+        let tokens = stmt
+            .query_map(params![user_id.to_string()], read_api_token)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tokens)
+    }
+
+    fn members(&self, list_id: Uuid) -> StoreResult<Vec<ListMember>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM list_members WHERE list_id = ?1 ORDER BY added_at, user_id",
+            MEMBER_COLUMNS
+        ))?;
+        let members = stmt
+            .query_map(params![list_id.to_string()], read_member)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(members)
+    }
+
+    fn memberships(&self, user_id: Uuid) -> StoreResult<Vec<ListMember>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM list_members WHERE user_id = ?1 ORDER BY added_at, list_id",
+            MEMBER_COLUMNS
+        ))?;
+        let members = stmt
+            .query_map(params![user_id.to_string()], read_member)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(members)
+    }
+
+    fn invitation(&self, id: Uuid) -> StoreResult<Option<Invitation>> {
+        let conn = self.pool.get()?;
+        let invitation = conn
+            .query_row(
+                &format!("SELECT {} FROM invitations WHERE id = ?1", INVITATION_COLUMNS),
+                params![id.to_string()],
+                read_invitation,
+            )
+            .optional()?;
+        Ok(invitation)
+    }
+
+    fn list_invitations(&self, list_id: Uuid) -> StoreResult<Vec<Invitation>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM invitations WHERE list_id = ?1 ORDER BY created_at, id",
+            INVITATION_COLUMNS
+        ))?;
+        let invitations = stmt
+            .query_map(params![list_id.to_string()], read_invitation)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(invitations)
+    }
+
+    fn user_invitations(&self, user_id: Uuid) -> StoreResult<Vec<Invitation>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM invitations WHERE user_id = ?1 ORDER BY created_at, id",
+            INVITATION_COLUMNS
+        ))?;
+        let invitations = stmt
+            .query_map(params![user_id.to_string()], read_invitation)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(invitations)
+    }
+
+    fn access_log(&self, list_id: Uuid) -> StoreResult<Vec<AccessChange>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM access_log WHERE list_id = ?1 ORDER BY rowid",
+            ACCESS_LOG_COLUMNS
+        ))?;
+        let changes = stmt
+            .query_map(params![list_id.to_string()], read_access_change)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(changes)
+    }
+
+    fn share_link(&self, id: Uuid) -> StoreResult<Option<ShareLink>> {
+        let conn = self.pool.get()?;
+        let link = conn
+            .query_row(
+                &format!("SELECT {} FROM share_links WHERE id = ?1", SHARE_LINK_COLUMNS),
+                params![id.to_string()],
+                read_share_link,
+            )
+            .optional()?;
+        Ok(link)
+    }
+
+    fn share_links(&self, owner_id: Uuid) -> StoreResult<Vec<ShareLink>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM share_links WHERE owner_id = ?1 ORDER BY created_at, id",
+            SHARE_LINK_COLUMNS
+        ))?;
+        let links = stmt
+            .query_map(params![owner_id.to_string()], read_share_link)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(links)
+    }
+
+    fn list_share_links(&self, list_id: Uuid) -> StoreResult<Vec<ShareLink>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM share_links WHERE list_id = ?1 ORDER BY created_at, id",
+            SHARE_LINK_COLUMNS
+        ))?;
+        let links = stmt
+            .query_map(params![list_id.to_string()], read_share_link)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(links)
+    }
+
+    fn two_factor(&self, user_id: Uuid) -> StoreResult<Option<TwoFactor>> {
+        let conn = self.pool.get()?;
+        let factor = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM two_factors WHERE user_id = ?1",
+                    TWO_FACTOR_COLUMNS
+                ),
+                params![user_id.to_string()],
+                read_two_factor,
+            )
+            .optional()?;
+        Ok(factor)
+    }
+
+    fn commit(&self, events: &[Event]) -> StoreResult<()> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        for event in events {
+            write_event(&tx, event)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+// Translate a single event into the matching statement; any error rolls back the whole batch
+fn write_event(tx: &Transaction<'_>, event: &Event) -> StoreResult<()> {
+    match event {
+        Event::TodoCreated(todo) => {
+            let columns: Vec<&str> = TODO_COLUMNS.split(", ").collect();
+            let placeholders: Vec<String> =
+                (1..=columns.len()).map(|n| format!("?{}", n)).collect();
+            let inserted = tx.execute(
+                &format!(
+                    "INSERT INTO todos ({}) VALUES ({}) ON CONFLICT (id) DO NOTHING",
+                    TODO_COLUMNS,
+                    placeholders.join(", ")
+                ),
+                params_from_iter(todo_params(todo)),
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "todo {} already exists",
+                    todo.id
+                )));
+            }
+        }
+        Event::TodoUpdated(todo) => {
+            let assignments: Vec<String> = TODO_COLUMNS
+                .split(", ")
+                .enumerate()
+                .skip(1)
+                .map(|(index, column)| format!("{} = ?{}", column, index + 1))
+                .collect();
+            let updated = tx.execute(
+                &format!("UPDATE todos SET {} WHERE id = ?1", assignments.join(", ")),
+                params_from_iter(todo_params(todo)),
+            )?;
+            if updated == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "todo {} does not exist",
+                    todo.id
+                )));
+            }
+        }
+        Event::TodoDeleted { id } => {
+            let removed = tx.execute("DELETE FROM todos WHERE id = ?1", params![id.to_string()])?;
+            if removed == 0 {
+                return Err(StoreError::Conflict(format!("todo {} does not exist", id)));
+            }
+        }
+        Event::RevisionRecorded(revision) => {
+            tx.execute(
+                "INSERT INTO revisions (id, todo_id, number, data) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    revision.id.to_string(),
+                    revision.todo_id.to_string(),
+                    revision.number,
+                    serde_json::to_string(revision)?,
+                ],
+            )?;
+        }
+        Event::SmartListCreated(list) => {
+            let inserted = tx.execute(
+                &format!(
+                    "INSERT INTO smart_lists ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6) ON CONFLICT (id) DO NOTHING",
+                    SMART_LIST_COLUMNS
+                ),
+                params_from_iter(smart_list_params(list)),
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "smart list {} already exists",
+                    list.id
+                )));
+            }
+        }
+        Event::SmartListUpdated(list) => {
+            let updated = tx.execute(
+                "UPDATE smart_lists SET name = ?2, filter = ?3, created_at = ?4, updated_at = ?5, owner_id = ?6 WHERE id = ?1",
+                params_from_iter(smart_list_params(list)),
+            )?;
+            if updated == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "smart list {} does not exist",
+                    list.id
+                )));
+            }
+        }
+        Event::SmartListDeleted { id } => {
+            let removed = tx.execute(
+                "DELETE FROM smart_lists WHERE id = ?1",
+                params![id.to_string()],
+            )?;
+            if removed == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "smart list {} does not exist",
+                    id
+                )));
+            }
+        }
+        Event::ListCreated(list) => {
+            let inserted = tx.execute(
+                &format!(
+                    "INSERT INTO lists ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8) ON CONFLICT (id) DO NOTHING",
+                    LIST_COLUMNS
+                ),
+                params_from_iter(list_params(list)),
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "list {} already exists",
+                    list.id
+                )));
+            }
+        }
+        Event::ListUpdated(list) => {
+            let updated = tx.execute(
+                "UPDATE lists SET name = ?2, color = ?3, archived = ?4, position = ?5, created_at = ?6, updated_at = ?7, owner_id = ?8 WHERE id = ?1",
+                params_from_iter(list_params(list)),
+            )?;
+            if updated == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "list {} does not exist",
+                    list.id
+                )));
+            }
+        }
+        Event::ListDeleted { id } => {
+            let removed = tx.execute("DELETE FROM lists WHERE id = ?1", params![id.to_string()])?;
+            if removed == 0 {
+                return Err(StoreError::Conflict(format!("list {} does not exist", id)));
+            }
+        }
+        Event::AttachmentCreated(attachment) => {
+            let inserted = tx.execute(
+                &format!(
+                    "INSERT INTO attachments ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7) ON CONFLICT (id) DO NOTHING",
+                    ATTACHMENT_COLUMNS
+                ),
+                params![
+                    attachment.id.to_string(),
+                    attachment.todo_id.to_string(),
+                    attachment.file_name,
+                    attachment.content_type,
+                    attachment.size,
+                    attachment.digest,
+                    encode_time(&attachment.created_at),
+                ],
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "attachment {} already exists",
+                    attachment.id
+                )));
+            }
+        }
+        Event::CommentCreated(comment) => {
+            let inserted = tx.execute(
+                &format!(
+                    "INSERT INTO comments ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6) ON CONFLICT (id) DO NOTHING",
+                    COMMENT_COLUMNS
+                ),
+                params_from_iter(comment_params(comment)),
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "comment {} already exists",
+                    comment.id
+                )));
+            }
+        }
+        Event::CommentUpdated(comment) => {
+            let updated = tx.execute(
+                "UPDATE comments SET todo_id = ?2, author = ?3, body = ?4, created_at = ?5, edited_at = ?6 WHERE id = ?1",
+                params_from_iter(comment_params(comment)),
+            )?;
+            if updated == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "comment {} does not exist",
+                    comment.id
+                )));
+            }
+        }
+        Event::CommentDeleted { id } => {
+            let removed = tx.execute(
+                "DELETE FROM comments WHERE id = ?1",
+                params![id.to_string()],
+            )?;
+            if removed == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "comment {} does not exist",
+                    id
+                )));
+            }
+        }
+        Event::UserCreated(user) => {
+            let inserted = tx.execute(
+                &format!(
+                    "INSERT INTO users ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6) ON CONFLICT DO NOTHING",
+                    USER_COLUMNS
+                ),
+                params_from_iter(user_params(user)),
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "user {} already exists",
+                    user.username
+                )));
+            }
+        }
+        Event::UserUpdated(user) => {
+            let updated = tx.execute(
+                "UPDATE users SET username = ?2, password_hash = ?3, created_at = ?4, failed_logins = ?5, locked_until = ?6 WHERE id = ?1",
+                params_from_iter(user_params(user)),
+            )?;
+            if updated == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "user {} does not exist",
+                    user.id
+                )));
+            }
+        }
+        Event::SessionCreated(session) => {
+            let inserted = tx.execute(
+                &format!(
+                    "INSERT INTO sessions ({}) VALUES (?1, ?2, ?3, ?4) ON CONFLICT (id) DO NOTHING",
+                    SESSION_COLUMNS
+                ),
+                params![
+                    session.id,
+                    session.user_id.to_string(),
+                    encode_time(&session.created_at),
+                    encode_time(&session.expires_at),
+                ],
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Conflict("session already exists".to_string()));
+            }
+        }
+        Event::SessionDeleted { id } => {
+            let removed = tx.execute("DELETE FROM sessions WHERE id = ?1", params![id])?;
+            if removed == 0 {
+                return Err(StoreError::Conflict("session does not exist".to_string()));
+            }
+        }
+        Event::ApiTokenCreated(token) => {
+            let inserted = tx.execute(
+                &format!(
+                    "INSERT INTO api_tokens ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8) ON CONFLICT DO NOTHING",
+                    API_TOKEN_COLUMNS
+                ),
+                params_from_iter(api_token_params(token)),
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "api token {} already exists",
+                    token.id
+                )));
+            }
+        }
+        Event::ApiTokenUpdated(token) => {
+            let updated = tx.execute(
+                "UPDATE api_tokens SET user_id = ?2, name = ?3, hash = ?4, scopes = ?5, created_at = ?6, expires_at = ?7, last_used_at = ?8 WHERE id = ?1",
+                params_from_iter(api_token_params(token)),
+            )?;
+            if updated == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "api token {} does not exist",
+                    token.id
+                )));
+            }
+        }
+        Event::ApiTokenDeleted { id } => {
+            let removed = tx.execute(
+                "DELETE FROM api_tokens WHERE id = ?1",
+                params![id.to_string()],
+            )?;
+            if removed == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "api token {} does not exist",
+                    id
+                )));
+            }
+        }
+        Event::MemberAdded(member) => {
+            let inserted = tx.execute(
+                &format!(
+                    "INSERT INTO list_members ({}) VALUES (?1, ?2, ?3, ?4) ON CONFLICT DO NOTHING",
+                    MEMBER_COLUMNS
+                ),
+                params_from_iter(member_params(member)),
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "list {} is already shared with {}",
+                    member.list_id, member.user_id
+                )));
+            }
+        }
+        Event::MemberUpdated(member) => {
+            let updated = tx.execute(
+                "UPDATE list_members SET role = ?3, added_at = ?4 WHERE list_id = ?1 AND user_id = ?2",
+                params_from_iter(member_params(member)),
+            )?;
+            if updated == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "list {} is not shared with {}",
+                    member.list_id, member.user_id
+                )));
+            }
+        }
+        Event::MemberRemoved { list_id, user_id } => {
+            let removed = tx.execute(
+                "DELETE FROM list_members WHERE list_id = ?1 AND user_id = ?2",
+                params![list_id.to_string(), user_id.to_string()],
+            )?;
+            if removed == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "list {} is not shared with {}",
+                    list_id, user_id
+                )));
+            }
+        }
+        Event::InvitationCreated(invitation) => {
+            let inserted = tx.execute(
+                &format!(
+                    "INSERT INTO invitations ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6) ON CONFLICT DO NOTHING",
+                    INVITATION_COLUMNS
+                ),
+                params![
+                    invitation.id.to_string(),
+                    invitation.list_id.to_string(),
+                    invitation.user_id.to_string(),
+                    invitation.role.name(),
+                    invitation.invited_by.to_string(),
+                    encode_time(&invitation.created_at),
+                ],
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "invitation {} already exists",
+                    invitation.id
+                )));
+            }
+        }
+        Event::InvitationDeleted { id } => {
+            let removed = tx.execute(
+                "DELETE FROM invitations WHERE id = ?1",
+                params![id.to_string()],
+            )?;
+            if removed == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "invitation {} does not exist",
+                    id
+                )));
+            }
+        }
+        Event::AccessRecorded(change) => {
+            tx.execute(
+                &format!(
+                    "INSERT INTO access_log ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    ACCESS_LOG_COLUMNS
+                ),
+                params![
+                    change.id.to_string(),
+                    change.list_id.to_string(),
+                    change.actor,
+                    change.action.name(),
+                    change.username,
+                    change.role.map(Role::name),
+                    encode_time(&change.at),
+                ],
+            )?;
+        }
+        Event::ShareLinkCreated(link) => {
+            let inserted = tx.execute(
+                &format!(
+                    "INSERT INTO share_links ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6) ON CONFLICT DO NOTHING",
+                    SHARE_LINK_COLUMNS
+                ),
+                params![
+                    link.id.to_string(),
+                    link.owner_id.to_string(),
+                    link.list_id.map(|id| id.to_string()),
+                    link.todo_id.map(|id| id.to_string()),
+                    encode_time(&link.created_at),
+                    encode_time(&link.expires_at),
+                ],
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "share link {} already exists",
+                    link.id
+                )));
+            }
+        }
+        Event::ShareLinkDeleted { id } => {
+            let removed = tx.execute(
+                "DELETE FROM share_links WHERE id = ?1",
+                params![id.to_string()],
+            )?;
+            if removed == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "share link {} does not exist",
+                    id
+                )));
+            }
+        }
+        Event::TwoFactorCreated(factor) => {
+            let inserted = tx.execute(
+                &format!(
+                    "INSERT INTO two_factors ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8) ON CONFLICT DO NOTHING",
+                    TWO_FACTOR_COLUMNS
+                ),
+                params_from_iter(two_factor_params(factor)),
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "account {} already has a second sign-in step",
+                    factor.user_id
+                )));
+            }
+        }
+        Event::TwoFactorUpdated(factor) => {
+            let updated = tx.execute(
+                "UPDATE two_factors SET secret = ?2, enabled = ?3, recovery_codes = ?4, last_step = ?5, failures = ?6, locked_until = ?7, created_at = ?8 WHERE user_id = ?1",
+                params_from_iter(two_factor_params(factor)),
+            )?;
+            if updated == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "account {} has no second sign-in step",
+                    factor.user_id
+                )));
+            }
+        }
+        Event::TwoFactorDeleted { user_id } => {
+            let removed = tx.execute(
+                "DELETE FROM two_factors WHERE user_id = ?1",
+                params![user_id.to_string()],
+            )?;
+            if removed == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "account {} has no second sign-in step",
+                    user_id
+                )));
+            }
+        }
+        Event::AttachmentDeleted { id } => {
+            let removed = tx.execute(
+                "DELETE FROM attachments WHERE id = ?1",
+                params![id.to_string()],
+            )?;
+            if removed == 0 {
+                return Err(StoreError::Conflict(format!(
+                    "attachment {} does not exist",
+                    id
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Positional parameters collected while a query string is being built
+#[derive(Default)]
+struct Args {
+    values: Vec<Box<dyn ToSql>>,
+}
+
+impl Args {
+    // Record `value` and return the placeholder referring to it
+    fn bind(&mut self, value: impl ToSql + 'static) -> String {
+        self.values.push(Box::new(value));
+        format!("?{}", self.values.len())
+    }
+}
+
+// SQL expression matching `SortField::key`
+fn sort_expression(field: SortField) -> &'static str {
+    match field {
+        SortField::Position => "rank",
+        SortField::CreatedAt => "created_at",
+        SortField::UpdatedAt => "COALESCE(updated_at, created_at)",
+        SortField::Title => "lower(title)",
+    }
+}
+
+// Values bound for `TODO_COLUMNS`, in the same order
+fn todo_params(todo: &TodoItem) -> Vec<Box<dyn ToSql>> {
+    vec![
+        Box::new(todo.id.to_string()),
+        Box::new(todo.title.clone()),
+        Box::new(todo.completed),
+        Box::new(encode_time(&todo.created_at)),
+        Box::new(todo.updated_at.as_ref().map(encode_time)),
+        Box::new(todo.deleted_at.as_ref().map(encode_time)),
+        Box::new(todo.due_at.as_ref().map(Schedule::encode)),
+        Box::new(todo.start_at.as_ref().map(Schedule::encode)),
+        Box::new(todo.priority.name()),
+        Box::new(serde_json::json!(todo.tags).to_string()),
+        Box::new(todo.parent_id.map(|id| id.to_string())),
+        Box::new(serde_json::json!(todo.blocked_by).to_string()),
+        Box::new(todo.recurrence.as_ref().map(Recurrence::to_string)),
+        Box::new(todo.repeat_from.name()),
+        Box::new(serde_json::json!(todo.reminders).to_string()),
+        Box::new(serde_json::json!(todo.reminded).to_string()),
+        Box::new(todo.list_id.map(|id| id.to_string())),
+        Box::new(todo.rank.clone()),
+        Box::new(todo.notes.clone()),
+        Box::new(serde_json::json!(todo.checklist).to_string()),
+        Box::new(todo.owner_id.map(|id| id.to_string())),
+    ]
+}
+
+// Build a to-do item from a row selected with `TODO_COLUMNS`
+fn read_todo(row: &Row<'_>) -> rusqlite::Result<TodoItem> {
+    let id: String = row.get(0)?;
+    Ok(TodoItem {
+        id: Uuid::parse_str(&id).map_err(|err| conversion_error(0, err))?,
+        title: row.get(1)?,
+        completed: row.get(2)?,
+        created_at: decode_time(3, &row.get::<_, String>(3)?)?,
+        updated_at: decode_optional_time(row, 4)?,
+        deleted_at: decode_optional_time(row, 5)?,
+        due_at: decode_schedule(row, 6)?,
+        start_at: decode_schedule(row, 7)?,
+        priority: decode_priority(row, 8)?,
+        tags: read_json(row, 9)?,
+        parent_id: decode_optional_id(row, 10)?,
+        blocked_by: read_json(row, 11)?,
+        recurrence: decode_recurrence(row, 12)?,
+        repeat_from: decode_repeat_from(row, 13)?,
+        reminders: read_json(row, 14)?,
+        reminded: read_json(row, 15)?,
+        list_id: decode_optional_id(row, 16)?,
+        rank: row.get(17)?,
+        notes: row.get(18)?,
+        checklist: read_json(row, 19)?,
+        owner_id: decode_optional_id(row, 20)?,
+    })
+}
+
+// Values bound for `SMART_LIST_COLUMNS`, in the same order
+fn smart_list_params(list: &SmartList) -> Vec<Box<dyn ToSql>> {
+    vec![
+        Box::new(list.id.to_string()),
+        Box::new(list.name.clone()),
+        Box::new(list.filter.clone()),
+        Box::new(encode_time(&list.created_at)),
+        Box::new(list.updated_at.as_ref().map(encode_time)),
+        Box::new(list.owner_id.map(|id| id.to_string())),
+    ]
+}
+
+// Build a smart list from a row selected with `SMART_LIST_COLUMNS`
+fn read_smart_list(row: &Row<'_>) -> rusqlite::Result<SmartList> {
+    let id: String = row.get(0)?;
+    Ok(SmartList {
+        id: Uuid::parse_str(&id).map_err(|err| conversion_error(0, err))?,
+        name: row.get(1)?,
+        filter: row.get(2)?,
+        created_at: decode_time(3, &row.get::<_, String>(3)?)?,
+        updated_at: decode_optional_time(row, 4)?,
+        owner_id: decode_optional_id(row, 5)?,
+    })
+}
+
+// Values bound for `LIST_COLUMNS`, in the same order
+fn list_params(list: &TodoList) -> Vec<Box<dyn ToSql>> {
+    vec![
+        Box::new(list.id.to_string()),
+        Box::new(list.name.clone()),
+        Box::new(list.color.clone()),
+        Box::new(list.archived),
+        Box::new(list.position),
+        Box::new(encode_time(&list.created_at)),
+        Box::new(list.updated_at.as_ref().map(encode_time)),
+        Box::new(list.owner_id.map(|id| id.to_string())),
+    ]
+}
+
+// Build a list from a row selected with `LIST_COLUMNS`
+fn read_list(row: &Row<'_>) -> rusqlite::Result<TodoList> {
+    let id: String = row.get(0)?;
+    Ok(TodoList {
+        id: Uuid::parse_str(&id).map_err(|err| conversion_error(0, err))?,
+        name: row.get(1)?,
+        color: row.get(2)?,
+        archived: row.get(3)?,
+        position: row.get(4)?,
+        created_at: decode_time(5, &row.get::<_, String>(5)?)?,
+        updated_at: decode_optional_time(row, 6)?,
+        owner_id: decode_optional_id(row, 7)?,
+    })
+}
+
+// Build an attachment from a row selected with `ATTACHMENT_COLUMNS`
+fn read_attachment(row: &Row<'_>) -> rusqlite::Result<Attachment> {
+    let id: String = row.get(0)?;
+    let todo_id: String = row.get(1)?;
+    Ok(Attachment {
+        id: Uuid::parse_str(&id).map_err(|err| conversion_error(0, err))?,
+        todo_id: Uuid::parse_str(&todo_id).map_err(|err| conversion_error(1, err))?,
+        file_name: row.get(2)?,
+        content_type: row.get(3)?,
+        size: row.get(4)?,
+        digest: row.get(5)?,
+        created_at: decode_time(6, &row.get::<_, String>(6)?)?,
+    })
+}
+
+// Values bound for `COMMENT_COLUMNS`, in the same order
+fn comment_params(comment: &Comment) -> Vec<Box<dyn ToSql>> {
+    vec![
+        Box::new(comment.id.to_string()),
+        Box::new(comment.todo_id.to_string()),
+        Box::new(comment.author.clone()),
+        Box::new(comment.body.clone()),
+        Box::new(encode_time(&comment.created_at)),
+        Box::new(comment.edited_at.as_ref().map(encode_time)),
+    ]
+}
+
+// Build a comment from a row selected with `COMMENT_COLUMNS`
+fn read_comment(row: &Row<'_>) -> rusqlite::Result<Comment> {
+    let id: String = row.get(0)?;
+    let todo_id: String = row.get(1)?;
+    Ok(Comment {
+        id: Uuid::parse_str(&id).map_err(|err| conversion_error(0, err))?,
+        todo_id: Uuid::parse_str(&todo_id).map_err(|err| conversion_error(1, err))?,
+        author: row.get(2)?,
+        body: row.get(3)?,
+        created_at: decode_time(4, &row.get::<_, String>(4)?)?,
+        edited_at: decode_optional_time(row, 5)?,
+    })
+}
+
+// Build an account from a row selected with `USER_COLUMNS`
+fn read_user(row: &Row<'_>) -> rusqlite::Result<User> {
+    let id: String = row.get(0)?;
+    Ok(User {
+        id: Uuid::parse_str(&id).map_err(|err| conversion_error(0, err))?,
+        username: row.get(1)?,
+        password_hash: row.get(2)?,
+        created_at: decode_time(3, &row.get::<_, String>(3)?)?,
+        failed_logins: row.get(4)?,
+        locked_until: decode_optional_time(row, 5)?,
+    })
+}
+
+// Build a session from a row selected with `SESSION_COLUMNS`
+fn read_session(row: &Row<'_>) -> rusqlite::Result<Session> {
+    let user_id: String = row.get(1)?;
+    Ok(Session {
+        id: row.get(0)?,
+        user_id: Uuid::parse_str(&user_id).map_err(|err| conversion_error(1, err))?,
+        created_at: decode_time(2, &row.get::<_, String>(2)?)?,
+        expires_at: decode_time(3, &row.get::<_, String>(3)?)?,
+    })
+}
+
+// Values bound for `API_TOKEN_COLUMNS`, in the same order
+fn api_token_params(token: &ApiToken) -> Vec<Box<dyn ToSql>> {
+    vec![
+        Box::new(token.id.to_string()),
+        Box::new(token.user_id.to_string()),
+        Box::new(token.name.clone()),
+        Box::new(token.hash.clone()),
+        Box::new(serde_json::json!(token.scopes).to_string()),
+        Box::new(encode_time(&token.created_at)),
+        Box::new(token.expires_at.as_ref().map(encode_time)),
+        Box::new(token.last_used_at.as_ref().map(encode_time)),
+    ]
+}
+
+// Values bound for `USER_COLUMNS`, in the same order
+fn user_params(user: &User) -> Vec<Box<dyn ToSql>> {
+    vec![
+        Box::new(user.id.to_string()),
+        Box::new(user.username.clone()),
+        Box::new(user.password_hash.clone()),
+        Box::new(encode_time(&user.created_at)),
+        Box::new(user.failed_logins),
+        Box::new(user.locked_until.as_ref().map(encode_time)),
+    ]
+}
+
+// Values bound for `TWO_FACTOR_COLUMNS`, in the same order
+fn two_factor_params(factor: &TwoFactor) -> Vec<Box<dyn ToSql>> {
+    vec![
+        Box::new(factor.user_id.to_string()),
+        Box::new(factor.secret.clone()),
+        Box::new(factor.enabled),
+        Box::new(serde_json::json!(factor.recovery_codes).to_string()),
+        Box::new(factor.last_step),
+        Box::new(factor.failures),
+        Box::new(factor.locked_until.as_ref().map(encode_time)),
+        Box::new(encode_time(&factor.created_at)),
+    ]
+}
+
+// Values bound for `MEMBER_COLUMNS`, in the same order
+fn member_params(member: &ListMember) -> Vec<Box<dyn ToSql>> {
+    vec![
+        Box::new(member.list_id.to_string()),
+        Box::new(member.user_id.to_string()),
+        Box::new(member.role.name()),
+        Box::new(encode_time(&member.added_at)),
+    ]
+}
+
+// Build a list member from a row selected with `MEMBER_COLUMNS`
+fn read_member(row: &Row<'_>) -> rusqlite::Result<ListMember> {
+    let list_id: String = row.get(0)?;
+    let user_id: String = row.get(1)?;
+    Ok(ListMember {
+        list_id: Uuid::parse_str(&list_id).map_err(|err| conversion_error(0, err))?,
+        user_id: Uuid::parse_str(&user_id).map_err(|err| conversion_error(1, err))?,
+        role: decode_role(row, 2)?,
+        added_at: decode_time(3, &row.get::<_, String>(3)?)?,
+    })
+}
+
+// Build an invitation from a row selected with `INVITATION_COLUMNS`
+fn read_invitation(row: &Row<'_>) -> rusqlite::Result<Invitation> {
+    let id: String = row.get(0)?;
+    let list_id: String = row.get(1)?;
+    let user_id: String = row.get(2)?;
+    let invited_by: String = row.get(4)?;
+    Ok(Invitation {
+        id: Uuid::parse_str(&id).map_err(|err| conversion_error(0, err))?,
+        list_id: Uuid::parse_str(&list_id).map_err(|err| conversion_error(1, err))?,
+        user_id: Uuid::parse_str(&user_id).map_err(|err| conversion_error(2, err))?,
+        role: decode_role(row, 3)?,
+        invited_by: Uuid::parse_str(&invited_by).map_err(|err| conversion_error(4, err))?,
+        created_at: decode_time(5, &row.get::<_, String>(5)?)?,
+    })
+}
+
+// Build an audit log entry from a row selected with `ACCESS_LOG_COLUMNS`
+fn read_access_change(row: &Row<'_>) -> rusqlite::Result<AccessChange> {
+    let id: String = row.get(0)?;
+    let list_id: String = row.get(1)?;
+    Ok(AccessChange {
+        id: Uuid::parse_str(&id).map_err(|err| conversion_error(0, err))?,
+        list_id: Uuid::parse_str(&list_id).map_err(|err| conversion_error(1, err))?,
+        actor: row.get(2)?,
+        action: decode_access_action(row, 3)?,
+        username: row.get(4)?,
+        role: match row.get::<_, Option<String>>(5)? {
+            Some(_) => Some(decode_role(row, 5)?),
+            None => None,
+        },
+        at: decode_time(6, &row.get::<_, String>(6)?)?,
+    })
+}
+
+// Build a share link from a row selected with `SHARE_LINK_COLUMNS`
+fn read_share_link(row: &Row<'_>) -> rusqlite::Result<ShareLink> {
+    let id: String = row.get(0)?;
+    let owner_id: String = row.get(1)?;
+    Ok(ShareLink {
+        id: Uuid::parse_str(&id).map_err(|err| conversion_error(0, err))?,
+        owner_id: Uuid::parse_str(&owner_id).map_err(|err| conversion_error(1, err))?,
+        list_id: decode_optional_id(row, 2)?,
+        todo_id: decode_optional_id(row, 3)?,
+        created_at: decode_time(4, &row.get::<_, String>(4)?)?,
+        expires_at: decode_time(5, &row.get::<_, String>(5)?)?,
+    })
+}
+
+// Build a second sign-in step from a row selected with `TWO_FACTOR_COLUMNS`
+fn read_two_factor(row: &Row<'_>) -> rusqlite::Result<TwoFactor> {
+    let user_id: String = row.get(0)?;
+    Ok(TwoFactor {
+        user_id: Uuid::parse_str(&user_id).map_err(|err| conversion_error(0, err))?,
+        secret: row.get(1)?,
+        enabled: row.get(2)?,
+        recovery_codes: read_json(row, 3)?,
+        last_step: row.get(4)?,
+        failures: row.get(5)?,
+        locked_until: decode_optional_time(row, 6)?,
+        created_at: decode_time(7, &row.get::<_, String>(7)?)?,
+    })
+}
+
+// Build an API token from a row selected with `API_TOKEN_COLUMNS`
+fn read_api_token(row: &Row<'_>) -> rusqlite::Result<ApiToken> {
+    let id: String = row.get(0)?;
+    let user_id: String = row.get(1)?;
+    Ok(ApiToken {
+        id: Uuid::parse_str(&id).map_err(|err| conversion_error(0, err))?,
+        user_id: Uuid::parse_str(&user_id).map_err(|err| conversion_error(1, err))?,
+        name: row.get(2)?,
+        hash: row.get(3)?,
+        scopes: read_json(row, 4)?,
+        created_at: decode_time(5, &row.get::<_, String>(5)?)?,
+        expires_at: decode_optional_time(row, 6)?,
+        last_used_at: decode_optional_time(row, 7)?,
+    })
+}
+
+// Timestamps are stored as fixed-width RFC 3339 text so that they also sort correctly as strings
+fn encode_time(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn decode_time(column: usize, value: &str) -> rusqlite::Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|err| conversion_error(column, err))
+}
+
+fn decode_optional_time(row: &Row<'_>, column: usize) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    match row.get::<_, Option<String>>(column)? {
+        Some(value) => Ok(Some(decode_time(column, &value)?)),
+        None => Ok(None),
+    }
+}
+
+fn decode_schedule(row: &Row<'_>, column: usize) -> rusqlite::Result<Option<Schedule>> {
+    match row.get::<_, Option<String>>(column)? {
+        Some(value) => Schedule::decode(&value)
+            .map(Some)
+            .ok_or_else(|| conversion_error(column, InvalidValue(value))),
+        None => Ok(None),
+    }
+}
+
+fn decode_optional_id(row: &Row<'_>, column: usize) -> rusqlite::Result<Option<Uuid>> {
+    match row.get::<_, Option<String>>(column)? {
+        Some(value) => Uuid::parse_str(&value)
+            .map(Some)
+            .map_err(|err| conversion_error(column, err)),
+        None => Ok(None),
+    }
+}
+
+fn decode_priority(row: &Row<'_>, column: usize) -> rusqlite::Result<Priority> {
+    let name: String = row.get(column)?;
+    Priority::parse(&name).ok_or_else(|| conversion_error(column, InvalidValue(name)))
+}
+
+fn decode_role(row: &Row<'_>, column: usize) -> rusqlite::Result<Role> {
+    let name: String = row.get(column)?;
+    Role::parse(&name).ok_or_else(|| conversion_error(column, InvalidValue(name)))
+}
+
+fn decode_access_action(row: &Row<'_>, column: usize) -> rusqlite::Result<AccessAction> {
+    let name: String = row.get(column)?;
+    AccessAction::parse(&name).ok_or_else(|| conversion_error(column, InvalidValue(name)))
+}
+
+fn decode_recurrence(row: &Row<'_>, column: usize) -> rusqlite::Result<Option<Recurrence>> {
+    match row.get::<_, Option<String>>(column)? {
+        Some(value) => Recurrence::parse(&value)
+            .map(Some)
+            .map_err(|_| conversion_error(column, InvalidValue(value))),
+        None => Ok(None),
+    }
+}
+
+fn decode_repeat_from(row: &Row<'_>, column: usize) -> rusqlite::Result<RepeatFrom> {
+    let name: String = row.get(column)?;
+    RepeatFrom::parse(&name).ok_or_else(|| conversion_error(column, InvalidValue(name)))
+}
+
+// A stored value that does not decode to its Rust type, e.g. a malformed date or an unknown priority
+#[derive(Debug)]
+struct InvalidValue(String);
+
+impl std::fmt::Display for InvalidValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid value `{}`", self.0)
+    }
+}
+
+impl std::error::Error for InvalidValue {}
+
+// Decode a column holding a JSON document
+fn read_json<T: serde::de::DeserializeOwned>(row: &Row<'_>, column: usize) -> rusqlite::Result<T> {
+    let text: String = row.get(column)?;
+    serde_json::from_str(&text).map_err(|err| conversion_error(column, err))
+}
+
+fn conversion_error<E: std::error::Error + Send + Sync + 'static>(
+    column: usize,
+    err: E,
+) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, Box::new(err))
+}
@@ -0,0 +1,80 @@
+// Helpers shared by the tests of every module
+use chrono::{Duration, Utc};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::attachments::BlobStore;
+use crate::models::{TodoItem, TodoList, User};
+use crate::share_links::ShareSigner;
+use crate::state::AppState;
+use crate::store::MemoryStore;
+
+// A fresh directory under the system temp dir, removed again when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        let dir = env::temp_dir().join(format!("todo-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    pub fn path(&self, name: &str) -> String {
+        self.0.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// An open item with only the fields every item has
+pub fn todo(list_id: Option<Uuid>) -> TodoItem {
+    serde_json::from_value(serde_json::json!({
+        "id": Uuid::new_v4(),
+        "title": "milk",
+        "completed": false,
+        "created_at": Utc::now(),
+        "updated_at": null,
+        "list_id": list_id,
+    }))
+    .unwrap()
+}
+
+// An account called `username`, with no password that signs in to it
+pub fn user(username: &str) -> User {
+    User {
+        id: Uuid::new_v4(),
+        username: username.to_string(),
+        password_hash: String::new(),
+        created_at: Utc::now(),
+        failed_logins: 0,
+        locked_until: None,
+    }
+}
+
+// A list belonging to `owner`
+pub fn list(owner: &User) -> TodoList {
+    TodoList {
+        id: Uuid::new_v4(),
+        name: "groceries".to_string(),
+        color: None,
+        archived: false,
+        position: 0,
+        created_at: Utc::now(),
+        updated_at: None,
+        owner_id: Some(owner.id),
+    }
+}
+
+// Application state over an empty memory store, keeping attachments in `dir`
+pub fn app_state(dir: &TempDir) -> AppState {
+    let store = Box::new(MemoryStore::new());
+    let blobs = BlobStore::open(PathBuf::from(dir.path("attachments")), 1024 * 1024).unwrap();
+    let signer = ShareSigner::from_env().unwrap();
+    AppState::new(store, blobs, Duration::hours(1), None, signer).unwrap()
+}
@@ -0,0 +1,306 @@
+// Time-based one-time passwords (RFC 6238) for the second sign-in step
+//
+// A code is the six-digit HOTP (RFC 4226) of the number of 30-second steps since the Unix epoch,
+// keyed with a secret the authenticator app was given through an otpauth:// URI. Codes of the step
+// before and after the current one are accepted too, for clocks that drift, but each step only
+// once.
+// Recovery codes stand in for the app; each works once and only its hash is kept. Too many wrong
+// codes in a row lock the account's codes for a while. Nothing here reads the clock: callers pass
+// the current time in, so every result can be checked against a fixed one.
+use chrono::{DateTime, Duration, Utc};
+use rand_core::{OsRng, RngCore};
+use ring::hmac;
+
+use crate::auth::{self, Refusal};
+use crate::models::TwoFactor;
+
+// Seconds each code is valid for
+const STEP_SECS: i64 = 30;
+
+// Digits of a code
+const DIGITS: usize = 6;
+
+// Steps before and after the current one whose codes are still accepted
+const SKEW_STEPS: i64 = 1;
+
+// Bytes of a new secret; 160 bits, the size of an HMAC-SHA1 key recommended by RFC 4226
+const SECRET_LEN: usize = 20;
+
+// Recovery codes handed out at a time, and the random bytes in each
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LEN: usize = 10;
+
+// Wrong codes in a row after which no code is checked for `LOCKOUT`
+const MAX_FAILURES: u32 = 5;
+const LOCKOUT: Duration = Duration::minutes(15);
+
+// Name of the service shown next to the account in authenticator apps
+const ISSUER: &str = "Todo";
+
+// RFC 4648 base32 alphabet, used for secrets and recovery codes
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+// A new random secret, in base32
+pub fn new_secret() -> String {
+    let mut bytes = [0u8; SECRET_LEN];
+    OsRng.fill_bytes(&mut bytes);
+    base32(&bytes)
+}
+
+// otpauth:// URI handing `secret` of `username` to an authenticator app
+pub fn uri(username: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = percent_encode(ISSUER),
+        account = percent_encode(username),
+        secret = secret,
+        digits = DIGITS,
+        period = STEP_SECS,
+    )
+}
+
+// The code for time step `step` under the base32 `secret`
+pub fn code(secret: &str, step: i64) -> Option<String> {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &base32_decode(secret)?);
+    let digest = hmac::sign(&key, &step.to_be_bytes());
+    let digest = digest.as_ref();
+    // Dynamic truncation: four bytes at the offset named by the low nibble of the last byte
+    let offset = usize::from(digest[digest.len() - 1] & 0x0f);
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&digest[offset..offset + 4]);
+    let value = u32::from_be_bytes(bytes) & 0x7fff_ffff;
+    Some(format!(
+        "{:0width$}",
+        value % 10u32.pow(DIGITS as u32),
+        width = DIGITS
+    ))
+}
+
+// Time step `time` falls in
+pub fn step(time: DateTime<Utc>) -> i64 {
+    time.timestamp().div_euclid(STEP_SECS)
+}
+
+// New recovery codes, as handed out and as hashed for keeping
+pub fn new_recovery_codes() -> (Vec<String>, Vec<String>) {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; RECOVERY_CODE_LEN];
+            OsRng.fill_bytes(&mut bytes);
+            let code = base32(&bytes).to_lowercase();
+            let shown = code
+                .as_bytes()
+                .chunks(4)
+                .map(|chunk| String::from_utf8_lossy(chunk))
+                .collect::<Vec<_>>()
+                .join("-");
+            (shown, auth::token_id(&code))
+        })
+        .unzip()
+}
+
+// Check `code` against `factor` at `now`: a code from the app, or an unused recovery code when
+// `recovery` is set. Failures, lockouts, the last step used and the recovery codes left are
+// updated in `factor`, which the caller saves whatever the outcome.
+pub fn check(
+    factor: &mut TwoFactor,
+    code: &str,
+    recovery: bool,
+    now: DateTime<Utc>,
+) -> Result<(), Refusal> {
+    if let Some(until) = factor.locked_until.filter(|until| *until > now) {
+        return Err(Refusal::Locked(until));
+    }
+    let given = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_lowercase();
+    let current = step(now);
+    let matched_step = (current - SKEW_STEPS..=current + SKEW_STEPS)
+        .filter(|step| factor.last_step.is_none_or(|last| *step > last))
+        .find(|step| self::code(&factor.secret, *step).as_deref() == Some(given.as_str()));
+    let hash = auth::token_id(&given);
+    let recovery_index = factor
+        .recovery_codes
+        .iter()
+        .position(|kept| recovery && *kept == hash);
+    if matched_step.is_none() && recovery_index.is_none() {
+        factor.failures += 1;
+        if factor.failures >= MAX_FAILURES {
+            factor.failures = 0;
+            factor.locked_until = Some(now + LOCKOUT);
+            return Err(Refusal::Locked(now + LOCKOUT));
+        }
+        return Err(Refusal::Wrong);
+    }
+    if let Some(step) = matched_step {
+        factor.last_step = Some(step);
+    }
+    if let Some(index) = recovery_index.filter(|_| matched_step.is_none()) {
+        factor.recovery_codes.remove(index);
+    }
+    factor.failures = 0;
+    factor.locked_until = None;
+    Ok(())
+}
+
+fn base32(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.trim_end_matches('=').bytes() {
+        let value = BASE32.iter().position(|b| *b == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+// Percent-encode everything but the unreserved characters of RFC 3986
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    // The SHA-1 secret of the RFC 6238 test vectors
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    fn factor() -> TwoFactor {
+        TwoFactor {
+            user_id: Uuid::new_v4(),
+            secret: base32(RFC_SECRET),
+            enabled: true,
+            recovery_codes: Vec::new(),
+            last_step: None,
+            failures: 0,
+            locked_until: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(seconds, 0).unwrap()
+    }
+
+    #[test]
+    fn codes_match_the_rfc_test_vector() {
+        // 94287082 at eight digits
+        assert_eq!(
+            code(&base32(RFC_SECRET), step(at(59))).as_deref(),
+            Some("287082")
+        );
+        assert_eq!(
+            base32_decode(&base32(RFC_SECRET)).as_deref(),
+            Some(RFC_SECRET)
+        );
+    }
+
+    #[test]
+    fn codes_of_the_neighbouring_steps_are_accepted() {
+        let now = at(1_000_000_020);
+        let current = step(now);
+        for offset in -SKEW_STEPS..=SKEW_STEPS {
+            let mut factor = factor();
+            let given = code(&factor.secret, current + offset).unwrap();
+            assert!(check(&mut factor, &given, false, now).is_ok());
+            assert_eq!(factor.last_step, Some(current + offset));
+        }
+        for offset in [-SKEW_STEPS - 1, SKEW_STEPS + 1] {
+            let mut factor = factor();
+            let given = code(&factor.secret, current + offset).unwrap();
+            assert!(matches!(
+                check(&mut factor, &given, false, now),
+                Err(Refusal::Wrong)
+            ));
+        }
+    }
+
+    #[test]
+    fn a_code_works_once() {
+        let now = at(1_000_000_020);
+        let mut factor = factor();
+        let given = code(&factor.secret, step(now)).unwrap();
+        assert!(check(&mut factor, &given, false, now).is_ok());
+        assert!(matches!(
+            check(&mut factor, &given, false, now),
+            Err(Refusal::Wrong)
+        ));
+        // Nor does an earlier step still in the window count once a later one was used
+        let earlier = code(&factor.secret, step(now) - 1).unwrap();
+        assert!(check(&mut factor, &earlier, false, now).is_err());
+    }
+
+    #[test]
+    fn wrong_codes_lock_the_codes_for_a_while() {
+        let now = at(1_000_000_020);
+        let mut factor = factor();
+        for _ in 1..MAX_FAILURES {
+            assert!(matches!(
+                check(&mut factor, "000000", false, now),
+                Err(Refusal::Wrong)
+            ));
+        }
+        let until = now + LOCKOUT;
+        assert!(
+            matches!(check(&mut factor, "000000", false, now), Err(Refusal::Locked(at)) if at == until)
+        );
+        // Not even the right code is taken until the lock lifts
+        let later = until - Duration::seconds(1);
+        let right = code(&factor.secret, step(later)).unwrap();
+        assert!(matches!(
+            check(&mut factor, &right, false, later),
+            Err(Refusal::Locked(_))
+        ));
+        let right = code(&factor.secret, step(until)).unwrap();
+        assert!(check(&mut factor, &right, false, until).is_ok());
+        assert_eq!((factor.failures, factor.locked_until), (0, None));
+    }
+
+    #[test]
+    fn a_recovery_code_works_once() {
+        let now = at(1_000_000_020);
+        let mut factor = factor();
+        let (shown, hashes) = new_recovery_codes();
+        factor.recovery_codes = hashes;
+        assert!(check(&mut factor, &shown[0], false, now).is_err());
+        assert!(check(&mut factor, &shown[0].to_uppercase(), true, now).is_ok());
+        assert_eq!(factor.recovery_codes.len(), RECOVERY_CODE_COUNT - 1);
+        assert!(check(&mut factor, &shown[0], true, now).is_err());
+        assert!(check(&mut factor, &shown[1], true, now).is_ok());
+    }
+}
@@ -0,0 +1,149 @@
+// Background purge of items that have sat in the trash for longer than the retention period
+use actix_web::rt::time::interval;
+use actix_web::web;
+use chrono::{DateTime, Duration, Utc};
+use std::env;
+
+use crate::history;
+use crate::models::{RevisionAction, TodoItem};
+use crate::store::{Event, StoreResult, TodoQuery, TodoStore};
+use crate::state::AppState;
+
+// How often the purge runs
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+// Longest retention accepted, in days
+const MAX_RETENTION_DAYS: i64 = 100 * 366;
+
+// Days an item stays in the trash, from TODO_TRASH_RETENTION_DAYS (default 30, 0 keeps items forever)
+pub fn retention_from_env() -> Result<Option<Duration>, String> {
+    match env::var("TODO_TRASH_RETENTION_DAYS") {
+        Ok(value) => parse_retention(&value),
+        Err(_) => Ok(Some(Duration::days(30))),
+    }
+}
+
+fn parse_retention(value: &str) -> Result<Option<Duration>, String> {
+    match value.trim().parse::<i64>() {
+        Ok(0) => Ok(None),
+        Ok(days) if (1..=MAX_RETENTION_DAYS).contains(&days) => Ok(Duration::try_days(days)),
+        _ => Err(format!(
+            "invalid day count `{}` in TODO_TRASH_RETENTION_DAYS; use 0 to {}",
+            value, MAX_RETENTION_DAYS
+        )),
+    }
+}
+
+// Events permanently removing a trashed item with its attachments and comments, recorded in its history as done by
+// `actor`
+pub fn purge_events(
+    store: &dyn TodoStore,
+    todo: &TodoItem,
+    actor: &str,
+) -> StoreResult<Vec<Event>> {
+    let mut events: Vec<Event> = store
+        .attachments(Some(todo.id))?
+        .into_iter()
+        .map(|attachment| Event::AttachmentDeleted { id: attachment.id })
+        .collect();
+    events.extend(
+        store
+            .comments(todo.id)?
+            .into_iter()
+            .map(|comment| Event::CommentDeleted { id: comment.id }),
+    );
+    events.push(Event::TodoDeleted { id: todo.id });
+    events.extend(history::record(
+        store,
+        Some(todo),
+        todo,
+        RevisionAction::Purged,
+        actor,
+    )?);
+    Ok(events)
+}
+
+// Permanently remove every item deleted more than `retention` before `now`, returning how many were
+// purged
+pub fn purge_expired(
+    data: &AppState,
+    retention: Duration,
+    now: DateTime<Utc>,
+) -> StoreResult<usize> {
+    let _guard = data.writes.lock().unwrap();
+    let cutoff = now - retention;
+    let query = TodoQuery {
+        in_trash: true,
+        ..TodoQuery::default()
+    };
+    let mut events = Vec::new();
+    let mut purged = 0;
+    for todo in data.store.list(&query)? {
+        if todo
+            .deleted_at
+            .is_some_and(|deleted_at| deleted_at < cutoff)
+        {
+            events.extend(purge_events(data.store.as_ref(), &todo, "system")?);
+            purged += 1;
+        }
+    }
+    if !events.is_empty() {
+        data.commit(&events)?;
+    }
+    Ok(purged)
+}
+
+// Run `purge_expired` now and then every hour on the actix runtime
+pub fn spawn_purger(data: web::Data<AppState>, retention: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut ticks = interval(PURGE_INTERVAL);
+        loop {
+            ticks.tick().await;
+            match purge_expired(&data, retention, Utc::now()) {
+                Ok(0) => {}
+                Ok(purged) => log::info!("purged {} todos from the trash", purged),
+                Err(err) => log::error!("failed to purge the trash: {}", err),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app_state, todo, TempDir};
+
+    #[test]
+    fn the_retention_must_be_a_sensible_day_count() {
+        assert_eq!(parse_retention("0"), Ok(None));
+        assert_eq!(parse_retention("30"), Ok(Some(Duration::days(30))));
+        for value in ["", "thirty", "-1", "36601", "9223372036854775807"] {
+            assert!(parse_retention(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn only_items_trashed_before_the_cutoff_are_purged() {
+        let dir = TempDir::new();
+        let data = app_state(&dir);
+        let now = Utc::now();
+        let trashed = |days| {
+            let mut item = todo(None);
+            item.deleted_at = Some(now - Duration::days(days));
+            item
+        };
+        let (old, recent, live) = (trashed(31), trashed(29), todo(None));
+        data.commit(&[
+            Event::TodoCreated(old.clone()),
+            Event::TodoCreated(recent.clone()),
+            Event::TodoCreated(live.clone()),
+        ])
+        .unwrap();
+
+        assert_eq!(purge_expired(&data, Duration::days(30), now).unwrap(), 1);
+        assert!(data.store.get(old.id).unwrap().is_none());
+        assert!(data.store.get(recent.id).unwrap().is_some());
+        assert!(data.store.get(live.id).unwrap().is_some());
+        assert_eq!(purge_expired(&data, Duration::days(30), now).unwrap(), 0);
+    }
+}
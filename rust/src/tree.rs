@@ -0,0 +1,113 @@
+// Subtask hierarchy: walking descendants, guarding against cycles and building nested trees
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+use crate::models::{Progress, TodoItem, TodoTree};
+use crate::store::{StoreResult, TodoQuery, TodoStore};
+
+// Items grouped by the task they are subtasks of
+pub struct Children(HashMap<Uuid, Vec<TodoItem>>);
+
+impl Children {
+    // Group the live items below `id` at any depth, plus the trashed ones when `with_trash` is set.
+    // Only the subtasks of items found on the way are looked up, one level at a time.
+    pub fn below(store: &dyn TodoStore, id: Uuid, with_trash: bool) -> StoreResult<Self> {
+        let mut children: HashMap<Uuid, Vec<TodoItem>> = HashMap::new();
+        let mut pending = vec![id];
+        while let Some(parent_id) = pending.pop() {
+            if children.contains_key(&parent_id) {
+                continue; // Already looked up, through a loop in the hierarchy
+            }
+            let query = TodoQuery {
+                parent: Some(parent_id),
+                ..TodoQuery::default()
+            };
+            let mut subtasks = store.list(&query)?;
+            if with_trash {
+                subtasks.extend(store.list(&TodoQuery {
+                    in_trash: true,
+                    ..query
+                })?);
+            }
+            pending.extend(subtasks.iter().map(|todo| todo.id));
+            children.insert(parent_id, subtasks);
+        }
+        Ok(Children(children))
+    }
+
+    pub fn of(&self, id: Uuid) -> &[TodoItem] {
+        self.0.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    // Every item below `id` at any depth, parents before their own subtasks
+    pub fn descendants(&self, id: Uuid) -> Vec<TodoItem> {
+        let mut seen: HashSet<Uuid> = [id].iter().copied().collect();
+        let mut found: Vec<TodoItem> = Vec::new();
+        let mut next = 0;
+        let mut parent = Some(id);
+        while let Some(parent_id) = parent {
+            for child in self.of(parent_id) {
+                if seen.insert(child.id) {
+                    found.push(child.clone());
+                }
+            }
+            parent = found.get(next).map(|todo| todo.id);
+            next += 1;
+        }
+        found
+    }
+
+    // `root` with its subtasks nested below it and their completion rolled up
+    pub fn tree(&self, root: TodoItem) -> TodoTree {
+        let mut seen = HashSet::new();
+        self.subtree(root, &mut seen)
+    }
+
+    fn subtree(&self, todo: TodoItem, seen: &mut HashSet<Uuid>) -> TodoTree {
+        seen.insert(todo.id);
+        let mut children = Vec::new();
+        for child in self.of(todo.id) {
+            if !seen.contains(&child.id) {
+                children.push(self.subtree(child.clone(), seen));
+            }
+        }
+        let total = children
+            .iter()
+            .map(|child: &TodoTree| 1 + child.progress.total)
+            .sum();
+        let completed = children
+            .iter()
+            .map(|child| child.todo.completed as usize + child.progress.completed)
+            .sum();
+        let percent = match total {
+            0 if todo.completed => 100,
+            0 => 0,
+            _ => (completed * 100 / total) as u32,
+        };
+        TodoTree {
+            todo,
+            progress: Progress {
+                completed,
+                total,
+                percent,
+            },
+            children,
+        }
+    }
+}
+
+// Whether filing `id` under `parent_id` would make the item a subtask of itself
+pub fn creates_cycle(store: &dyn TodoStore, id: Uuid, parent_id: Uuid) -> StoreResult<bool> {
+    let mut seen = HashSet::new();
+    let mut ancestor = Some(parent_id);
+    while let Some(ancestor_id) = ancestor {
+        if ancestor_id == id {
+            return Ok(true);
+        }
+        if !seen.insert(ancestor_id) {
+            break; // An existing loop further up that does not involve `id`
+        }
+        ancestor = store.get(ancestor_id)?.and_then(|todo| todo.parent_id);
+    }
+    Ok(false)
+}